@@ -37,11 +37,10 @@ pub(crate) enum ConsensusAction {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum Action {
     /// Trigger a vote for an event so we can process the deferred action on consensus.
-    /// (Currently immediately.)
     ConsensusVote(ConsensusAction),
     /// Send a validated client request from client handlers to the appropriate destination.
     ForwardClientRequest(Rpc),
@@ -65,3 +64,111 @@ pub(crate) enum Action {
         rpc: Rpc,
     },
 }
+
+impl Action {
+    /// Returns the name of the variant, for logging a chain of actions without their payloads.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::ConsensusVote(_) => "ConsensusVote",
+            Action::ForwardClientRequest(_) => "ForwardClientRequest",
+            Action::ProxyClientRequest(_) => "ProxyClientRequest",
+            Action::RespondToOurDataHandlers { .. } => "RespondToOurDataHandlers",
+            Action::RespondToClientHandlers { .. } => "RespondToClientHandlers",
+            Action::SendToPeers { .. } => "SendToPeers",
+        }
+    }
+}
+
+/// Handles `maybe_action` and every follow-up action it produces, depth first, unless a chain of
+/// them grows longer than `max_depth`.  The depth is counted from `maybe_action`, however many
+/// follow-ups each action fans out into.  All remaining actions are then abandoned, dropping the
+/// next follow-up, and the names of the actions in its chain are returned, ending with the
+/// dropped one.
+pub(crate) fn drain<F>(
+    maybe_action: Option<Action>,
+    max_depth: usize,
+    mut handle: F,
+) -> Result<(), Vec<&'static str>>
+where
+    F: FnMut(Action) -> Vec<Action>,
+{
+    let mut pending: Vec<_> = maybe_action
+        .into_iter()
+        .map(|action| (action, Vec::new()))
+        .collect();
+    while let Some((action, mut chain)) = pending.pop() {
+        chain.push(action.name());
+        if chain.len() > max_depth {
+            return Err(chain);
+        }
+        // Pushed in reverse, so that follow-ups are handled in the order they were produced.
+        for follow_up in handle(action).into_iter().rev() {
+            pending.push((follow_up, chain.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use safe_nd::NodeFullId;
+
+    fn vote() -> Action {
+        Action::ConsensusVote(ConsensusAction::Forward {
+            request: Request::GetBalance,
+            client_public_id: PublicId::Node(
+                NodeFullId::new(&mut rand::thread_rng()).public_id().clone(),
+            ),
+            message_id: MessageId::new(),
+        })
+    }
+
+    #[test]
+    fn chain_feeding_back_into_itself_is_stopped() {
+        let mut handled = 0;
+        let result = drain(Some(vote()), 5, |action| {
+            handled += 1;
+            vec![action]
+        });
+        assert_eq!(handled, 5);
+        assert_eq!(result, Err(vec!["ConsensusVote"; 6]));
+
+        let mut remaining = 5;
+        let result = drain(Some(vote()), 5, |action| {
+            remaining -= 1;
+            if remaining > 0 {
+                vec![action]
+            } else {
+                Vec::new()
+            }
+        });
+        assert_eq!(remaining, 0);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn depth_is_counted_across_follow_ups() {
+        // Each action fans out into two, and the first chain to exceed the depth is stopped as soon
+        // as its fourth action is reached, without the others being handled.
+        let mut handled = 0;
+        let result = drain(Some(vote()), 3, |action| {
+            handled += 1;
+            vec![action.clone(), action]
+        });
+        assert_eq!(handled, 3);
+        assert_eq!(result, Err(vec!["ConsensusVote"; 4]));
+
+        let mut handled = 0;
+        let result = drain(Some(vote()), 3, |action| {
+            handled += 1;
+            if handled < 3 {
+                vec![action.clone(), action]
+            } else {
+                Vec::new()
+            }
+        });
+        assert_eq!(handled, 5);
+        assert_eq!(result, Ok(()));
+    }
+}
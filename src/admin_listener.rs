@@ -0,0 +1,197 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A listener accepting admin commands over a loopback TCP connection, so that a running vault can
+//! be operated from outside its process.
+//!
+//! Each line received is one request, and is answered with one line:
+//!
+//! * `token <token>` sets the operator token sent with subsequent privileged commands on this
+//!   connection.  It must be listed in `Config::operator_whitelist` for them to be accepted.
+//! * `status` runs a self-test and replies with its report.
+//! * `shutdown` shuts the vault down.  Privileged.
+//! * `loglevel <level>` sets the maximum log level, e.g. `loglevel debug`.  Privileged.
+//! * `maintenance on` and `maintenance off` enter and exit maintenance mode.  Privileged.
+//! * `compact` is accepted, but there's currently no storage compaction to run.
+
+use crate::{AdminCommand, Command, Error, OperatorCapability, Result};
+use crossbeam_channel::Sender;
+use log::{info, warn};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+// How long a connection may stay idle before it's closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+// How long to wait for the vault to answer a request before giving up on it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Starts listening for admin commands on `address`, forwarding them to the vault on `commands`.
+/// Each connection is served on its own thread, and closed once idle for five minutes.
+///
+/// Returns `Error::InvalidConfig` if `address` isn't a loopback address.
+pub fn start_admin_listener(address: SocketAddr, commands: Sender<Command>) -> Result<()> {
+    if !address.ip().is_loopback() {
+        return Err(Error::InvalidConfig(format!(
+            "admin address {} isn't a loopback address",
+            address
+        )));
+    }
+    let listener = TcpListener::bind(address)?;
+    info!("Listening for admin commands on {}", address);
+    let _ = thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!("Failed to accept admin connection: {}", error);
+                    continue;
+                }
+            };
+            let commands = commands.clone();
+            let _ = thread::spawn(move || {
+                if let Err(error) = serve(stream, &commands) {
+                    warn!("Admin connection failed: {}", error);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// A request parsed from a line received by the listener.
+#[derive(Debug, PartialEq)]
+enum Request {
+    Token(String),
+    Status,
+    Admin(AdminCommand),
+    Compact,
+}
+
+fn parse(line: &str) -> Result<Request, String> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["token", token] => Ok(Request::Token(token.to_string())),
+        ["status"] => Ok(Request::Status),
+        ["shutdown"] => Ok(Request::Admin(AdminCommand::Shutdown)),
+        ["loglevel", level] => level
+            .parse()
+            .map(|level| Request::Admin(AdminCommand::SetLogLevel(level)))
+            .map_err(|_| format!("unknown log level {:?}", level)),
+        ["maintenance", "on"] => Ok(Request::Admin(AdminCommand::EnterMaintenance)),
+        ["maintenance", "off"] => Ok(Request::Admin(AdminCommand::ExitMaintenance)),
+        ["compact"] => Ok(Request::Compact),
+        _ => Err(format!("unknown command {:?}", line.trim())),
+    }
+}
+
+fn serve(stream: TcpStream, commands: &Sender<Command>) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut token = None;
+    for line in BufReader::new(stream).lines() {
+        let reply = match parse(&line?) {
+            Ok(Request::Token(value)) => {
+                token = Some(value);
+                "ok".to_string()
+            }
+            Ok(Request::Status) => status(commands),
+            Ok(Request::Admin(command)) => match token {
+                Some(ref token) => admin(commands, command, token),
+                None => "error: send `token <token>` first".to_string(),
+            },
+            Ok(Request::Compact) => "ok: nothing to compact".to_string(),
+            Err(error) => format!("error: {}", error),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+// Only forwards `command` once the vault has confirmed that `token` is whitelisted, so that "ok"
+// is never the reply to a command the vault will reject.
+fn admin(commands: &Sender<Command>, command: AdminCommand, token: &str) -> String {
+    let capability = OperatorCapability::Token(token.to_string());
+    let (reply, is_operator) = crossbeam_channel::bounded(1);
+    send(
+        commands,
+        Command::IsOperator {
+            capability: capability.clone(),
+            reply,
+        },
+    );
+    match is_operator.recv_timeout(REPLY_TIMEOUT) {
+        Ok(true) => {
+            send(
+                commands,
+                Command::Admin {
+                    command,
+                    capability,
+                },
+            );
+            "ok".to_string()
+        }
+        Ok(false) => "error: token isn't in the operator whitelist".to_string(),
+        Err(_) => "error: vault didn't respond".to_string(),
+    }
+}
+
+fn status(commands: &Sender<Command>) -> String {
+    let (reply, report) = crossbeam_channel::bounded(1);
+    send(commands, Command::SelfTest { reply });
+    match report.recv_timeout(REPLY_TIMEOUT) {
+        Ok(report) => format!("self-test {}", report),
+        Err(_) => "error: vault didn't respond".to_string(),
+    }
+}
+
+fn send(commands: &Sender<Command>, command: Command) {
+    if commands.send(command).is_err() {
+        warn!("Vault has stopped; admin command dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::LevelFilter;
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(parse("status"), Ok(Request::Status));
+        assert_eq!(
+            parse(" token abc \r"),
+            Ok(Request::Token("abc".to_string()))
+        );
+        assert_eq!(
+            parse("shutdown"),
+            Ok(Request::Admin(AdminCommand::Shutdown))
+        );
+        assert_eq!(
+            parse("loglevel debug"),
+            Ok(Request::Admin(AdminCommand::SetLogLevel(
+                LevelFilter::Debug
+            )))
+        );
+        assert_eq!(
+            parse("maintenance on"),
+            Ok(Request::Admin(AdminCommand::EnterMaintenance))
+        );
+        assert_eq!(
+            parse("maintenance off"),
+            Ok(Request::Admin(AdminCommand::ExitMaintenance))
+        );
+        assert_eq!(parse("compact"), Ok(Request::Compact));
+        assert!(parse("loglevel loud").is_err());
+        assert!(parse("shutdown now").is_err());
+        assert!(parse("").is_err());
+    }
+}
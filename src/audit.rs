@@ -0,0 +1,27 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::data_handler::OpType;
+use safe_nd::{IDataAddress, Result as NdResult, XorName};
+use std::time::SystemTime;
+
+/// A client's ImmutableData op which has concluded, as reported to the sink set by
+/// `Vault::set_audit_sink`.  This never includes the chunk's contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// Name of the client which made the request.
+    pub requester: XorName,
+    /// The type of op.
+    pub op: OpType,
+    /// Address of the chunk.
+    pub address: IDataAddress,
+    /// The outcome reported to the client.
+    pub result: NdResult<()>,
+    /// When the op concluded.
+    pub timestamp: SystemTime,
+}
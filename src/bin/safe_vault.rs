@@ -44,7 +44,13 @@ mod detail {
 
     /// Runs a SAFE Network vault.
     pub fn main() {
-        let mut config = Config::new();
+        let mut config = match Config::new() {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Cannot start vault due to error: {}", e);
+                process::exit(1);
+            }
+        };
 
         if let Some(c) = &config.completions() {
             match c.parse::<clap::Shell>() {
@@ -117,6 +123,15 @@ mod detail {
 
         let (command_tx, command_rx) = crossbeam_channel::bounded(1);
 
+        #[cfg(feature = "admin_listener")]
+        {
+            if let Some(address) = config.admin_address() {
+                if let Err(error) = safe_vault::start_admin_listener(address, command_tx.clone()) {
+                    log::error!("Failed to start admin listener: {:?}", error)
+                }
+            }
+        }
+
         // Shutdown the vault gracefully on SIGINT (Ctrl+C).
         let result = ctrlc::set_handler(move || {
             let _ = command_tx.send(Command::Shutdown);
@@ -50,6 +50,8 @@ pub(crate) struct ChunkStore<T: Chunk> {
     dir: PathBuf,
     // Maximum space allowed for all `ChunkStore`s to consume.
     max_capacity: u64,
+    // Free space to leave on the filesystem holding `dir`, whatever `max_capacity` allows.
+    min_free_space: u64,
     used_space: UsedSpace,
     _phantom: PhantomData<T>,
 }
@@ -83,6 +85,7 @@ where
         Ok(ChunkStore {
             dir,
             max_capacity,
+            min_free_space: 0,
             used_space,
             _phantom: PhantomData,
         })
@@ -104,8 +107,9 @@ impl<T: Chunk> ChunkStore<T> {
 
     /// Stores a new data chunk.
     ///
-    /// If there is not enough storage space available, returns `Error::NotEnoughSpace`.  In case of
-    /// an IO error, it returns `Error::Io`.
+    /// If there is not enough storage space available, or storing the chunk would leave less than
+    /// the minimum free space on the filesystem, returns `Error::NotEnoughSpace`.  In case of an IO
+    /// error, it returns `Error::Io`.
     ///
     /// If a chunk with the same id already exists, it will be overwritten.
     pub fn put(&mut self, chunk: &T) -> Result<()> {
@@ -114,6 +118,11 @@ impl<T: Chunk> ChunkStore<T> {
         if self.used_space.total().saturating_add(consumed_space) > self.max_capacity {
             return Err(Error::NotEnoughSpace);
         }
+        if self.min_free_space > 0
+            && fs2::available_space(&self.dir)?.saturating_sub(consumed_space) < self.min_free_space
+        {
+            return Err(Error::NotEnoughSpace);
+        }
 
         let file_path = self.file_path(chunk.id())?;
         let _ = self.do_delete(&file_path);
@@ -149,6 +158,27 @@ impl<T: Chunk> ChunkStore<T> {
         }
     }
 
+    /// Returns the space consumed by all `ChunkStore`s, including this one.
+    pub fn used_space(&self) -> u64 {
+        self.used_space.total()
+    }
+
+    /// Sets the free space to leave on the filesystem holding the store.  Zero disables the check.
+    pub fn set_min_free_space(&mut self, min_free_space: u64) {
+        self.min_free_space = min_free_space;
+    }
+
+    /// Returns the maximum space allowed for all `ChunkStore`s to consume.
+    pub fn max_capacity(&self) -> u64 {
+        self.max_capacity
+    }
+
+    /// Sets the maximum space allowed for all `ChunkStore`s to consume.  Chunks already stored
+    /// beyond it are kept, but no more can be put until enough are deleted.
+    pub fn set_max_capacity(&mut self, max_capacity: u64) {
+        self.max_capacity = max_capacity;
+    }
+
     /// Tests if a data chunk has been previously stored under `id`.
     pub fn has(&self, id: &T::Id) -> bool {
         if let Ok(path) = self.file_path(id) {
@@ -162,7 +192,6 @@ impl<T: Chunk> ChunkStore<T> {
     }
 
     /// Lists all keys of currently stored data.
-    #[cfg_attr(not(test), allow(unused))]
     pub fn keys(&self) -> Vec<T::Id> {
         fs::read_dir(&self.dir)
             .map(|entries| {
@@ -154,6 +154,27 @@ fn failed_put_when_not_enough_space() {
     }
 }
 
+#[test]
+fn failed_put_when_below_min_free_space() {
+    let mut rng = new_rng();
+    let root = temp_dir();
+    let used_space = Rc::new(Cell::new(0));
+    let mut chunk_store = unwrap!(ChunkStore::new(root.path(), 1024, used_space, Init::New));
+    // No filesystem has this much free space.
+    chunk_store.set_min_free_space(u64::max_value());
+
+    let data = Data {
+        id: Id(rng.gen()),
+        value: vec![0; 8],
+    };
+
+    match chunk_store.put(&data) {
+        Err(Error::NotEnoughSpace) => (),
+        x => panic!("Unexpected: {:?}", x),
+    }
+    assert!(!chunk_store.has(&data.id));
+}
+
 #[test]
 fn delete() {
     let mut rng = new_rng();
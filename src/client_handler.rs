@@ -8,7 +8,9 @@
 
 mod auth_keys;
 mod balance;
+mod refund;
 
+pub use self::refund::RefundReason;
 use self::{
     auth_keys::AuthKeysDb,
     balance::{Balance, BalancesDb},
@@ -16,9 +18,9 @@ use self::{
 use crate::{
     action::{Action, ConsensusAction},
     chunk_store::{error::Error as ChunkStoreError, LoginPacketChunkStore},
-    routing::Node,
+    routing_api::RoutingApi,
     rpc::Rpc,
-    utils::{self, AuthorisationKind},
+    utils::{self, AuthorisationKind, Instant},
     vault::Init,
     Config, Result,
 };
@@ -28,37 +30,63 @@ use rand::{CryptoRng, Rng};
 use safe_nd::{
     AData, ADataAddress, AppPermissions, AppPublicId, Coins, Error as NdError, HandshakeRequest,
     HandshakeResponse, IData, IDataAddress, IDataKind, LoginPacket, MData, Message, MessageId,
-    NodePublicId, Notification, PublicId, PublicKey, Request, Response, Result as NdResult,
-    Signature, Transaction, TransactionId, XorName,
+    NodePublicId, Notification, PublicId, PublicKey, Request, RequestType, Response,
+    Result as NdResult, Signature, Transaction, TransactionId, XorName,
 };
 use serde::Serialize;
 use std::{
     cell::{Cell, RefCell},
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
     fmt::{self, Display, Formatter},
     net::SocketAddr,
     rc::Rc,
+    time::Duration,
 };
 
 /// The cost to Put a chunk to the network.
 pub const COST_OF_PUT: Coins = Coins::from_nano(1);
+// How long the responses to a disconnected client's requests are held in case it reconnects.
+const RECONNECT_WINDOW: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Debug)]
 struct ClientInfo {
     public_id: PublicId,
 }
 
+// A request whose client disconnected before it was answered.
+struct AwaitingReconnect {
+    client: PublicId,
+    disconnected: Instant,
+    // The response, once it's arrived.
+    response: Option<Response>,
+}
+
 pub(crate) struct ClientHandler {
     id: NodePublicId,
     auth_keys: AuthKeysDb,
     balances: BalancesDb,
     clients: HashMap<SocketAddr, ClientInfo>,
+    // All open client connections, whether or not the client has identified itself yet.
+    connections: BTreeSet<SocketAddr>,
     pending_msg_ids: HashMap<MessageId, SocketAddr>,
     pending_actions: HashMap<MessageId, Response>,
+    // Responses sent to clients which haven't yet been confirmed as sent, keyed by send token.
+    pending_client_sends: HashMap<u64, (SocketAddr, MessageId)>,
+    next_send_token: u64,
+    // Number of put refunds made for each reason.
+    refunds: BTreeMap<RefundReason, u64>,
     // Map of new client connections to the challenge value we sent them.
     client_candidates: HashMap<SocketAddr, (Vec<u8>, PublicId)>,
     login_packets: LoginPacketChunkStore,
-    routing_node: Rc<RefCell<Node>>,
+    routing_node: Rc<RefCell<dyn RoutingApi>>,
+    // Whether new data requests are refused while the vault is maintained.
+    maintenance: bool,
+    // Whether mutations and coin transactions are refused, only gets being served.
+    read_only: bool,
+    // Requests of clients which have disconnected, answered if the client reconnects in time.
+    awaiting_reconnect: HashMap<MessageId, AwaitingReconnect>,
+    // Number of responses dropped because their client didn't reconnect in time.
+    expired_responses: u64,
 }
 
 impl ClientHandler {
@@ -67,32 +95,55 @@ impl ClientHandler {
         config: &Config,
         total_used_space: &Rc<Cell<u64>>,
         init_mode: Init,
-        routing_node: Rc<RefCell<Node>>,
+        routing_node: Rc<RefCell<dyn RoutingApi>>,
     ) -> Result<Self> {
         let root_dir = config.root_dir()?;
         let root_dir = root_dir.as_path();
-        let auth_keys = AuthKeysDb::new(root_dir, init_mode)?;
-        let balances = BalancesDb::new(root_dir, init_mode)?;
-        let login_packets = LoginPacketChunkStore::new(
+        let auth_keys = AuthKeysDb::new(root_dir, init_mode, config.load_retries())?;
+        let balances = BalancesDb::new(root_dir, init_mode, config.load_retries())?;
+        let mut login_packets = LoginPacketChunkStore::new(
             root_dir,
             config.max_capacity(),
             Rc::clone(&total_used_space),
             init_mode,
         )?;
+        login_packets.set_min_free_space(config.min_free_space());
         let client_handler = Self {
             id,
             auth_keys,
             balances,
             clients: Default::default(),
+            connections: Default::default(),
             pending_msg_ids: Default::default(),
             pending_actions: Default::default(),
+            pending_client_sends: Default::default(),
+            next_send_token: 1,
+            refunds: Default::default(),
             client_candidates: Default::default(),
             login_packets,
             routing_node,
+            maintenance: false,
+            read_only: config.read_only(),
+            awaiting_reconnect: Default::default(),
+            expired_responses: 0,
         };
         Ok(client_handler)
     }
 
+    /// Applies the storage limits and read-only mode from a reloaded `config`.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.login_packets.set_max_capacity(config.max_capacity());
+        self.login_packets
+            .set_min_free_space(config.min_free_space());
+        self.read_only = config.read_only();
+    }
+
+    /// Sets whether new gets and mutations from clients are refused with the `MAINTENANCE` error.
+    /// Coin transactions are still handled.
+    pub fn set_maintenance(&mut self, maintenance: bool) {
+        self.maintenance = maintenance;
+    }
+
     pub fn handle_new_connection(&mut self, peer_addr: SocketAddr) {
         // If we already know the peer, drop the connection attempt.
         if self.clients.contains_key(&peer_addr) || self.client_candidates.contains_key(&peer_addr)
@@ -101,23 +152,122 @@ impl ClientHandler {
         }
 
         info!("{}: Connected to new client on {}", self, peer_addr);
+        let _ = self.connections.insert(peer_addr);
     }
 
-    pub fn handle_connection_failure(&mut self, peer_addr: SocketAddr) {
+    /// Handles the loss of the connection to `peer_addr`.  Returns the client's ID if this was its
+    /// last connection to us and it has no outstanding requests, so that work done solely on its
+    /// behalf can be cancelled.  Otherwise responses to its outstanding requests are held for
+    /// `RECONNECT_WINDOW` in case it reconnects, and its ID is instead returned by
+    /// `expire_awaiting_reconnect` if it fails to do so.
+    pub fn handle_connection_failure(&mut self, peer_addr: SocketAddr) -> Option<PublicId> {
+        self.pending_client_sends
+            .retain(|_, (recipient, _)| *recipient != peer_addr);
+        let _ = self.connections.remove(&peer_addr);
         if let Some(client) = self.clients.remove(&peer_addr) {
             info!(
                 "{}: Disconnected from {:?} on {}",
                 self, client.public_id, peer_addr
             );
+            let other_connection = self
+                .lookup_client_peer_addrs(&client.public_id)
+                .into_iter()
+                .next();
+            let orphaned: Vec<_> = self
+                .pending_msg_ids
+                .iter()
+                .filter(|(_, recipient)| **recipient == peer_addr)
+                .map(|(message_id, _)| *message_id)
+                .collect();
+            for message_id in orphaned {
+                if let Some(other_connection) = other_connection {
+                    let _ = self.pending_msg_ids.insert(message_id, other_connection);
+                } else {
+                    let _ = self.pending_msg_ids.remove(&message_id);
+                    let _ = self.awaiting_reconnect.insert(
+                        message_id,
+                        AwaitingReconnect {
+                            client: client.public_id.clone(),
+                            disconnected: Instant::now(),
+                            response: None,
+                        },
+                    );
+                }
+            }
+            let parked = other_connection.is_none()
+                && self
+                    .awaiting_reconnect
+                    .values()
+                    .any(|awaiting| awaiting.client == client.public_id);
+            if other_connection.is_some() || parked {
+                None
+            } else {
+                Some(client.public_id)
+            }
         } else {
             let _ = self.client_candidates.remove(&peer_addr);
             info!(
                 "{}: Disconnected from client candidate on {}",
                 self, peer_addr
             );
+            None
         }
     }
 
+    /// Handles the client transport shutting down.  No client can reconnect over it, so all are
+    /// treated as gone for good: the responses still owed to them, and any unclaimed ones, are
+    /// dropped rather than held for a reconnection.  Returns the clients which were connected, so
+    /// that work done on their behalf can be cancelled.
+    pub fn handle_transport_finished(&mut self) -> Vec<PublicId> {
+        let mut clients = Vec::new();
+        for (_, client) in self.clients.drain() {
+            if !clients.contains(&client.public_id) {
+                clients.push(client.public_id);
+            }
+        }
+        let dropped =
+            self.pending_msg_ids.len() + self.awaiting_reconnect.len() + self.pending_actions.len();
+        self.connections.clear();
+        self.client_candidates.clear();
+        self.pending_client_sends.clear();
+        self.pending_msg_ids.clear();
+        self.pending_actions.clear();
+        self.awaiting_reconnect.clear();
+        info!(
+            "{}: Client transport finished. Disconnected {} clients, dropping {} responses.",
+            self,
+            clients.len(),
+            dropped
+        );
+        clients
+    }
+
+    /// Handles confirmation that the message sent with `token` was delivered to `peer_addr`.
+    pub fn handle_sent_user_message(&mut self, peer_addr: SocketAddr, token: u64) {
+        trace!("{}: Succesfully sent message to: {}", self, peer_addr);
+        let _ = self.pending_client_sends.remove(&token);
+    }
+
+    /// Handles failure to deliver the message sent with `token` to `peer_addr`.  If it was a
+    /// response, the client is treated as unreachable and must reconnect.  Returns the client if
+    /// this was its last connection, as `handle_connection_failure` does.
+    pub fn handle_unsent_user_message(
+        &mut self,
+        peer_addr: SocketAddr,
+        token: u64,
+    ) -> Option<PublicId> {
+        info!("{}: Not sent message to: {}", self, peer_addr);
+        let (_, message_id) = self.pending_client_sends.remove(&token)?;
+        op_log!(
+            warn,
+            self,
+            message_id,
+            "Failed to send response to client on {}; marking it unreachable",
+            peer_addr
+        );
+        self.handle_connection_failure(peer_addr)
+    }
+
     pub fn handle_consensused_action(&mut self, action: ConsensusAction) -> Option<Action> {
         use ConsensusAction::*;
         trace!("{}: Consensused {:?}", self, action,);
@@ -273,6 +423,8 @@ impl ClientHandler {
         self.verify_signature(&client.public_id, &request, message_id, signature)?;
         self.authorise_app(&client.public_id, &request, message_id)?;
         self.verify_consistent_address(&request, message_id)?;
+        self.verify_not_in_maintenance(&request, message_id)?;
+        self.verify_not_read_only(&request, message_id)?;
 
         match request {
             //
@@ -425,9 +577,14 @@ impl ClientHandler {
         match pub_key.verify(signature, utils::serialise(&(request, message_id))) {
             Ok(_) => true,
             Err(error) => {
-                warn!(
-                    "{}: ({:?}/{:?}) from {} is invalid: {}",
-                    self, request, message_id, client_id, error
+                op_log!(
+                    warn,
+                    self,
+                    message_id,
+                    "{:?} from {} is invalid: {}",
+                    request,
+                    client_id,
+                    error
                 );
                 false
             }
@@ -664,8 +821,7 @@ impl ClientHandler {
         if !self
             .routing_node
             .borrow()
-            .matches_our_prefix(&routing::XorName(client_id.name().0))
-            .unwrap_or(false)
+            .matches_our_prefix(client_id.name())
         {
             debug!(
                 "Client {} ({}) wants to join us but we are not its client handler",
@@ -712,7 +868,7 @@ impl ClientHandler {
             match public_key.verify(&signature, challenge) {
                 Ok(()) => {
                     info!("{}: Accepted {} on {}.", self, public_id, peer_addr,);
-                    let _ = self.clients.insert(peer_addr, ClientInfo { public_id });
+                    self.accept_client(peer_addr, public_id);
                 }
                 Err(err) => {
                     info!(
@@ -743,6 +899,28 @@ impl ClientHandler {
         }
     }
 
+    // Registers `public_id` as connected on `peer_addr`, delivering any responses held since it
+    // last disconnected.
+    fn accept_client(&mut self, peer_addr: SocketAddr, public_id: PublicId) {
+        let reclaimed: Vec<_> = self
+            .awaiting_reconnect
+            .iter()
+            .filter(|(_, awaiting)| awaiting.client == public_id)
+            .map(|(message_id, _)| *message_id)
+            .collect();
+        let _ = self.clients.insert(peer_addr, ClientInfo { public_id });
+        for message_id in reclaimed {
+            let awaiting = match self.awaiting_reconnect.remove(&message_id) {
+                Some(awaiting) => awaiting,
+                None => continue,
+            };
+            let _ = self.pending_msg_ids.insert(message_id, peer_addr);
+            if let Some(response) = awaiting.response {
+                self.send_response_to_client(message_id, response);
+            }
+        }
+    }
+
     pub fn handle_vault_rpc(&mut self, src: XorName, rpc: Rpc) -> Option<Action> {
         match rpc {
             Rpc::Request {
@@ -756,6 +934,27 @@ impl ClientHandler {
                 message_id,
                 refund,
             } => self.handle_response(src, &requester, response, message_id, refund),
+            Rpc::PutIDataResponse { message_id, .. } => {
+                error!(
+                    "{}: Should not receive a holder's response ({:?}) as a client handler.",
+                    self, message_id
+                );
+                None
+            }
+            Rpc::PutIDataSegment { message_id, .. } => {
+                error!(
+                    "{}: Should not receive an ImmutableData segment ({:?}) as a client handler.",
+                    self, message_id
+                );
+                None
+            }
+            Rpc::DiscardIData { message_id, .. } | Rpc::DiscardIDataResponse { message_id, .. } => {
+                error!(
+                    "{}: Should not receive a put rollback ({:?}) as a client handler.",
+                    self, message_id
+                );
+                None
+            }
         }
     }
 
@@ -894,6 +1093,17 @@ impl ClientHandler {
         );
 
         if let Some(refund_amount) = refund {
+            let reason = RefundReason::of(&response);
+            op_log!(
+                info,
+                self,
+                message_id,
+                "Refunding {} coins to {:?}: {:?}",
+                refund_amount,
+                requester,
+                reason
+            );
+            *self.refunds.entry(reason).or_insert(0) += 1;
             if let Err(error) = self.deposit(requester.name(), refund_amount) {
                 error!(
                     "{}: Failed to refund {} coins for {:?}: {:?}",
@@ -1082,7 +1292,7 @@ impl ClientHandler {
                 }
                 self.login_packets
                     .put(&updated_login_packet)
-                    .map_err(|err| err.to_string().into())
+                    .map_err(utils::chunk_store_put_error)
             });
         Some(Action::RespondToClientHandlers {
             sender: *self.id.name(),
@@ -1126,39 +1336,53 @@ impl ClientHandler {
     }
 
     fn send<T: Serialize>(&mut self, recipient: SocketAddr, msg: &T) {
+        let _ = self.send_with_token(recipient, msg, 0);
+    }
+
+    /// Sends `msg` tagged with `token` (zero for untracked sends), returning whether it was handed
+    /// to the transport.
+    fn send_with_token<T: Serialize>(
+        &mut self,
+        recipient: SocketAddr,
+        msg: &T,
+        token: u64,
+    ) -> bool {
         let msg = utils::serialise(msg);
         let msg = Bytes::from(msg);
 
         if let Err(e) = self
             .routing_node
             .borrow_mut()
-            .send_message_to_client(recipient, msg, 0)
+            .send_message_to_client(recipient, msg, token)
         {
             warn!(
                 "{}: Could not send message to client {}: {:?}",
                 self, recipient, e
             );
+            return false;
         }
+        true
+    }
+
+    fn new_send_token(&mut self) -> u64 {
+        let token = self.next_send_token;
+        // Zero is reserved for untracked sends.
+        self.next_send_token = self.next_send_token.checked_add(1).unwrap_or(1);
+        token
     }
 
     fn handle_bootstrap_request(&mut self, peer_addr: SocketAddr, client_id: &PublicId) {
         if !self
             .routing_node
             .borrow()
-            .matches_our_prefix(&routing::XorName(client_id.name().0))
-            .unwrap_or(false)
+            .matches_our_prefix(client_id.name())
         {
             let closest_known_elders = match self
                 .routing_node
                 .borrow()
-                .closest_known_elders_to(&routing::XorName(client_id.name().0))
+                .closest_known_elders_to(client_id.name())
             {
-                Ok(elders_iter) => elders_iter
-                    .map(|p2p_node| {
-                        let peer_addr = *p2p_node.peer_addr();
-                        (XorName(p2p_node.name().0), peer_addr)
-                    })
-                    .collect::<Vec<_>>(),
+                Ok(elders) => elders,
                 Err(e) => {
                     info!("Could not handle bootstrap request: {:?}", e);
                     return;
@@ -1174,18 +1398,7 @@ impl ClientHandler {
                 self.send(peer_addr, &HandshakeResponse::Join(closest_known_elders));
             }
         } else {
-            let elders = self
-                .routing_node
-                .borrow_mut()
-                .our_elders_info()
-                .map(|iter| {
-                    iter.map(|p2p_node| {
-                        let peer_addr = *p2p_node.peer_addr();
-                        (XorName(p2p_node.name().0), peer_addr)
-                    })
-                    .collect::<Vec<_>>()
-                });
-
+            let elders = self.routing_node.borrow().our_elders();
             if let Some(elders) = elders {
                 self.send(peer_addr, &HandshakeResponse::Join(elders));
             } else {
@@ -1215,26 +1428,114 @@ impl ClientHandler {
         }
     }
 
+    /// Returns the number of put refunds made for each reason.
+    pub fn refunds(&self) -> BTreeMap<RefundReason, u64> {
+        self.refunds.clone()
+    }
+
+    /// Returns the number of responses held for a client request we haven't received.  A response
+    /// is delivered if its request arrives later, otherwise it's never sent.
+    pub fn unclaimed_responses(&self) -> usize {
+        self.pending_actions.len()
+    }
+
+    /// Returns the number of responses dropped because their client disconnected and didn't
+    /// reconnect within `RECONNECT_WINDOW`.
+    pub fn expired_responses(&self) -> u64 {
+        self.expired_responses
+    }
+
+    /// Stops waiting for disconnected clients which haven't reconnected within
+    /// `RECONNECT_WINDOW`, dropping any responses held for them.  Returns the clients given up on,
+    /// so that work done on their behalf can be cancelled.
+    pub fn expire_awaiting_reconnect(&mut self) -> Vec<PublicId> {
+        let expired: Vec<_> = self
+            .awaiting_reconnect
+            .iter()
+            .filter(|(_, awaiting)| awaiting.disconnected.elapsed() > RECONNECT_WINDOW)
+            .map(|(message_id, _)| *message_id)
+            .collect();
+        let mut clients = Vec::new();
+        for message_id in expired {
+            let awaiting = match self.awaiting_reconnect.remove(&message_id) {
+                Some(awaiting) => awaiting,
+                None => continue,
+            };
+            if awaiting.response.is_some() {
+                self.expired_responses += 1;
+                op_log!(
+                    info,
+                    self,
+                    message_id,
+                    "Dropping response, as {} didn't reconnect in time",
+                    awaiting.client
+                );
+            }
+            if !clients.contains(&awaiting.client) {
+                clients.push(awaiting.client);
+            }
+        }
+        clients.retain(|client| {
+            !self
+                .awaiting_reconnect
+                .values()
+                .any(|awaiting| awaiting.client == *client)
+        });
+        clients
+    }
+
+    /// Returns each open client connection, along with the client's ID once it has completed the
+    /// handshake.
+    pub fn connected_clients(&self) -> Vec<(SocketAddr, Option<PublicId>)> {
+        self.connections
+            .iter()
+            .map(|peer_addr| {
+                let public_id = self
+                    .clients
+                    .get(peer_addr)
+                    .map(|client| client.public_id.clone());
+                (*peer_addr, public_id)
+            })
+            .collect()
+    }
+
     fn send_response_to_client(&mut self, message_id: MessageId, response: Response) {
         let peer_addr = match self.pending_msg_ids.remove(&message_id) {
             Some(peer_addr) => peer_addr,
             None => {
-                info!(
-                    "{} for message-id {:?}, Unable to find the client to respond to.",
-                    self, message_id
-                );
+                if let Some(awaiting) = self.awaiting_reconnect.get_mut(&message_id) {
+                    awaiting.response = Some(response);
+                    op_log!(
+                        info,
+                        self,
+                        message_id,
+                        "Holding response until the client reconnects"
+                    );
+                    return;
+                }
                 let _ = self.pending_actions.insert(message_id, response);
+                op_log!(
+                    info,
+                    self,
+                    message_id,
+                    "Unable to find the client to respond to. Holding {} unclaimed responses.",
+                    self.pending_actions.len()
+                );
                 return;
             }
         };
 
-        self.send(
-            peer_addr,
-            &Message::Response {
-                response,
-                message_id,
-            },
-        )
+        let token = self.new_send_token();
+        let _ = self
+            .pending_client_sends
+            .insert(token, (peer_addr, message_id));
+        let msg = Message::Response {
+            response,
+            message_id,
+        };
+        if !self.send_with_token(peer_addr, &msg, token) {
+            let _ = self.pending_client_sends.remove(&token);
+        }
     }
 
     fn lookup_client_peer_addrs(&self, id: &PublicId) -> Vec<SocketAddr> {
@@ -1367,7 +1668,7 @@ impl ClientHandler {
         } else {
             self.login_packets
                 .put(login_packet)
-                .map_err(|error| error.to_string().into())
+                .map_err(utils::chunk_store_put_error)
         };
         let refund = utils::get_refund_for_put(&result);
         Some(Action::RespondToClientHandlers {
@@ -1721,6 +2022,46 @@ impl ClientHandler {
         }
     }
 
+    fn verify_not_in_maintenance(
+        &mut self,
+        request: &Request,
+        message_id: MessageId,
+    ) -> Option<()> {
+        let refused = self.maintenance
+            && match request.get_type() {
+                RequestType::PublicGet | RequestType::PrivateGet | RequestType::Mutation => true,
+                RequestType::Transaction => false,
+            };
+        if refused {
+            trace!("{}: Refusing {:?} during maintenance", self, request);
+            self.send_response_to_client(
+                message_id,
+                request.error_response(utils::maintenance_error()),
+            );
+            None
+        } else {
+            Some(())
+        }
+    }
+
+    fn verify_not_read_only(&mut self, request: &Request, message_id: MessageId) -> Option<()> {
+        let refused = self.read_only
+            && match request.get_type() {
+                RequestType::PublicGet | RequestType::PrivateGet => false,
+                RequestType::Mutation | RequestType::Transaction => true,
+            };
+        if refused {
+            trace!("{}: Refusing {:?} as read-only", self, request);
+            self.send_response_to_client(
+                message_id,
+                request.error_response(utils::read_only_error()),
+            );
+            None
+        } else {
+            Some(())
+        }
+    }
+
     fn verify_consistent_address(
         &mut self,
         request: &Request,
@@ -1750,3 +2091,170 @@ impl Display for ClientHandler {
         write!(formatter, "{}", self.id.name())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing_api::MockRoutingApi;
+    use safe_nd::{ClientFullId, NodeFullId};
+    use tempdir::TempDir;
+    use unwrap::unwrap;
+
+    fn new_client_handler(root: &TempDir, routing: &Rc<RefCell<MockRoutingApi>>) -> ClientHandler {
+        let mut config = Config::default();
+        config.set_root_dir(root.path());
+        let id = NodeFullId::new(&mut rand::thread_rng()).public_id().clone();
+        unwrap!(ClientHandler::new(
+            id,
+            &config,
+            &Rc::new(Cell::new(0)),
+            Init::New,
+            routing.clone(),
+        ))
+    }
+
+    fn new_client_id() -> PublicId {
+        PublicId::Client(
+            ClientFullId::new_ed25519(&mut rand::thread_rng())
+                .public_id()
+                .clone(),
+        )
+    }
+
+    #[test]
+    fn bootstrap_outside_our_prefix_is_redirected_to_closest_elders() {
+        let root = unwrap!(TempDir::new("test"));
+        let elders = vec![
+            (XorName([1; 32]), unwrap!("10.0.0.1:5000".parse())),
+            (XorName([2; 32]), unwrap!("10.0.0.2:5000".parse())),
+        ];
+        let routing = Rc::new(RefCell::new(MockRoutingApi {
+            matches_our_prefix: false,
+            closest_known_elders: elders.clone(),
+            ..Default::default()
+        }));
+        let mut client_handler = new_client_handler(&root, &routing);
+
+        let peer_addr = unwrap!("192.168.0.1:5000".parse());
+        client_handler.handle_bootstrap_request(peer_addr, &new_client_id());
+
+        let routing = routing.borrow();
+        assert_eq!(routing.sent.len(), 1);
+        let (recipient, msg) = &routing.sent[0];
+        assert_eq!(*recipient, peer_addr);
+        match unwrap!(bincode::deserialize(msg)) {
+            HandshakeResponse::Join(redirected) => assert_eq!(redirected, elders),
+            response => panic!("Unexpected {:?}", response),
+        }
+    }
+
+    #[test]
+    fn response_is_delivered_to_client_reconnecting_in_time() {
+        let root = unwrap!(TempDir::new("test"));
+        let routing = Rc::new(RefCell::new(MockRoutingApi::default()));
+        let mut client_handler = new_client_handler(&root, &routing);
+        let client_id = new_client_id();
+        let old_addr = unwrap!("192.168.0.1:5000".parse());
+        let new_addr = unwrap!("192.168.0.1:5001".parse());
+
+        client_handler.accept_client(old_addr, client_id.clone());
+        let message_id = MessageId::new();
+        let _ = client_handler.pending_msg_ids.insert(message_id, old_addr);
+        // Its request is still outstanding, so nothing should be cancelled yet.
+        assert_eq!(client_handler.handle_connection_failure(old_addr), None);
+
+        // The response arrives while the client is disconnected, so is held.
+        let response = Response::Mutation(Ok(()));
+        client_handler.send_response_to_client(message_id, response.clone());
+        assert!(routing.borrow().sent.is_empty());
+
+        client_handler.accept_client(new_addr, client_id);
+        let routing = routing.borrow();
+        assert_eq!(routing.sent.len(), 1);
+        let (recipient, msg) = &routing.sent[0];
+        assert_eq!(*recipient, new_addr);
+        match unwrap!(bincode::deserialize(msg)) {
+            Message::Response {
+                response: sent,
+                message_id: sent_id,
+            } => {
+                assert_eq!(sent, response);
+                assert_eq!(sent_id, message_id);
+            }
+            msg => panic!("Unexpected {:?}", msg),
+        }
+        assert!(client_handler.awaiting_reconnect.is_empty());
+    }
+
+    #[cfg(feature = "mock_base")]
+    #[test]
+    fn response_is_dropped_once_client_fails_to_reconnect() {
+        use fake_clock::FakeClock;
+
+        let root = unwrap!(TempDir::new("test"));
+        let routing = Rc::new(RefCell::new(MockRoutingApi::default()));
+        let mut client_handler = new_client_handler(&root, &routing);
+        let client_id = new_client_id();
+        let peer_addr = unwrap!("192.168.0.1:5000".parse());
+
+        client_handler.accept_client(peer_addr, client_id.clone());
+        let message_id = MessageId::new();
+        let _ = client_handler.pending_msg_ids.insert(message_id, peer_addr);
+        let _ = client_handler.handle_connection_failure(peer_addr);
+        client_handler.send_response_to_client(message_id, Response::Mutation(Ok(())));
+
+        FakeClock::advance_time(RECONNECT_WINDOW.as_millis() as u64 + 1);
+        assert_eq!(
+            client_handler.expire_awaiting_reconnect(),
+            vec![client_id.clone()]
+        );
+        assert_eq!(client_handler.expired_responses(), 1);
+
+        client_handler.accept_client(peer_addr, client_id);
+        assert!(routing.borrow().sent.is_empty());
+    }
+
+    #[test]
+    fn transport_finishing_clears_client_state() {
+        let root = unwrap!(TempDir::new("test"));
+        let routing = Rc::new(RefCell::new(MockRoutingApi::default()));
+        let mut client_handler = new_client_handler(&root, &routing);
+        let connected_id = new_client_id();
+        let disconnected_id = new_client_id();
+        let connected_addr = unwrap!("192.168.0.1:5000".parse());
+        let disconnected_addr = unwrap!("192.168.0.2:5000".parse());
+        let unidentified_addr = unwrap!("192.168.0.3:5000".parse());
+
+        client_handler.handle_new_connection(connected_addr);
+        client_handler.accept_client(connected_addr, connected_id.clone());
+        let _ = client_handler
+            .pending_msg_ids
+            .insert(MessageId::new(), connected_addr);
+
+        client_handler.handle_new_connection(disconnected_addr);
+        client_handler.accept_client(disconnected_addr, disconnected_id.clone());
+        let message_id = MessageId::new();
+        let _ = client_handler
+            .pending_msg_ids
+            .insert(message_id, disconnected_addr);
+        let _ = client_handler.handle_connection_failure(disconnected_addr);
+        assert_eq!(client_handler.awaiting_reconnect.len(), 1);
+
+        client_handler.handle_new_connection(unidentified_addr);
+
+        assert_eq!(
+            client_handler.handle_transport_finished(),
+            vec![connected_id]
+        );
+        assert!(client_handler.clients.is_empty());
+        assert!(client_handler.connections.is_empty());
+        assert!(client_handler.client_candidates.is_empty());
+        assert!(client_handler.pending_msg_ids.is_empty());
+        assert!(client_handler.awaiting_reconnect.is_empty());
+
+        // The disconnected client's response is no longer held for it.
+        client_handler.send_response_to_client(message_id, Response::Mutation(Ok(())));
+        client_handler.accept_client(disconnected_addr, disconnected_id);
+        assert!(routing.borrow().sent.is_empty());
+    }
+}
@@ -39,9 +39,9 @@ pub(super) struct AuthKeysDb {
 }
 
 impl AuthKeysDb {
-    pub fn new<R: AsRef<Path>>(root_dir: R, init_mode: Init) -> Result<Self> {
+    pub fn new<R: AsRef<Path>>(root_dir: R, init_mode: Init, load_retries: u32) -> Result<Self> {
         Ok(Self {
-            db: utils::new_db(root_dir, AUTH_KEYS_DB_NAME, init_mode)?,
+            db: utils::new_db(root_dir, AUTH_KEYS_DB_NAME, init_mode, load_retries)?,
         })
     }
 
@@ -29,8 +29,8 @@ pub(super) struct BalancesDb {
 }
 
 impl BalancesDb {
-    pub fn new<R: AsRef<Path>>(root_dir: R, init_mode: Init) -> Result<Self> {
-        let db = utils::new_db(root_dir, BALANCES_DB_NAME, init_mode)?;
+    pub fn new<R: AsRef<Path>>(root_dir: R, init_mode: Init, load_retries: u32) -> Result<Self> {
+        let db = utils::new_db(root_dir, BALANCES_DB_NAME, init_mode, load_retries)?;
         let index = db
             .get_all()
             .into_iter()
@@ -0,0 +1,43 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::SECTION_BUSY;
+use safe_nd::{Error as NdError, Response};
+
+/// Why the cost of a put was refunded to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RefundReason {
+    /// The section was busy or out of space.  See `SECTION_BUSY`.
+    SectionBusy,
+    /// The data already exists.
+    DataExists,
+    /// The client isn't permitted to put the data.
+    AccessDenied,
+    /// The data is too large.
+    ExceededSize,
+    /// Any other failure.
+    Other,
+}
+
+impl RefundReason {
+    /// Returns the reason for refunding the put which produced `response`.
+    pub(super) fn of(response: &Response) -> Self {
+        match response {
+            Response::Mutation(Err(error)) => match error {
+                NdError::NetworkOther(message) if message == SECTION_BUSY => {
+                    RefundReason::SectionBusy
+                }
+                NdError::DataExists => RefundReason::DataExists,
+                NdError::AccessDenied => RefundReason::AccessDenied,
+                NdError::ExceededSize => RefundReason::ExceededSize,
+                _ => RefundReason::Other,
+            },
+            _ => RefundReason::Other,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::routing::NetworkEvent as ClientEvent;
+use safe_nd::{Message, RequestType};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    net::SocketAddr,
+};
+
+// Number of reads handled in a row while writes are waiting, before a write is let through.
+const MAX_CONSECUTIVE_READS: usize = 4;
+
+/// Client events waiting to be handled.  Requests which mutate data are queued behind other
+/// clients' events, so that a burst of them doesn't delay reads, but one is still let through
+/// after every `MAX_CONSECUTIVE_READS` other events so that they aren't starved either.  Events
+/// from the same peer are always handled in the order they arrived.
+#[derive(Default)]
+pub(crate) struct ClientQueue {
+    events: VecDeque<QueuedEvent>,
+    consecutive_reads: usize,
+}
+
+struct QueuedEvent {
+    event: ClientEvent,
+    peer_addr: Option<SocketAddr>,
+    is_write: bool,
+}
+
+impl ClientQueue {
+    pub fn push(&mut self, event: ClientEvent) {
+        let peer_addr = peer_addr(&event);
+        let is_write = is_write(&event);
+        self.events.push_back(QueuedEvent {
+            event,
+            peer_addr,
+            is_write,
+        });
+    }
+
+    pub fn pop(&mut self) -> Option<ClientEvent> {
+        let writes_waiting = self.events.iter().any(|queued| queued.is_write);
+        let (first_read, first_write) = self.first_ready();
+        let index = if self.consecutive_reads >= MAX_CONSECUTIVE_READS || first_read.is_none() {
+            first_write.or(first_read)
+        } else {
+            first_read
+        }?;
+        let queued = self.events.remove(index)?;
+        if queued.is_write {
+            self.consecutive_reads = 0;
+        } else if writes_waiting {
+            self.consecutive_reads += 1;
+        }
+        Some(queued.event)
+    }
+
+    // Returns the indices of the first read and of the first write which can be handled next, i.e.
+    // which are the oldest event queued for their peer.  An event with no peer is only ready once
+    // it's at the front, and nothing queued after it is.
+    fn first_ready(&self) -> (Option<usize>, Option<usize>) {
+        let mut first_read = None;
+        let mut first_write = None;
+        let mut seen_peers = BTreeSet::new();
+        for (index, queued) in self.events.iter().enumerate() {
+            let ready = match queued.peer_addr {
+                Some(peer_addr) => seen_peers.insert(peer_addr),
+                None => index == 0,
+            };
+            if ready {
+                if queued.is_write {
+                    first_write = first_write.or(Some(index));
+                } else {
+                    first_read = first_read.or(Some(index));
+                }
+            }
+            if queued.peer_addr.is_none() || (first_read.is_some() && first_write.is_some()) {
+                break;
+            }
+        }
+        (first_read, first_write)
+    }
+}
+
+// Returns the address of the peer `event` concerns, if any.
+fn peer_addr(event: &ClientEvent) -> Option<SocketAddr> {
+    match event {
+        ClientEvent::ConnectedTo { peer }
+        | ClientEvent::ConnectionFailure { peer, .. }
+        | ClientEvent::NewMessage { peer, .. }
+        | ClientEvent::SentUserMessage { peer, .. }
+        | ClientEvent::UnsentUserMessage { peer, .. } => Some(peer.peer_addr()),
+        ClientEvent::BootstrapFailure
+        | ClientEvent::BootstrappedTo { .. }
+        | ClientEvent::Finish => None,
+    }
+}
+
+// Returns whether `event` carries a client request which mutates data or transfers coins.  All
+// other events, including connection events and handshakes, are treated as reads.
+fn is_write(event: &ClientEvent) -> bool {
+    let msg = match event {
+        ClientEvent::NewMessage { msg, .. } => msg,
+        _ => return false,
+    };
+    match bincode::deserialize(msg) {
+        Ok(Message::Request { request, .. }) => match request.get_type() {
+            RequestType::Mutation | RequestType::Transaction => true,
+            RequestType::PublicGet | RequestType::PrivateGet => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use mock_quic_p2p::Peer;
+    use safe_nd::{IData, MessageId, PubImmutableData, Request};
+    use unwrap::unwrap;
+
+    fn request_event(peer_addr: SocketAddr, request: Request) -> (ClientEvent, MessageId) {
+        let message_id = MessageId::new();
+        let msg = Message::Request {
+            request,
+            message_id,
+            signature: None,
+        };
+        let event = ClientEvent::NewMessage {
+            peer: Peer::Client(peer_addr),
+            msg: Bytes::from(unwrap!(bincode::serialize(&msg))),
+        };
+        (event, message_id)
+    }
+
+    fn read(peer_addr: SocketAddr) -> (ClientEvent, MessageId) {
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        request_event(peer_addr, Request::GetIData(*data.address()))
+    }
+
+    fn write(peer_addr: SocketAddr) -> (ClientEvent, MessageId) {
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        request_event(peer_addr, Request::PutIData(data))
+    }
+
+    fn message_id(event: &ClientEvent) -> MessageId {
+        match event {
+            ClientEvent::NewMessage { msg, .. } => match unwrap!(bincode::deserialize(msg)) {
+                Message::Request { message_id, .. } => message_id,
+                msg => panic!("Unexpected {:?}", msg),
+            },
+            event => panic!("Unexpected {:?}", event),
+        }
+    }
+
+    fn drain(queue: &mut ClientQueue) -> Vec<MessageId> {
+        let mut popped = Vec::new();
+        while let Some(event) = queue.pop() {
+            popped.push(message_id(&event));
+        }
+        popped
+    }
+
+    #[test]
+    fn write_is_let_through_after_max_consecutive_reads() {
+        let reader: SocketAddr = unwrap!("192.168.0.1:5000".parse());
+        let writer: SocketAddr = unwrap!("192.168.0.2:5000".parse());
+        let mut queue = ClientQueue::default();
+
+        let mut writes = Vec::new();
+        for _ in 0..2 {
+            let (event, message_id) = write(writer);
+            queue.push(event);
+            writes.push(message_id);
+        }
+        let mut reads = Vec::new();
+        for _ in 0..2 * MAX_CONSECUTIVE_READS + 1 {
+            let (event, message_id) = read(reader);
+            queue.push(event);
+            reads.push(message_id);
+        }
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&reads[..MAX_CONSECUTIVE_READS]);
+        expected.push(writes[0]);
+        expected.extend_from_slice(&reads[MAX_CONSECUTIVE_READS..2 * MAX_CONSECUTIVE_READS]);
+        expected.push(writes[1]);
+        expected.push(reads[2 * MAX_CONSECUTIVE_READS]);
+        assert_eq!(drain(&mut queue), expected);
+    }
+
+    #[test]
+    fn events_from_one_peer_keep_their_order() {
+        let peer_addr: SocketAddr = unwrap!("192.168.0.1:5000".parse());
+        let other_addr: SocketAddr = unwrap!("192.168.0.2:5000".parse());
+        let mut queue = ClientQueue::default();
+
+        let (first_write, first_write_id) = write(peer_addr);
+        let (read_after_write, read_after_write_id) = read(peer_addr);
+        let (other_read, other_read_id) = read(other_addr);
+        queue.push(first_write);
+        queue.push(read_after_write);
+        queue.push(other_read);
+
+        // The other peer's read may overtake the write, but the read sent after the write by the
+        // same peer may not.
+        assert_eq!(
+            drain(&mut queue),
+            vec![other_read_id, first_write_id, read_after_write_id]
+        );
+    }
+}
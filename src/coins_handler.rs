@@ -23,8 +23,13 @@ pub(crate) struct CoinsHandler {
 }
 
 impl CoinsHandler {
-    pub fn new<P: AsRef<Path>>(id: NodePublicId, root_dir: P, init_mode: Init) -> Result<Self> {
-        let _farmed = utils::new_db(root_dir, COINS_DB_NAME, init_mode)?;
+    pub fn new<P: AsRef<Path>>(
+        id: NodePublicId,
+        root_dir: P,
+        init_mode: Init,
+        load_retries: u32,
+    ) -> Result<Self> {
+        let _farmed = utils::new_db(root_dir, COINS_DB_NAME, init_mode, load_retries)?;
         Ok(Self { id, _farmed })
     }
 }
@@ -7,16 +7,20 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::routing::NetworkConfig;
-use crate::Result;
+use crate::{Error, Result};
 use directories::ProjectDirs;
 use lazy_static::lazy_static;
 use log::{trace, Level};
 use serde::{Deserialize, Serialize};
 use std::{
+    env,
+    fmt::Display,
     fs::{self, File},
     io::{self, BufReader},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
+    str::FromStr,
+    time::Duration,
 };
 use structopt::StructOpt;
 use unwrap::unwrap;
@@ -36,7 +40,11 @@ const CONFIG_FILE: &str = "vault.config";
 const CONNECTION_INFO_FILE: &str = "vault_connection_info.config";
 const DEFAULT_ROOT_DIR_NAME: &str = "root_dir";
 const DEFAULT_MAX_CAPACITY: u64 = 2 * 1024 * 1024 * 1024;
-const ARGS: [&str; 17] = [
+const DEFAULT_MAX_IN_FLIGHT_OPS: usize = 10_000;
+const DEFAULT_MAX_HOLDERS_PER_OP: usize = 8;
+const DEFAULT_LOAD_RETRIES: u32 = 3;
+const DEFAULT_MAX_ACTION_CHAIN: usize = 64;
+const ARGS: [&str; 35] = [
     "wallet-address",
     "max-capacity",
     "root-dir",
@@ -54,7 +62,64 @@ const ARGS: [&str; 17] = [
     "log-dir",
     "update",
     "update-only",
+    "segment-size-threshold",
+    "max-in-flight-ops",
+    "operator-whitelist",
+    "min-free-space",
+    "admin-address",
+    "watchdog-interval",
+    "watchdog-abort",
+    "idempotent-unpub-put",
+    "max-holders-per-op",
+    "metrics-log-interval",
+    "advertised-address",
+    "load-retries",
+    "network-name",
+    "max-action-chain",
+    "read-only",
+    "trim-strategy",
+    "reconcile-metadata",
+    "max-rpc-size",
 ];
+const ENV_VAR_PREFIX: &str = "SAFE_VAULT_";
+// Indices into `ARGS` of the options which can also be set by environment variable.
+const ENV_ARGS: [usize; 26] = [
+    0, 1, 2, 3, 5, 6, 12, 14, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33,
+    34,
+];
+
+/// Which holders an over-replicated immutable data chunk is deleted from when it's trimmed back to
+/// its target number of holders.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrimStrategy {
+    /// Delete it from the holders farthest from its name.
+    Farthest,
+    /// Delete it from the holders with the lowest reliability score, the farthest of those with
+    /// equal scores.
+    LeastReliable,
+}
+
+impl Default for TrimStrategy {
+    fn default() -> Self {
+        TrimStrategy::Farthest
+    }
+}
+
+impl FromStr for TrimStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value {
+            "farthest" => Ok(TrimStrategy::Farthest),
+            "least-reliable" => Ok(TrimStrategy::LeastReliable),
+            _ => Err(format!(
+                "expected \"farthest\" or \"least-reliable\", got {:?}",
+                value
+            )),
+        }
+    }
+}
 
 /// Vault configuration
 #[derive(Default, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, StructOpt)]
@@ -97,12 +162,94 @@ pub struct Config {
     /// Attempt to self-update without starting the vault process
     #[structopt(long, name = "update-only")]
     update_only: bool,
+    /// Size in bytes above which immutable data is sent to its holders in segments of at most this
+    /// size, rather than in a single message. If not set, data is never segmented.
+    #[structopt(long)]
+    segment_size_threshold: Option<u64>,
+    /// Maximum number of immutable data operations handled concurrently. Further requests are
+    /// refused as "section busy" until some complete. If not set, defaults to 10,000.
+    #[structopt(long)]
+    max_in_flight_ops: Option<usize>,
+    /// Tokens accepted as operator credentials for privileged commands which don't arrive over the
+    /// vault's in-process command channel, as a JSON array of strings.
+    #[structopt(long)]
+    #[serde(default)]
+    operator_whitelist: Vec<String>,
+    /// Free space in bytes to leave on the filesystem holding the root dir. Puts which would
+    /// leave less than this are refused, regardless of `max_capacity`. If not set, no floor is
+    /// enforced.
+    #[structopt(long)]
+    min_free_space: Option<u64>,
+    /// Loopback address on which to accept admin commands, if built with the `admin_listener`
+    /// feature. If not set, no admin listener is started.
+    #[structopt(long)]
+    admin_address: Option<SocketAddr>,
+    /// Seconds the event loop may go without making progress before the watchdog reports it as
+    /// wedged. If not set, no watchdog runs.
+    #[structopt(long)]
+    watchdog_interval: Option<u64>,
+    /// Abort the process when the watchdog finds the event loop wedged, so that a supervisor can
+    /// restart it.
+    #[structopt(long)]
+    watchdog_abort: bool,
+    /// Reply success rather than `DataExists` when the owner of unpublished immutable data puts it
+    /// again.
+    #[structopt(long)]
+    idempotent_unpub_put: bool,
+    /// Maximum number of holders a single immutable data get queries, however many the chunk's
+    /// metadata lists. If not set, defaults to 8.
+    #[structopt(long)]
+    max_holders_per_op: Option<usize>,
+    /// Seconds between logging the vault's metrics as a single line. If not set, metrics are only
+    /// logged on request.
+    #[structopt(long)]
+    metrics_log_interval: Option<u64>,
+    /// External address to report as our connection info instead of the one we're bound to, e.g.
+    /// when behind NAT. Must be routable. If not set, the bound address is reported.
+    #[structopt(long)]
+    advertised_address: Option<SocketAddr>,
+    /// Times to retry reading the state file or a database which fails to load with a transient IO
+    /// error, backing off between attempts. If not set, defaults to 3.
+    #[structopt(long)]
+    load_retries: Option<u32>,
+    /// Name of the network the vault belongs to, shown in its logs and stored in its state file, to
+    /// tell apart vaults of different networks run from one host.
+    #[structopt(long)]
+    network_name: Option<String>,
+    /// Maximum number of actions handled in a chain of follow-ups to a single event. A longer chain
+    /// is abandoned and logged as an error. If not set, defaults to 64.
+    #[structopt(long)]
+    max_action_chain: Option<usize>,
+    /// Hold and serve data, but refuse all mutations and coin transactions from clients, e.g. for
+    /// an archive or standby vault.
+    #[structopt(long)]
+    read_only: bool,
+    /// Which holders an over-replicated immutable data chunk is deleted from when a storage scan
+    /// trims it back to its target number of holders: "farthest" or "least-reliable". If not set,
+    /// defaults to "farthest".
+    #[structopt(long)]
+    trim_strategy: Option<TrimStrategy>,
+    /// Correct the immutable data chunk metadata from the responses to gets: holders which report
+    /// not having a chunk they're listed as holding are dropped, and the chunk is replicated to
+    /// replace them.
+    #[structopt(long)]
+    reconcile_metadata: bool,
+    /// Largest RPC, in bytes once serialised, which the vault sends. A larger put is split into
+    /// segments which fit, a larger chunk returned by a get is replaced by an error, and any other
+    /// larger RPC is dropped with an error logged. If not set, defaults to the network config's
+    /// `max_msg_size_allowed`, if that's set.
+    #[structopt(long)]
+    max_rpc_size: Option<u64>,
 }
 
 impl Config {
     /// Returns a new `Config` instance.  Tries to read from the default vault config file location,
-    /// and overrides values with any equivalent command line args.
-    pub fn new() -> Self {
+    /// and overrides values with any equivalent environment variables (see `from_env`) and then
+    /// with any equivalent command line args.
+    ///
+    /// Returns `Error::InvalidConfig` if an environment variable is unknown or has an invalid
+    /// value, as `from_env` does.  Panics if a command line arg has an invalid value.
+    pub fn new() -> Result<Self> {
         let mut config = Self::read_from_file().unwrap_or(Self {
             wallet_address: None,
             max_capacity: None,
@@ -114,7 +261,26 @@ impl Config {
             log_dir: None,
             update: false,
             update_only: false,
+            segment_size_threshold: None,
+            max_in_flight_ops: None,
+            operator_whitelist: Default::default(),
+            min_free_space: None,
+            admin_address: None,
+            watchdog_interval: None,
+            watchdog_abort: false,
+            idempotent_unpub_put: false,
+            max_holders_per_op: None,
+            metrics_log_interval: None,
+            advertised_address: None,
+            load_retries: None,
+            network_name: None,
+            max_action_chain: None,
+            read_only: false,
+            trim_strategy: None,
+            reconcile_metadata: false,
+            max_rpc_size: None,
         });
+        config.set_from_env(env::vars())?;
 
         let command_line_args = Config::clap().get_matches();
         for arg in &ARGS {
@@ -128,7 +294,48 @@ impl Config {
             }
         }
 
-        config
+        Ok(config)
+    }
+
+    /// Returns a new `Config` instance with default values overridden by any equivalent
+    /// environment variables.  The config file and command line args are ignored.
+    ///
+    /// Each variable is named `SAFE_VAULT_` followed by the option's long command line name in
+    /// upper snake case, and takes the same value as that option:
+    ///
+    /// * `SAFE_VAULT_WALLET_ADDRESS`
+    /// * `SAFE_VAULT_MAX_CAPACITY` (default 2 GiB)
+    /// * `SAFE_VAULT_ROOT_DIR` (default "root_dir" in the project data directory)
+    /// * `SAFE_VAULT_VERBOSE`, as a number of `-v` occurrences (default 0)
+    /// * `SAFE_VAULT_PORT`
+    /// * `SAFE_VAULT_IP`
+    /// * `SAFE_VAULT_FIRST`, as `true` or `false` (default `false`)
+    /// * `SAFE_VAULT_LOG_DIR`
+    /// * `SAFE_VAULT_SEGMENT_SIZE_THRESHOLD`
+    /// * `SAFE_VAULT_MAX_IN_FLIGHT_OPS` (default 10,000)
+    /// * `SAFE_VAULT_OPERATOR_WHITELIST`, as a JSON array of strings
+    /// * `SAFE_VAULT_MIN_FREE_SPACE` (default 0)
+    /// * `SAFE_VAULT_ADMIN_ADDRESS`
+    /// * `SAFE_VAULT_WATCHDOG_INTERVAL`, in seconds
+    /// * `SAFE_VAULT_WATCHDOG_ABORT`, as `true` or `false` (default `false`)
+    /// * `SAFE_VAULT_IDEMPOTENT_UNPUB_PUT`, as `true` or `false` (default `false`)
+    /// * `SAFE_VAULT_MAX_HOLDERS_PER_OP` (default 8)
+    /// * `SAFE_VAULT_METRICS_LOG_INTERVAL`, in seconds
+    /// * `SAFE_VAULT_ADVERTISED_ADDRESS`
+    /// * `SAFE_VAULT_LOAD_RETRIES` (default 3)
+    /// * `SAFE_VAULT_NETWORK_NAME`
+    /// * `SAFE_VAULT_MAX_ACTION_CHAIN` (default 64)
+    /// * `SAFE_VAULT_READ_ONLY`, as `true` or `false` (default `false`)
+    /// * `SAFE_VAULT_TRIM_STRATEGY`, as `farthest` or `least-reliable` (default `farthest`)
+    /// * `SAFE_VAULT_RECONCILE_METADATA`, as `true` or `false` (default `false`)
+    /// * `SAFE_VAULT_MAX_RPC_SIZE`
+    ///
+    /// Returns `Error::InvalidConfig` naming the variable if any of these has an invalid value, or
+    /// if any other variable starting with `SAFE_VAULT_` is set.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        config.set_from_env(env::vars())?;
+        Ok(config)
     }
 
     /// The address to be credited when this vault farms SafeCoin.
@@ -202,6 +409,254 @@ impl Config {
         self.update_only
     }
 
+    /// Size in bytes above which immutable data is sent to its holders in segments.
+    pub fn segment_size_threshold(&self) -> Option<u64> {
+        self.segment_size_threshold
+    }
+
+    /// Set the size in bytes above which immutable data is sent to its holders in segments.
+    pub fn set_segment_size_threshold(&mut self, threshold: Option<u64>) {
+        self.segment_size_threshold = threshold;
+    }
+
+    /// Maximum number of immutable data operations handled concurrently.
+    pub fn max_in_flight_ops(&self) -> usize {
+        self.max_in_flight_ops.unwrap_or(DEFAULT_MAX_IN_FLIGHT_OPS)
+    }
+
+    /// Set the maximum number of immutable data operations handled concurrently.
+    pub fn set_max_in_flight_ops(&mut self, max_in_flight_ops: Option<usize>) {
+        self.max_in_flight_ops = max_in_flight_ops;
+    }
+
+    /// Tokens accepted as operator credentials for privileged commands.
+    pub fn operator_whitelist(&self) -> &[String] {
+        &self.operator_whitelist
+    }
+
+    /// Set the tokens accepted as operator credentials for privileged commands.
+    pub fn set_operator_whitelist(&mut self, operator_whitelist: Vec<String>) {
+        self.operator_whitelist = operator_whitelist;
+    }
+
+    /// Free space in bytes to leave on the filesystem holding the root dir.
+    pub fn min_free_space(&self) -> u64 {
+        self.min_free_space.unwrap_or(0)
+    }
+
+    /// Set the free space in bytes to leave on the filesystem holding the root dir.
+    pub fn set_min_free_space(&mut self, min_free_space: Option<u64>) {
+        self.min_free_space = min_free_space;
+    }
+
+    /// Loopback address on which to accept admin commands, if any.
+    pub fn admin_address(&self) -> Option<SocketAddr> {
+        self.admin_address
+    }
+
+    /// Checks that `new` differs from this config only in fields which can be applied to a running
+    /// vault, returning `Error::InvalidConfig` naming the others if not.
+    ///
+    /// `max_capacity`, `min_free_space`, `max_in_flight_ops`, `segment_size_threshold`,
+    /// `operator_whitelist`, `idempotent_unpub_put`, `max_holders_per_op`, `metrics_log_interval`,
+    /// `max_action_chain`, `read_only`, `trim_strategy`, `reconcile_metadata` and `max_rpc_size`
+    /// can be changed.  All other fields only take effect on restart.
+    pub fn check_reloadable(&self, new: &Config) -> Result<()> {
+        let mut fixed = Vec::new();
+        if self.wallet_address != new.wallet_address {
+            fixed.push("wallet_address");
+        }
+        if self.root_dir != new.root_dir {
+            fixed.push("root_dir");
+        }
+        if self.verbose != new.verbose {
+            fixed.push("verbose");
+        }
+        if self.first != new.first {
+            fixed.push("first");
+        }
+        if self.network_config != new.network_config {
+            fixed.push("network_config");
+        }
+        if self.completions != new.completions {
+            fixed.push("completions");
+        }
+        if self.log_dir != new.log_dir {
+            fixed.push("log_dir");
+        }
+        if self.update != new.update || self.update_only != new.update_only {
+            fixed.push("update");
+        }
+        if self.admin_address != new.admin_address {
+            fixed.push("admin_address");
+        }
+        if self.advertised_address != new.advertised_address {
+            fixed.push("advertised_address");
+        }
+        if self.load_retries != new.load_retries {
+            fixed.push("load_retries");
+        }
+        if self.network_name != new.network_name {
+            fixed.push("network_name");
+        }
+        if self.watchdog_interval != new.watchdog_interval
+            || self.watchdog_abort != new.watchdog_abort
+        {
+            fixed.push("watchdog");
+        }
+        if fixed.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(format!(
+                "{} can't be changed without a restart",
+                fixed.join(", ")
+            )))
+        }
+    }
+
+    /// How long the event loop may go without making progress before the watchdog reports it as
+    /// wedged, if a watchdog is to run.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval.map(Duration::from_secs)
+    }
+
+    /// Set how many seconds the event loop may go without making progress before the watchdog
+    /// reports it as wedged.  `None` disables the watchdog.
+    pub fn set_watchdog_interval(&mut self, watchdog_interval: Option<u64>) {
+        self.watchdog_interval = watchdog_interval;
+    }
+
+    /// Whether to abort the process when the watchdog finds the event loop wedged.
+    pub fn watchdog_abort(&self) -> bool {
+        self.watchdog_abort
+    }
+
+    /// Whether the owner of unpublished immutable data putting it again gets success rather than
+    /// `DataExists`.
+    pub fn idempotent_unpub_put(&self) -> bool {
+        self.idempotent_unpub_put
+    }
+
+    /// Set whether the owner of unpublished immutable data putting it again gets success rather
+    /// than `DataExists`.
+    pub fn set_idempotent_unpub_put(&mut self, idempotent_unpub_put: bool) {
+        self.idempotent_unpub_put = idempotent_unpub_put;
+    }
+
+    /// Maximum number of holders a single immutable data get queries.
+    pub fn max_holders_per_op(&self) -> usize {
+        self.max_holders_per_op
+            .unwrap_or(DEFAULT_MAX_HOLDERS_PER_OP)
+    }
+
+    /// Set the maximum number of holders a single immutable data get queries.
+    pub fn set_max_holders_per_op(&mut self, max_holders_per_op: Option<usize>) {
+        self.max_holders_per_op = max_holders_per_op;
+    }
+
+    /// How often to log the vault's metrics, if at all.
+    pub fn metrics_log_interval(&self) -> Option<Duration> {
+        self.metrics_log_interval.map(Duration::from_secs)
+    }
+
+    /// Set how many seconds to wait between logging the vault's metrics.  `None` only logs them on
+    /// request.
+    pub fn set_metrics_log_interval(&mut self, metrics_log_interval: Option<u64>) {
+        self.metrics_log_interval = metrics_log_interval;
+    }
+
+    /// External address to report as our connection info, if set.
+    ///
+    /// Returns `Error::InvalidConfig` if the address isn't routable: unspecified, loopback,
+    /// multicast, broadcast, or with port 0.
+    pub fn advertised_address(&self) -> Result<Option<SocketAddr>> {
+        match self.advertised_address {
+            Some(address) if !is_routable(&address) => Err(Error::InvalidConfig(format!(
+                "advertised_address {} isn't routable",
+                address
+            ))),
+            address => Ok(address),
+        }
+    }
+
+    /// Set the external address to report as our connection info.
+    pub fn set_advertised_address(&mut self, advertised_address: Option<SocketAddr>) {
+        self.advertised_address = advertised_address;
+    }
+
+    /// Times to retry reading the state file or a database which fails to load with a transient
+    /// IO error.
+    pub fn load_retries(&self) -> u32 {
+        self.load_retries.unwrap_or(DEFAULT_LOAD_RETRIES)
+    }
+
+    /// Set the times to retry reading the state file or a database which fails to load with a
+    /// transient IO error.
+    pub fn set_load_retries(&mut self, load_retries: Option<u32>) {
+        self.load_retries = load_retries;
+    }
+
+    /// Name of the network the vault belongs to, if set.
+    pub fn network_name(&self) -> Option<&String> {
+        self.network_name.as_ref()
+    }
+
+    /// Set the name of the network the vault belongs to.
+    pub fn set_network_name(&mut self, network_name: Option<String>) {
+        self.network_name = network_name;
+    }
+
+    /// Maximum number of actions handled in a chain of follow-ups to a single event.
+    pub fn max_action_chain(&self) -> usize {
+        self.max_action_chain.unwrap_or(DEFAULT_MAX_ACTION_CHAIN)
+    }
+
+    /// Set the maximum number of actions handled in a chain of follow-ups to a single event.
+    pub fn set_max_action_chain(&mut self, max_action_chain: Option<usize>) {
+        self.max_action_chain = max_action_chain;
+    }
+
+    /// Whether mutations and coin transactions from clients are refused.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Set whether mutations and coin transactions from clients are refused.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Which holders an over-replicated chunk is deleted from.
+    pub fn trim_strategy(&self) -> TrimStrategy {
+        self.trim_strategy.unwrap_or_default()
+    }
+
+    /// Set which holders an over-replicated chunk is deleted from.
+    pub fn set_trim_strategy(&mut self, trim_strategy: Option<TrimStrategy>) {
+        self.trim_strategy = trim_strategy;
+    }
+
+    /// Whether chunk metadata is corrected from the responses to gets.
+    pub fn reconcile_metadata(&self) -> bool {
+        self.reconcile_metadata
+    }
+
+    /// Set whether chunk metadata is corrected from the responses to gets.
+    pub fn set_reconcile_metadata(&mut self, reconcile_metadata: bool) {
+        self.reconcile_metadata = reconcile_metadata;
+    }
+
+    /// Largest RPC sent, in bytes once serialised, if limited.
+    pub fn max_rpc_size(&self) -> Option<u64> {
+        self.max_rpc_size
+            .or_else(|| self.network_config.max_msg_size_allowed.map(u64::from))
+    }
+
+    /// Set the largest RPC sent, in bytes once serialised.
+    pub fn set_max_rpc_size(&mut self, max_rpc_size: Option<u64>) {
+        self.max_rpc_size = max_rpc_size;
+    }
+
     /// Set the Quic-P2P `ip` configuration to 127.0.0.1.
     pub fn listen_on_loopback(&mut self) {
         self.network_config.ip = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
@@ -228,6 +683,34 @@ impl Config {
             self.completions = Some(unwrap!(value.parse()));
         } else if arg == ARGS[14] {
             self.log_dir = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[17] {
+            self.segment_size_threshold = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[18] {
+            self.max_in_flight_ops = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[19] {
+            self.operator_whitelist = unwrap!(serde_json::from_str(value));
+        } else if arg == ARGS[20] {
+            self.min_free_space = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[21] {
+            self.admin_address = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[22] {
+            self.watchdog_interval = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[25] {
+            self.max_holders_per_op = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[26] {
+            self.metrics_log_interval = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[27] {
+            self.advertised_address = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[28] {
+            self.load_retries = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[29] {
+            self.network_name = Some(value.to_string());
+        } else if arg == ARGS[30] {
+            self.max_action_chain = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[32] {
+            self.trim_strategy = Some(unwrap!(value.parse()));
+        } else if arg == ARGS[34] {
+            self.max_rpc_size = Some(unwrap!(value.parse()));
         } else {
             #[cfg(not(feature = "mock_base"))]
             {
@@ -258,11 +741,95 @@ impl Config {
             self.update = occurrences >= 1;
         } else if arg == ARGS[16] {
             self.update_only = occurrences >= 1;
+        } else if arg == ARGS[23] {
+            self.watchdog_abort = occurrences >= 1;
+        } else if arg == ARGS[24] {
+            self.idempotent_unpub_put = occurrences >= 1;
+        } else if arg == ARGS[31] {
+            self.read_only = occurrences >= 1;
+        } else if arg == ARGS[33] {
+            self.reconcile_metadata = occurrences >= 1;
         } else {
             println!("ERROR");
         }
     }
 
+    fn set_from_env<I: IntoIterator<Item = (String, String)>>(&mut self, vars: I) -> Result<()> {
+        for (name, value) in vars {
+            if !name.starts_with(ENV_VAR_PREFIX) {
+                continue;
+            }
+            let arg = ENV_ARGS
+                .iter()
+                .map(|&index| ARGS[index])
+                .find(|arg| env_var_name(arg) == name)
+                .ok_or_else(|| Error::InvalidConfig(format!("unknown variable {}", name)))?;
+            self.set_env_value(arg, &value).map_err(|reason| {
+                Error::InvalidConfig(format!("{}={:?}: {}", name, value, reason))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn set_env_value(&mut self, arg: &str, value: &str) -> Result<(), String> {
+        if arg == ARGS[0] {
+            self.wallet_address = Some(value.to_string());
+        } else if arg == ARGS[1] {
+            self.max_capacity = Some(parse_env_value(value)?);
+        } else if arg == ARGS[2] {
+            self.root_dir = Some(PathBuf::from(value));
+        } else if arg == ARGS[3] {
+            self.verbose = parse_env_value(value)?;
+        } else if arg == ARGS[5] {
+            self.network_config.port = Some(parse_env_value(value)?);
+        } else if arg == ARGS[6] {
+            self.network_config.ip = Some(parse_env_value(value)?);
+        } else if arg == ARGS[12] {
+            self.first = parse_env_value(value)?;
+        } else if arg == ARGS[14] {
+            self.log_dir = Some(value.to_string());
+        } else if arg == ARGS[17] {
+            self.segment_size_threshold = Some(parse_env_value(value)?);
+        } else if arg == ARGS[18] {
+            self.max_in_flight_ops = Some(parse_env_value(value)?);
+        } else if arg == ARGS[19] {
+            self.operator_whitelist = serde_json::from_str(value).map_err(|e| e.to_string())?;
+        } else if arg == ARGS[20] {
+            self.min_free_space = Some(parse_env_value(value)?);
+        } else if arg == ARGS[21] {
+            self.admin_address = Some(parse_env_value(value)?);
+        } else if arg == ARGS[22] {
+            self.watchdog_interval = Some(parse_env_value(value)?);
+        } else if arg == ARGS[23] {
+            self.watchdog_abort = parse_env_value(value)?;
+        } else if arg == ARGS[24] {
+            self.idempotent_unpub_put = parse_env_value(value)?;
+        } else if arg == ARGS[25] {
+            self.max_holders_per_op = Some(parse_env_value(value)?);
+        } else if arg == ARGS[26] {
+            self.metrics_log_interval = Some(parse_env_value(value)?);
+        } else if arg == ARGS[27] {
+            self.advertised_address = Some(parse_env_value(value)?);
+        } else if arg == ARGS[28] {
+            self.load_retries = Some(parse_env_value(value)?);
+        } else if arg == ARGS[29] {
+            self.network_name = Some(value.to_string());
+        } else if arg == ARGS[30] {
+            self.max_action_chain = Some(parse_env_value(value)?);
+        } else if arg == ARGS[31] {
+            self.read_only = parse_env_value(value)?;
+        } else if arg == ARGS[32] {
+            self.trim_strategy = Some(parse_env_value(value)?);
+        } else if arg == ARGS[33] {
+            self.reconcile_metadata = parse_env_value(value)?;
+        } else if arg == ARGS[34] {
+            self.max_rpc_size = Some(parse_env_value(value)?);
+        } else {
+            return Err(format!("{} can't be set from the environment", arg));
+        }
+        Ok(())
+    }
+
     /// Reads the default vault config file.
     fn read_from_file() -> Result<Config> {
         let path = project_dirs()?.config_dir().join(CONFIG_FILE);
@@ -294,6 +861,20 @@ impl Config {
     }
 }
 
+// Whether peers elsewhere could reach us at `address`.
+fn is_routable(address: &SocketAddr) -> bool {
+    let ip = address.ip();
+    let broadcast = match ip {
+        IpAddr::V4(ipv4) => ipv4.is_broadcast(),
+        IpAddr::V6(_) => false,
+    };
+    address.port() != 0
+        && !ip.is_unspecified()
+        && !ip.is_loopback()
+        && !ip.is_multicast()
+        && !broadcast
+}
+
 /// Writes connection info to file for use by clients.
 ///
 /// The file is written to the `current_bin_dir()` with the appropriate file name.
@@ -317,6 +898,20 @@ where
     Ok(path)
 }
 
+// Returns the name of the environment variable which sets `arg`, e.g. "SAFE_VAULT_MAX_CAPACITY" for
+// "max-capacity".
+fn env_var_name(arg: &str) -> String {
+    format!("{}{}", ENV_VAR_PREFIX, arg.to_uppercase().replace('-', "_"))
+}
+
+fn parse_env_value<T>(value: &str) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: Display,
+{
+    value.parse().map_err(|error: T::Err| error.to_string())
+}
+
 fn project_dirs() -> Result<&'static ProjectDirs> {
     PROJECT_DIRS
         .as_ref()
@@ -328,10 +923,11 @@ mod test {
     use super::Config;
     #[cfg(not(feature = "mock_base"))]
     use super::ARGS;
+    use crate::Error;
     use serde_json;
     #[cfg(not(feature = "mock_base"))]
     use std::mem;
-    use std::{fs::File, io::Read, path::Path};
+    use std::{fs::File, io::Read, net::SocketAddr, path::Path};
     #[cfg(not(feature = "mock_base"))]
     use structopt::StructOpt;
     use unwrap::unwrap;
@@ -339,7 +935,7 @@ mod test {
     #[cfg(not(feature = "mock_base"))]
     #[test]
     fn smoke() {
-        let expected_size = 344;
+        let expected_size = 592;
         assert_eq!(
             expected_size,
             mem::size_of::<Config>(),
@@ -369,6 +965,24 @@ mod test {
             ["log-dir", "log-dir-path"],
             ["update", "None"],
             ["update-only", "None"],
+            ["segment-size-threshold", "1"],
+            ["max-in-flight-ops", "1"],
+            ["operator-whitelist", "[\"abc\"]"],
+            ["min-free-space", "1048576"],
+            ["admin-address", "127.0.0.1:5000"],
+            ["watchdog-interval", "30"],
+            ["watchdog-abort", "None"],
+            ["idempotent-unpub-put", "None"],
+            ["max-holders-per-op", "4"],
+            ["metrics-log-interval", "60"],
+            ["advertised-address", "203.0.113.1:5483"],
+            ["load-retries", "5"],
+            ["network-name", "testnet"],
+            ["max-action-chain", "16"],
+            ["read-only", "None"],
+            ["trim-strategy", "least-reliable"],
+            ["reconcile-metadata", "None"],
+            ["max-rpc-size", "1048576"],
         ];
 
         for arg in &ARGS {
@@ -393,6 +1007,24 @@ mod test {
                 log_dir: None,
                 update: false,
                 update_only: false,
+                segment_size_threshold: None,
+                max_in_flight_ops: None,
+                operator_whitelist: Default::default(),
+                min_free_space: None,
+                admin_address: None,
+                watchdog_interval: None,
+                watchdog_abort: false,
+                idempotent_unpub_put: false,
+                max_holders_per_op: None,
+                metrics_log_interval: None,
+                advertised_address: None,
+                load_retries: None,
+                network_name: None,
+                max_action_chain: None,
+                read_only: false,
+                trim_strategy: None,
+                reconcile_metadata: false,
+                max_rpc_size: None,
             };
             let empty_config = config.clone();
             if let Some(val) = matches.value_of(arg) {
@@ -404,6 +1036,68 @@ mod test {
         }
     }
 
+    #[test]
+    fn set_from_env() {
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut config = Config::default();
+        unwrap!(config.set_from_env(vars(&[
+            ("SAFE_VAULT_MAX_CAPACITY", "1024"),
+            ("SAFE_VAULT_ROOT_DIR", "/data"),
+            ("SAFE_VAULT_FIRST", "true"),
+            ("SAFE_VAULT_OPERATOR_WHITELIST", "[\"token\"]"),
+            ("PATH", "/bin"),
+        ])));
+        assert_eq!(config.max_capacity(), 1024);
+        assert_eq!(config.root_dir, Some(Path::new("/data").to_path_buf()));
+        assert!(config.is_first());
+        assert_eq!(config.operator_whitelist, vec!["token".to_string()]);
+
+        let mut config = Config::default();
+        match config.set_from_env(vars(&[("SAFE_VAULT_MAX_CAPACITY", "lots")])) {
+            Err(Error::InvalidConfig(message)) => {
+                assert!(message.contains("SAFE_VAULT_MAX_CAPACITY"))
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match config.set_from_env(vars(&[("SAFE_VAULT_MAX_CAPACTY", "1024")])) {
+            Err(Error::InvalidConfig(message)) => {
+                assert!(message.contains("SAFE_VAULT_MAX_CAPACTY"))
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn advertised_address_must_be_routable() {
+        let mut config = Config::default();
+        assert_eq!(unwrap!(config.advertised_address()), None);
+
+        let external: SocketAddr = unwrap!("203.0.113.1:5483".parse());
+        config.set_advertised_address(Some(external));
+        assert_eq!(unwrap!(config.advertised_address()), Some(external));
+
+        for address in &[
+            "0.0.0.0:5483",
+            "127.0.0.1:5483",
+            "224.0.0.1:5483",
+            "255.255.255.255:5483",
+            "[::1]:5483",
+            "203.0.113.1:0",
+        ] {
+            config.set_advertised_address(Some(unwrap!(address.parse())));
+            match config.advertised_address() {
+                Err(Error::InvalidConfig(_)) => (),
+                result => panic!("Unexpected result for {}: {:?}", address, result),
+            }
+        }
+    }
+
     #[ignore]
     #[test]
     fn parse_sample_config_file() {
@@ -7,16 +7,26 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 mod adata_handler;
+mod holder_scores;
 mod idata_handler;
 mod idata_holder;
 mod idata_op;
 mod mdata_handler;
+mod seen_messages;
 
-use crate::{action::Action, rpc::Rpc, vault::Init, Config, Result};
+#[cfg(feature = "audit")]
+use crate::audit::AuditEvent;
+use crate::{
+    action::Action, client_handler::COST_OF_PUT, op_latency::OpLatencies, rpc::Rpc,
+    self_test::SelfTestReport, storage_check::StorageReport, utils, vault::Init, Config, Result,
+};
 use adata_handler::ADataHandler;
+#[cfg(feature = "audit")]
+use crossbeam_channel::Sender;
 use idata_handler::IDataHandler;
 use idata_holder::IDataHolder;
-use idata_op::{IDataOp, IDataRequest, OpType};
+pub use idata_op::OpType;
+use idata_op::{IDataOp, IDataRequest};
 use log::{error, trace};
 use mdata_handler::MDataHandler;
 
@@ -24,8 +34,11 @@ use safe_nd::{IData, IDataAddress, MessageId, NodePublicId, PublicId, Request, R
 
 use std::{
     cell::Cell,
+    collections::BTreeMap,
     fmt::{self, Display, Formatter},
+    path::Path,
     rc::Rc,
+    time::Duration,
 };
 
 pub(crate) struct DataHandler {
@@ -56,6 +69,175 @@ impl DataHandler {
         })
     }
 
+    /// Applies the tunables from a reloaded `config`.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.idata_handler.apply_config(config);
+        self.idata_holder.apply_config(config);
+        self.mdata_handler.apply_config(config);
+        self.adata_handler.apply_config(config);
+    }
+
+    /// Returns the current reliability score of each ImmutableData holder we've heard from.
+    pub fn holder_scores(&self) -> BTreeMap<XorName, i64> {
+        self.idata_handler.holder_scores()
+    }
+
+    /// Cancels all in-flight ImmutableData ops, returning the responses which tell their clients to
+    /// retry later.  Puts are refunded.
+    pub fn cancel_pending_ops(&mut self) -> Vec<Action> {
+        self.idata_handler
+            .drain_pending_ops()
+            .into_iter()
+            .map(|(message_id, idata_op)| {
+                let refund = if idata_op.op_type() == OpType::Put {
+                    Some(COST_OF_PUT)
+                } else {
+                    None
+                };
+                Action::RespondToClientHandlers {
+                    sender: *idata_op.address().name(),
+                    rpc: Rpc::Response {
+                        requester: idata_op.client().clone(),
+                        response: idata_op
+                            .request()
+                            .error_response(utils::section_busy_error()),
+                        message_id,
+                        refund,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Cancels any in-flight ImmutableData gets for `client`, e.g. once it has disconnected and
+    /// can no longer be sent the result.
+    pub fn cancel_idata_gets_for(&mut self, client: &PublicId) -> usize {
+        self.idata_handler.cancel_gets_for(client)
+    }
+
+    /// Returns the number of ImmutableData ops currently in flight.
+    pub fn in_flight_ops(&self) -> usize {
+        self.idata_handler.in_flight_ops()
+    }
+
+    /// Returns the latencies of the ImmutableData ops which have concluded.
+    pub fn idata_op_latencies(&self) -> &OpLatencies {
+        self.idata_handler.op_latencies()
+    }
+
+    /// Returns the number of ImmutableData responses rejected from each node which wasn't queried
+    /// for the op they claimed to answer.
+    pub fn unexpected_idata_responses(&self) -> BTreeMap<XorName, u64> {
+        self.idata_handler.unexpected_responses().clone()
+    }
+
+    /// Returns the number of ImmutableData get responses received after another holder's had
+    /// already answered the client.
+    pub fn redundant_idata_responses(&self) -> u64 {
+        self.idata_handler.redundant_get_responses()
+    }
+
+    /// Sets a channel on which each client's ImmutableData op is reported as it concludes.
+    #[cfg(feature = "audit")]
+    pub fn set_audit_sink(&mut self, sink: Sender<AuditEvent>) {
+        self.idata_handler.set_audit_sink(sink)
+    }
+
+    /// Returns how long the oldest ImmutableData op in flight has been running, if any are.
+    pub fn oldest_idata_op_age(&self) -> Option<Duration> {
+        self.idata_handler.oldest_op_age()
+    }
+
+    /// Returns whether the ImmutableData metadata db can still be found on disk.
+    pub fn is_idata_metadata_reachable(&self) -> bool {
+        self.idata_handler.is_metadata_reachable()
+    }
+
+    /// Returns the space used by stored chunks.
+    pub fn used_space(&self) -> u64 {
+        self.idata_holder.used_space()
+    }
+
+    /// Round-trips `data` through our ImmutableData store.
+    pub fn self_test(&mut self, data: &IData) -> SelfTestReport {
+        self.idata_holder.self_test(data)
+    }
+
+    /// Returns the number of ImmutableData chunks we hold.
+    pub fn held_idata_count(&self) -> usize {
+        self.idata_holder.held_count()
+    }
+
+    /// Gets the ImmutableData chunk at `address` from `holder` alone, on behalf of `requester`.
+    pub fn get_idata_from_holder(
+        &mut self,
+        requester: PublicId,
+        address: IDataAddress,
+        holder: XorName,
+        message_id: MessageId,
+    ) -> Option<Action> {
+        self.idata_handler
+            .handle_get_idata_from_holder_req(requester, address, holder, message_id)
+    }
+
+    /// Writes the ImmutableData chunk metadata to `path`.  See `import_idata_metadata`.
+    pub fn export_idata_metadata(&self, path: &Path) -> Result<usize> {
+        self.idata_handler.export_metadata(path)
+    }
+
+    /// Merges ImmutableData chunk metadata previously exported to `path` into ours.
+    pub fn import_idata_metadata(&mut self, path: &Path) -> Result<usize> {
+        self.idata_handler.import_metadata(path)
+    }
+
+    /// Removes the ImmutableData chunk metadata for `address`, returning whether the chunk was
+    /// recoverable from any of its holders.
+    pub fn purge_idata_metadata(&mut self, address: IDataAddress) -> Result<bool> {
+        self.idata_handler.purge_metadata(address)
+    }
+
+    /// Pins the ImmutableData chunk at `address`, so that it's never trimmed.
+    pub fn pin_idata(&mut self, address: IDataAddress) -> Result<()> {
+        self.idata_handler.pin(address)
+    }
+
+    /// Unpins the ImmutableData chunk at `address`.
+    pub fn unpin_idata(&mut self, address: IDataAddress) -> Result<()> {
+        self.idata_handler.unpin(address)
+    }
+
+    /// Starts trimming over-replicated chunks and re-verifying the chunks we hold, unless already
+    /// doing so.
+    pub fn start_storage_scan(&mut self) {
+        self.idata_handler.start_trim_scan();
+        self.idata_holder.start_storage_scan()
+    }
+
+    /// Trims or re-verifies up to `max_chunks` more chunks, returning the discards to send to the
+    /// holders dropped from trimmed chunks, and the report once all chunks have been checked.
+    pub fn continue_storage_scan(
+        &mut self,
+        max_chunks: usize,
+    ) -> (Vec<Action>, Option<StorageReport>) {
+        if self.idata_handler.is_trim_scan_running() {
+            return (self.idata_handler.continue_trim_scan(max_chunks), None);
+        }
+        let report = self
+            .idata_holder
+            .continue_storage_scan(max_chunks)
+            .map(|report| StorageReport {
+                trimmed: self.idata_handler.take_trimmed_count(),
+                ..report
+            });
+        (Vec::new(), report)
+    }
+
+    /// Discards segmented transfers to our holder which have stalled, returning the failed put
+    /// responses for their ops.
+    pub fn remove_stale_reassemblies(&mut self) -> Vec<Action> {
+        self.idata_holder.remove_stale_reassemblies()
+    }
+
     pub fn handle_vault_rpc(&mut self, src: XorName, rpc: Rpc) -> Option<Action> {
         match rpc {
             Rpc::Request {
@@ -68,6 +250,38 @@ impl DataHandler {
                 message_id,
                 ..
             } => self.handle_response(src, response, message_id),
+            Rpc::PutIDataResponse {
+                result,
+                message_id,
+                used_space,
+                max_capacity,
+                ..
+            } => {
+                self.idata_handler
+                    .update_holder_capacity(src, used_space, max_capacity);
+                self.idata_handler
+                    .handle_mutation_resp(src, result, message_id)
+            }
+            Rpc::PutIDataSegment {
+                address,
+                requester,
+                message_id,
+                index,
+                count,
+                bytes,
+            } => self
+                .idata_holder
+                .store_idata_segment(address, requester, message_id, index, count, bytes),
+            Rpc::DiscardIData {
+                address,
+                requester,
+                message_id,
+            } => self
+                .idata_holder
+                .discard_idata(address, requester, message_id),
+            Rpc::DiscardIDataResponse { message_id, .. } => self
+                .idata_handler
+                .handle_discard_idata_resp(src, message_id),
         }
     }
 
@@ -43,15 +43,22 @@ impl ADataHandler {
     ) -> Result<Self> {
         let root_dir = config.root_dir()?;
         let max_capacity = config.max_capacity();
-        let chunks = AppendOnlyChunkStore::new(
+        let mut chunks = AppendOnlyChunkStore::new(
             &root_dir,
             max_capacity,
             Rc::clone(total_used_space),
             init_mode,
         )?;
+        chunks.set_min_free_space(config.min_free_space());
         Ok(Self { id, chunks })
     }
 
+    /// Applies the storage limits from a reloaded `config`.
+    pub(super) fn apply_config(&mut self, config: &Config) {
+        self.chunks.set_max_capacity(config.max_capacity());
+        self.chunks.set_min_free_space(config.min_free_space());
+    }
+
     pub(super) fn handle_put_adata_req(
         &mut self,
         requester: PublicId,
@@ -61,9 +68,7 @@ impl ADataHandler {
         let result = if self.chunks.has(data.address()) {
             Err(NdError::DataExists)
         } else {
-            self.chunks
-                .put(&data)
-                .map_err(|error| error.to_string().into())
+            self.chunks.put(&data).map_err(utils::chunk_store_put_error)
         };
         let refund = utils::get_refund_for_put(&result);
         Some(Action::RespondToClientHandlers {
@@ -528,7 +533,7 @@ impl ADataHandler {
             .and_then(move |adata| {
                 self.chunks
                     .put(&adata)
-                    .map_err(|error| error.to_string().into())
+                    .map_err(utils::chunk_store_put_error)
             });
         let refund = utils::get_refund_for_put(&result);
         Some(Action::RespondToClientHandlers {
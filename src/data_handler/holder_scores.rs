@@ -0,0 +1,196 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    utils::{self, Instant},
+    vault::Init,
+    Result, ToDbKey,
+};
+use log::{trace, warn};
+use pickledb::PickleDb;
+use safe_nd::XorName;
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+const HOLDER_SCORES_DB_NAME: &str = "holder_scores.db";
+const MAX_SCORE: i64 = 100;
+const MIN_SCORE: i64 = -100;
+// Amount a holder's score is raised by for each successful response.
+const SUCCESS_REWARD: i64 = 1;
+// Amount a holder's score is lowered by for each failed response.
+const FAILURE_PENALTY: i64 = 10;
+// Holders scoring at or below this are only chosen once all healthier holders have been used.
+const UNHEALTHY_SCORE: i64 = -20;
+// Every score moves one step back towards zero for each elapsed interval.
+const DECAY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Per-holder reliability scores, raised when a holder responds successfully and lowered when it
+/// fails.
+pub(super) struct HolderScoresDb {
+    db: PickleDb,
+    last_decay: Instant,
+}
+
+impl HolderScoresDb {
+    pub fn new<R: AsRef<Path>>(root_dir: R, init_mode: Init, load_retries: u32) -> Result<Self> {
+        Ok(Self {
+            db: utils::new_db(root_dir, HOLDER_SCORES_DB_NAME, init_mode, load_retries)?,
+            last_decay: Instant::now(),
+        })
+    }
+
+    /// Returns the holder's score, which is zero for holders we've not heard from.
+    pub fn score(&self, holder: &XorName) -> i64 {
+        self.db.get(&holder.to_db_key()).unwrap_or(0)
+    }
+
+    /// Returns whether the holder's score is high enough for it to be preferred as a holder.
+    pub fn is_healthy(&self, holder: &XorName) -> bool {
+        self.score(holder) > UNHEALTHY_SCORE
+    }
+
+    pub fn record_success(&mut self, holder: &XorName) {
+        self.adjust(holder, SUCCESS_REWARD)
+    }
+
+    pub fn record_failure(&mut self, holder: &XorName) {
+        self.adjust(holder, -FAILURE_PENALTY)
+    }
+
+    /// Returns the scores of all holders we've heard from.
+    pub fn all(&self) -> BTreeMap<XorName, i64> {
+        self.db
+            .get_all()
+            .into_iter()
+            .filter_map(|key| {
+                let holder = base64::decode(&key)
+                    .ok()
+                    .and_then(|key| bincode::deserialize::<XorName>(&key).ok())?;
+                let score = self.db.get::<i64>(&key)?;
+                Some((holder, score))
+            })
+            .collect()
+    }
+
+    /// Moves every score one step back towards zero for each `DECAY_INTERVAL` elapsed since the
+    /// last decay, so that a recovered holder regains eligibility over time.
+    pub fn decay(&mut self) {
+        let intervals = self.last_decay.elapsed().as_secs() / DECAY_INTERVAL.as_secs();
+        if intervals == 0 {
+            return;
+        }
+        // Carry over the part of an interval which has already elapsed.
+        self.last_decay += DECAY_INTERVAL * intervals as u32;
+
+        let steps = intervals as i64;
+        for (holder, score) in self.all() {
+            let decayed = if score > 0 {
+                (score - steps).max(0)
+            } else {
+                (score + steps).min(0)
+            };
+            if decayed != score {
+                self.set(&holder, decayed);
+            }
+        }
+    }
+
+    fn adjust(&mut self, holder: &XorName, delta: i64) {
+        self.decay();
+        let score = (self.score(holder) + delta).max(MIN_SCORE).min(MAX_SCORE);
+        trace!("Setting reliability score of {} to {}", holder, score);
+        self.set(holder, score);
+    }
+
+    fn set(&mut self, holder: &XorName, score: i64) {
+        if let Err(error) = self.db.set(&holder.to_db_key(), &score) {
+            warn!(
+                "Failed to write reliability score of {} to DB: {:?}",
+                holder, error
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock_base"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use tempdir::TempDir;
+    use unwrap::unwrap;
+
+    fn advance_intervals(intervals: f64) {
+        FakeClock::advance_time((DECAY_INTERVAL.as_millis() as f64 * intervals) as u64);
+    }
+
+    #[test]
+    fn scores_are_clamped() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut scores = unwrap!(HolderScoresDb::new(root.path(), Init::New, 0));
+        let holder = XorName([1; 32]);
+
+        for _ in 0..2 * MAX_SCORE {
+            scores.record_success(&holder);
+        }
+        assert_eq!(scores.score(&holder), MAX_SCORE);
+
+        for _ in 0..2 * MIN_SCORE.abs() / FAILURE_PENALTY {
+            scores.record_failure(&holder);
+        }
+        assert_eq!(scores.score(&holder), MIN_SCORE);
+    }
+
+    #[test]
+    fn holder_is_unhealthy_once_its_score_falls_to_threshold() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut scores = unwrap!(HolderScoresDb::new(root.path(), Init::New, 0));
+        let holder = XorName([1; 32]);
+        assert!(scores.is_healthy(&holder));
+
+        let mut failures = 0;
+        while scores.score(&holder) - FAILURE_PENALTY > UNHEALTHY_SCORE {
+            scores.record_failure(&holder);
+            failures += 1;
+        }
+        assert!(scores.is_healthy(&holder));
+        scores.record_failure(&holder);
+        assert!(!scores.is_healthy(&holder));
+        assert_eq!(scores.score(&holder), -FAILURE_PENALTY * (failures + 1));
+        assert!(scores.is_healthy(&XorName([2; 32])));
+    }
+
+    #[test]
+    fn scores_decay_towards_zero() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut scores = unwrap!(HolderScoresDb::new(root.path(), Init::New, 0));
+        let good = XorName([1; 32]);
+        let bad = XorName([2; 32]);
+        for _ in 0..3 {
+            scores.record_success(&good);
+            scores.record_failure(&bad);
+        }
+        assert_eq!(scores.score(&good), 3 * SUCCESS_REWARD);
+        assert_eq!(scores.score(&bad), -3 * FAILURE_PENALTY);
+
+        advance_intervals(1.5);
+        scores.decay();
+        assert_eq!(scores.score(&good), 3 * SUCCESS_REWARD - 1);
+        assert_eq!(scores.score(&bad), -3 * FAILURE_PENALTY + 1);
+
+        // The half interval left over counts towards the next step.
+        advance_intervals(0.5);
+        scores.decay();
+        assert_eq!(scores.score(&good), 3 * SUCCESS_REWARD - 2);
+        assert_eq!(scores.score(&bad), -3 * FAILURE_PENALTY + 2);
+
+        // A score stops at zero, rather than crossing it.
+        advance_intervals(5.0);
+        scores.decay();
+        assert_eq!(scores.score(&good), 0);
+        assert_eq!(scores.score(&bad), -3 * FAILURE_PENALTY + 7);
+    }
+}
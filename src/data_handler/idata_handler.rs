@@ -6,53 +6,175 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{IDataOp, IDataRequest, OpType};
-use crate::{action::Action, rpc::Rpc, utils, vault::Init, Config, Result, ToDbKey};
-use log::{trace, warn};
+use super::{
+    holder_scores::HolderScoresDb, idata_holder::MAX_REASSEMBLY_BYTES, idata_op::RpcState,
+    seen_messages::SeenMessages, IDataOp, IDataRequest, OpType,
+};
+#[cfg(feature = "audit")]
+use crate::audit::AuditEvent;
+use crate::{
+    action::Action,
+    op_latency::OpLatencies,
+    rpc::Rpc,
+    utils::{self, Instant},
+    vault::Init,
+    Config, Result, ToDbKey, TrimStrategy,
+};
+#[cfg(feature = "audit")]
+use crossbeam_channel::Sender;
+use log::{info, warn};
 use pickledb::PickleDb;
 use safe_nd::{
-    Error as NdError, IData, IDataAddress, MessageId, NodePublicId, PublicId, Response,
+    Error as NdError, IData, IDataAddress, MessageId, NodePublicId, PublicId, Request, Response,
     Result as NdResult, XorName,
 };
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "audit")]
+use std::time::SystemTime;
 use std::{
+    cmp::Ordering,
     collections::{btree_map::Entry, BTreeMap, BTreeSet},
     fmt::{self, Display, Formatter},
-    iter,
+    fs, iter, mem,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 const IMMUTABLE_META_DB_NAME: &str = "immutable_data.db";
 const FULL_ADULTS_DB_NAME: &str = "full_adults.db";
 // The number of separate copies of an ImmutableData chunk which should be maintained.
 const IMMUTABLE_DATA_COPY_COUNT: usize = 3;
+// The percentage of its capacity a holder can use before it's treated as full.
+const FULL_HOLDER_PERCENTAGE: u64 = 90;
+// The maximum number of concluded ops' message IDs to remember for replay protection.
+const SEEN_MESSAGES_CAPACITY: usize = 10_000;
+// How long a concluded op's message ID is remembered for replay protection.
+const SEEN_MESSAGES_EXPIRY: Duration = Duration::from_secs(10 * 60);
+// The largest chunk accepted for storage: no more than a holder could buffer while reassembling it.
+const MAX_IDATA_SIZE: u64 = MAX_REASSEMBLY_BYTES;
 
 #[derive(Default, Serialize, Deserialize)]
 struct ChunkMetadata {
+    // Holders which have confirmed storing the chunk.  Holders a put has only been sent to are
+    // tracked in its op's `rpc_states` until they respond, and only added here on success.
     holders: BTreeSet<XorName>,
+    // Whether the chunk is exempt from trimming, e.g. for network-critical data.
+    pinned: bool,
+}
+
+// A put which failed on some holders, waiting for those which did store the chunk to discard it
+// before the client is told of the failure and refunded.
+struct Rollback {
+    client: PublicId,
+    address: IDataAddress,
+    error: NdError,
+    // Holders which haven't yet confirmed discarding the chunk.
+    pending: BTreeSet<XorName>,
 }
 
 pub(super) struct IDataHandler {
     id: NodePublicId,
     idata_ops: BTreeMap<MessageId, IDataOp>,
+    // When each op in `idata_ops` was created.
+    op_started: BTreeMap<MessageId, Instant>,
+    // Durations of concluded ops.
+    op_latencies: OpLatencies,
+    // Addresses with a put or delete in flight, or a failed put being rolled back.  Further
+    // mutations of them are refused until it concludes, so that updates to their metadata from
+    // different ops can't interleave.
+    locked_addresses: BTreeSet<IDataAddress>,
+    // Failed puts whose chunk is being discarded by the holders which stored it.
+    rollbacks: BTreeMap<MessageId, Rollback>,
+    // New requests are refused while this many ops are in flight.
+    max_in_flight_ops: usize,
+    // Whether an owner re-putting their unpublished data gets success rather than `DataExists`.
+    idempotent_unpub_put: bool,
+    // The most holders a get queries, however many the chunk's metadata lists.
+    max_holders_per_op: usize,
+    // Whether puts and deletes are refused, only gets being served.
+    read_only: bool,
+    // Which holders an over-replicated chunk is deleted from.
+    trim_strategy: TrimStrategy,
+    // Chunks still to be checked for over-replication by the trim scan in progress, if any.
+    trim_scan: Option<Vec<IDataAddress>>,
+    // Number of chunks trimmed since the count was last taken.
+    trimmed_count: usize,
+    // Trims whose dropped holders haven't all yet confirmed discarding the chunk.
+    trims: BTreeMap<MessageId, BTreeSet<XorName>>,
+    // Whether metadata is corrected from the responses to gets.
+    reconcile_metadata: bool,
+    // Chunks which reconciliation dropped a holder from, to be replicated to new holders from the
+    // next copy a get returns.
+    under_replicated: BTreeSet<IDataAddress>,
+    // Replications of chunks to new holders, with the holders which haven't yet responded.
+    replications: BTreeMap<MessageId, (IDataAddress, BTreeSet<XorName>)>,
+    // Message IDs of concluded ops, so that replayed messages aren't handled as new requests.
+    seen_messages: SeenMessages,
     metadata: PickleDb,
-    #[allow(unused)]
+    metadata_path: PathBuf,
+    // Holders which have reported being nearly out of space, with their reported usage.
     full_adults: PickleDb,
+    holder_scores: HolderScoresDb,
+    // Number of responses rejected from each node which wasn't queried for the op they claim to
+    // answer, e.g. a non-holder forging a response for a chunk.
+    unexpected_responses: BTreeMap<XorName, u64>,
+    // Number of get responses received after another holder's had already answered the client.
+    redundant_get_responses: u64,
+    // Where each client op is reported as it concludes, if anywhere.
+    #[cfg(feature = "audit")]
+    audit_sink: Option<Sender<AuditEvent>>,
 }
 
 impl IDataHandler {
     pub(super) fn new(id: NodePublicId, config: &Config, init_mode: Init) -> Result<Self> {
         let root_dir = config.root_dir()?;
-        let metadata = utils::new_db(&root_dir, IMMUTABLE_META_DB_NAME, init_mode)?;
-        let full_adults = utils::new_db(&root_dir, FULL_ADULTS_DB_NAME, init_mode)?;
+        let load_retries = config.load_retries();
+        let metadata = utils::new_db(&root_dir, IMMUTABLE_META_DB_NAME, init_mode, load_retries)?;
+        let full_adults = utils::new_db(&root_dir, FULL_ADULTS_DB_NAME, init_mode, load_retries)?;
+        let holder_scores = HolderScoresDb::new(&root_dir, init_mode, load_retries)?;
 
         Ok(Self {
             id,
             idata_ops: Default::default(),
+            op_started: Default::default(),
+            op_latencies: Default::default(),
+            locked_addresses: Default::default(),
+            rollbacks: Default::default(),
+            max_in_flight_ops: config.max_in_flight_ops(),
+            idempotent_unpub_put: config.idempotent_unpub_put(),
+            max_holders_per_op: config.max_holders_per_op(),
+            read_only: config.read_only(),
+            trim_strategy: config.trim_strategy(),
+            trim_scan: None,
+            trimmed_count: 0,
+            trims: Default::default(),
+            reconcile_metadata: config.reconcile_metadata(),
+            under_replicated: Default::default(),
+            replications: Default::default(),
+            seen_messages: SeenMessages::new(SEEN_MESSAGES_CAPACITY, SEEN_MESSAGES_EXPIRY),
             metadata,
+            metadata_path: root_dir.join(IMMUTABLE_META_DB_NAME),
             full_adults,
+            holder_scores,
+            unexpected_responses: Default::default(),
+            redundant_get_responses: 0,
+            #[cfg(feature = "audit")]
+            audit_sink: None,
         })
     }
 
+    /// Applies the limits on ops, the handling of unpublished re-puts, read-only mode, the trim
+    /// strategy and metadata reconciliation from a reloaded `config`.  Ops already in flight are
+    /// kept even if there are now more than the limit.
+    pub(super) fn apply_config(&mut self, config: &Config) {
+        self.max_in_flight_ops = config.max_in_flight_ops();
+        self.idempotent_unpub_put = config.idempotent_unpub_put();
+        self.max_holders_per_op = config.max_holders_per_op();
+        self.read_only = config.read_only();
+        self.trim_strategy = config.trim_strategy();
+        self.reconcile_metadata = config.reconcile_metadata();
+    }
+
     pub(super) fn handle_put_idata_req(
         &mut self,
         requester: PublicId,
@@ -76,12 +198,45 @@ impl IDataHandler {
             })
         };
 
+        if self.read_only {
+            return respond(Err(utils::read_only_error()));
+        }
+        if !is_valid_size(&data) {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Rejecting Put of {:?} with invalid size",
+                data.address()
+            );
+            return respond(Err(NdError::InvalidOperation));
+        }
+        if self.is_replayed(&message_id) {
+            return respond(Err(NdError::DuplicateMessageId));
+        }
+        if self.is_at_op_capacity() {
+            return respond(Err(utils::section_busy_error()));
+        }
+
         // Does the data already exist?
         if self.metadata.exists(&(*data.address()).to_db_key()) {
             return if data.is_pub() {
-                trace!(
-                    "{}: Replying success for Put {:?}, it already exists.",
+                op_log!(
+                    trace,
+                    self,
+                    message_id,
+                    "Replying success for Put {:?}, it already exists.",
+                    data
+                );
+                respond(Ok(()))
+            } else if self.idempotent_unpub_put && is_owner(&requester, &data) {
+                // The address covers the content and the owner, so this is the same data being put
+                // again by its owner.
+                op_log!(
+                    trace,
                     self,
+                    message_id,
+                    "Replying success for Put {:?} by its owner, it already exists.",
                     data
                 );
                 respond(Ok(()))
@@ -92,12 +247,24 @@ impl IDataHandler {
             };
         }
 
-        let target_holders = self
-            .non_full_adults_sorted(data.name())
-            .chain(self.elders_sorted(data.name()))
-            .take(IMMUTABLE_DATA_COPY_COUNT)
-            .cloned()
-            .collect::<BTreeSet<_>>();
+        // E.g. a retried put, sent with a new message ID before the original concluded, would race
+        // the original to update the chunk's metadata.
+        if self.is_locked(data.address()) {
+            return respond(Err(utils::operation_in_progress_error()));
+        }
+
+        let target_holders = self.make_holder_list_for_idata(data.name());
+        if target_holders.is_empty() {
+            // An op without holders would never conclude.
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "No holders available for {:?}",
+                data.address()
+            );
+            return respond(Err(utils::section_busy_error()));
+        }
         let data_name = *data.name();
         let idata_op = IDataOp::new(
             requester.clone(),
@@ -109,6 +276,8 @@ impl IDataHandler {
             Entry::Occupied(_) => respond(Err(NdError::DuplicateMessageId)),
             Entry::Vacant(vacant_entry) => {
                 let idata_op = vacant_entry.insert(idata_op);
+                let _ = self.op_started.insert(message_id, Instant::now());
+                let _ = self.locked_addresses.insert(idata_op.address());
                 Some(Action::SendToPeers {
                     sender: data_name,
                     targets: target_holders,
@@ -141,6 +310,19 @@ impl IDataHandler {
             })
         };
 
+        if self.read_only {
+            return respond(Err(utils::read_only_error()));
+        }
+        if self.is_replayed(&message_id) {
+            return respond(Err(NdError::DuplicateMessageId));
+        }
+        if self.is_at_op_capacity() {
+            return respond(Err(utils::section_busy_error()));
+        }
+        if self.is_locked(&address) {
+            return respond(Err(utils::operation_in_progress_error()));
+        }
+
         let metadata = match self.get_metadata_for(address) {
             Ok(metadata) => metadata,
             Err(error) => return respond(Err(error)),
@@ -155,6 +337,8 @@ impl IDataHandler {
             Entry::Occupied(_) => respond(Err(NdError::DuplicateMessageId)),
             Entry::Vacant(vacant_entry) => {
                 let idata_op = vacant_entry.insert(idata_op);
+                let _ = self.op_started.insert(message_id, Instant::now());
+                let _ = self.locked_addresses.insert(address);
                 Some(Action::SendToPeers {
                     sender: *address.name(),
                     targets: metadata.holders,
@@ -187,24 +371,111 @@ impl IDataHandler {
             })
         };
 
+        if self.is_replayed(&message_id) {
+            return respond(Err(NdError::DuplicateMessageId));
+        }
+        if self.is_at_op_capacity() {
+            return respond(Err(utils::section_busy_error()));
+        }
+
         // We're acting as data handler, received request from client handlers
         let metadata = match self.get_metadata_for(address) {
             Ok(metadata) => metadata,
             Err(error) => return respond(Err(error)),
         };
 
+        let holders = self.cap_holders(metadata.holders, &message_id);
+        self.send_get_idata_op(requester, address, holders, message_id)
+    }
+
+    /// Gets the chunk at `address` from `holder` alone, so that operators can compare what each
+    /// holder stores.  The holder's response is relayed as is, including any error, without
+    /// falling back to other holders.  Only nodes may request this.
+    pub(super) fn handle_get_idata_from_holder_req(
+        &mut self,
+        requester: PublicId,
+        address: IDataAddress,
+        holder: XorName,
+        message_id: MessageId,
+    ) -> Option<Action> {
+        let client_id = requester.clone();
+        let respond = |result: NdResult<IData>| {
+            Some(Action::RespondToClientHandlers {
+                sender: *address.name(),
+                rpc: Rpc::Response {
+                    requester: client_id,
+                    response: Response::GetIData(result),
+                    message_id,
+                    refund: None,
+                },
+            })
+        };
+
+        if let PublicId::Client(_) | PublicId::App(_) = requester {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Refusing to get {:?} from a single holder for {}",
+                address,
+                requester
+            );
+            return respond(Err(NdError::AccessDenied));
+        }
+        if self.is_replayed(&message_id) {
+            return respond(Err(NdError::DuplicateMessageId));
+        }
+        if self.is_at_op_capacity() {
+            return respond(Err(utils::section_busy_error()));
+        }
+
+        match self.get_metadata_for(address) {
+            Ok(ref metadata) if metadata.holders.contains(&holder) => (),
+            Ok(_) => {
+                op_log!(
+                    info,
+                    self,
+                    message_id,
+                    "{} isn't a holder of {:?}",
+                    holder,
+                    address
+                );
+                return respond(Err(NdError::NoSuchData));
+            }
+            Err(error) => return respond(Err(error)),
+        }
+
+        self.send_get_idata_op(requester, address, iter::once(holder).collect(), message_id)
+    }
+
+    fn send_get_idata_op(
+        &mut self,
+        requester: PublicId,
+        address: IDataAddress,
+        holders: BTreeSet<XorName>,
+        message_id: MessageId,
+    ) -> Option<Action> {
         let idata_op = IDataOp::new(
             requester.clone(),
             IDataRequest::GetIData(address),
-            metadata.holders.clone(),
+            holders.clone(),
         );
         match self.idata_ops.entry(message_id) {
-            Entry::Occupied(_) => respond(Err(NdError::DuplicateMessageId)),
+            Entry::Occupied(_) => Some(Action::RespondToClientHandlers {
+                sender: *address.name(),
+                rpc: Rpc::Response {
+                    requester,
+                    response: Response::GetIData(Err(NdError::DuplicateMessageId)),
+                    message_id,
+                    refund: None,
+                },
+            }),
             Entry::Vacant(vacant_entry) => {
                 let idata_op = vacant_entry.insert(idata_op);
+                let _ = self.op_started.insert(message_id, Instant::now());
                 Some(Action::SendToPeers {
                     sender: *address.name(),
-                    targets: metadata.holders,
+                    targets: holders,
                     rpc: Rpc::Request {
                         request: idata_op.request(),
                         requester,
@@ -221,7 +492,16 @@ impl IDataHandler {
         result: NdResult<()>,
         message_id: MessageId,
     ) -> Option<Action> {
+        if self.replications.contains_key(&message_id) {
+            return self.handle_replication_resp(sender, result, message_id);
+        }
+        if !self.verify_responder(&message_id, &sender) {
+            return None;
+        }
         let own_id = format!("{}", self);
+        if self.is_expected_holder(&message_id, &sender) {
+            self.update_holder_score(&sender, result.as_ref().err());
+        }
         let (idata_address, op_type) = self.idata_op_mut(&message_id).and_then(|idata_op| {
             let op_type = idata_op.op_type();
             idata_op
@@ -240,50 +520,160 @@ impl IDataHandler {
         &mut self,
         idata_address: IDataAddress,
         sender: XorName,
-        _result: &NdResult<()>,
+        result: &NdResult<()>,
         message_id: MessageId,
     ) -> Option<Action> {
-        // TODO -
-        // - if Ok, and this is the final of the three responses send success back to client handlers and
-        //   then on to the client.  Note: there's no functionality in place yet to know whether
-        //   this is the last response or not.
-        // - if Ok, and this is not the last response, just return `None` here.
-        // - if Err, we need to flag this sender as "full" (i.e. add to self.full_adults, try on
-        //   next closest non-full adult, or elder if none.  Also update the metadata for this
-        //   chunk.  Not known yet where we'll get the chunk from to do that.
-        //
-        // For phase 1, we can leave many of these unanswered.
-
-        // TODO - we'll assume `result` is success for phase 1.
+        // TODO - if Err, we need to flag this sender as "full" (i.e. add to self.full_adults, try
+        //        on next closest non-full adult, or elder if none.  Not known yet where we'll get
+        //        the chunk from to do that.  For now, the put fails and is rolled back.
+        if result.is_ok() {
+            self.add_holder(idata_address, sender, message_id);
+        }
+
+        let idata_op = self.remove_idata_op_if_concluded(&message_id)?;
+        let error = match idata_op.get_any_errors().values().next() {
+            Some(error) => error.clone(),
+            None => {
+                self.audit(idata_op.client(), OpType::Put, idata_address, Ok(()));
+                return Some(Action::RespondToClientHandlers {
+                    sender: *idata_address.name(),
+                    rpc: Rpc::Response {
+                        requester: idata_op.client().clone(),
+                        response: Response::Mutation(Ok(())),
+                        message_id,
+                        refund: None,
+                    },
+                });
+            }
+        };
+        self.roll_back_put(idata_op, error, message_id)
+    }
+
+    fn add_holder(&mut self, idata_address: IDataAddress, sender: XorName, message_id: MessageId) {
         let db_key = idata_address.to_db_key();
-        let mut metadata = self
-            .metadata
-            .get::<ChunkMetadata>(&db_key)
-            .unwrap_or_default();
+        let mut metadata = self.load_metadata(&db_key).unwrap_or_default();
         if !metadata.holders.insert(sender) {
-            warn!(
-                "{}: {} already registered as a holder for {:?}",
+            op_log!(
+                warn,
                 self,
+                message_id,
+                "{} already registered as a holder for {:?}",
                 sender,
-                self.idata_op(&message_id)?
+                idata_address
             );
         }
         if let Err(error) = self.metadata.set(&db_key, &metadata) {
-            warn!("{}: Failed to write metadata to DB: {:?}", self, error);
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Failed to write metadata to DB: {:?}",
+                error
+            );
             // TODO - send failure back to client handlers (hopefully won't accumulate), or
             //        maybe self-terminate if we can't fix this error?
         }
+    }
 
-        self.remove_idata_op_if_concluded(&message_id)
-            .map(|idata_op| Action::RespondToClientHandlers {
-                sender: *idata_address.name(),
-                rpc: Rpc::Response {
-                    requester: idata_op.client().clone(),
-                    response: Response::Mutation(Ok(())),
-                    message_id,
-                    refund: None,
-                },
-            })
+    // Forgets the chunk of a put which failed with `error` on some holders, and has any holders
+    // which did store it discard it.  The client is refunded once they all have, so that no
+    // holder is left storing a chunk which wasn't paid for.
+    fn roll_back_put(
+        &mut self,
+        idata_op: IDataOp,
+        error: NdError,
+        message_id: MessageId,
+    ) -> Option<Action> {
+        let address = idata_op.address();
+        if let Err(error) = self.metadata.rem(&address.to_db_key()) {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Failed to delete metadata from DB: {:?}",
+                error
+            );
+        }
+        let stored: BTreeSet<_> = idata_op
+            .rpc_states
+            .iter()
+            .filter(|(_, state)| **state == RpcState::Actioned(None))
+            .map(|(holder, _)| *holder)
+            .collect();
+        let rollback = Rollback {
+            client: idata_op.client().clone(),
+            address,
+            error,
+            pending: stored.clone(),
+        };
+        if stored.is_empty() {
+            self.audit(&rollback.client, OpType::Put, address, Err(&rollback.error));
+            return Some(rollback.into_response(message_id));
+        }
+
+        op_log!(
+            info,
+            self,
+            message_id,
+            "Put of {:?} failed; rolling back on {} holder(s)",
+            address,
+            stored.len()
+        );
+        let requester = rollback.client.clone();
+        let _ = self.locked_addresses.insert(address);
+        let _ = self.rollbacks.insert(message_id, rollback);
+        Some(Action::SendToPeers {
+            sender: *address.name(),
+            targets: stored,
+            rpc: Rpc::DiscardIData {
+                address,
+                requester,
+                message_id,
+            },
+        })
+    }
+
+    pub(super) fn handle_discard_idata_resp(
+        &mut self,
+        sender: XorName,
+        message_id: MessageId,
+    ) -> Option<Action> {
+        if let Some(pending) = self.trims.get_mut(&message_id) {
+            if pending.remove(&sender) && pending.is_empty() {
+                let _ = self.trims.remove(&message_id);
+            }
+            return None;
+        }
+        let rollback = match self.rollbacks.get_mut(&message_id) {
+            Some(rollback) => rollback,
+            None => {
+                op_log!(warn, self, message_id, "No current rollback of a Put");
+                return None;
+            }
+        };
+        if !rollback.pending.remove(&sender) {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Received discard confirmation from {} that we didn't expect.",
+                sender
+            );
+            return None;
+        }
+        if !rollback.pending.is_empty() {
+            return None;
+        }
+
+        let rollback = self.rollbacks.remove(&message_id)?;
+        let _ = self.locked_addresses.remove(&rollback.address);
+        self.audit(
+            &rollback.client,
+            OpType::Put,
+            rollback.address,
+            Err(&rollback.error),
+        );
+        Some(rollback.into_response(message_id))
     }
 
     pub(super) fn handle_delete_unpub_idata_resp(
@@ -296,64 +686,91 @@ impl IDataHandler {
         // TODO - Only rudimentary checks for if requests to Adult nodes were successful. These
         // mostly assume we're in practice only delegating to a single Adult (ourself in phase 1).
         if let Err(err) = result {
-            warn!("{}: Node reports error deleting: {}", self, err);
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Node reports error deleting: {}",
+                err
+            );
         } else {
             let db_key = idata_address.to_db_key();
-            let metadata = self.metadata.get::<ChunkMetadata>(&db_key).or_else(|| {
-                warn!(
-                    "{}: Failed to get metadata from DB: {:?}",
-                    self, idata_address
+            let metadata = self.load_metadata(&db_key).or_else(|| {
+                op_log!(
+                    warn,
+                    self,
+                    message_id,
+                    "Failed to get metadata from DB: {:?}",
+                    idata_address
                 );
                 None
             });
 
             if let Some(mut metadata) = metadata {
                 if !metadata.holders.remove(&sender) {
-                    warn!(
-                        "{}: {} is not registered as a holder for {:?}",
+                    op_log!(
+                        warn,
                         self,
+                        message_id,
+                        "{} is not registered as a holder for {:?}",
                         sender,
                         self.idata_op(&message_id)?
                     );
                 }
                 if metadata.holders.is_empty() {
                     if let Err(error) = self.metadata.rem(&db_key) {
-                        warn!("{}: Failed to delete metadata from DB: {:?}", self, error);
+                        op_log!(
+                            warn,
+                            self,
+                            message_id,
+                            "Failed to delete metadata from DB: {:?}",
+                            error
+                        );
                         // TODO - Send failure back to client handlers?
                     }
                 } else if let Err(error) = self.metadata.set(&db_key, &metadata) {
-                    warn!("{}: Failed to write metadata to DB: {:?}", self, error);
+                    op_log!(
+                        warn,
+                        self,
+                        message_id,
+                        "Failed to write metadata to DB: {:?}",
+                        error
+                    );
                     // TODO - Send failure back to client handlers?
                 }
             };
         }
 
-        self.remove_idata_op_if_concluded(&message_id)
-            .map(|idata_op| {
-                let response = {
-                    let errors_for_req = idata_op.get_any_errors();
-                    assert!(
-                        errors_for_req.len() <= 1,
-                        "Handling more than one response is not implemented."
-                    );
-                    if let Some(response) = errors_for_req.values().next() {
-                        Err(response.clone())
-                    } else {
-                        Ok(())
-                    }
-                };
-                Action::RespondToClientHandlers {
-                    sender: *idata_address.name(),
-                    rpc: Rpc::Response {
-                        requester: idata_op.client().clone(),
-                        response: Response::Mutation(response),
-                        message_id,
-                        // Deleting data is free so, no refund
-                        // This field can be put to use when deletion is incentivised
-                        refund: None,
-                    },
-                }
-            })
+        let idata_op = self.remove_idata_op_if_concluded(&message_id)?;
+        let response = {
+            let errors_for_req = idata_op.get_any_errors();
+            assert!(
+                errors_for_req.len() <= 1,
+                "Handling more than one response is not implemented."
+            );
+            if let Some(response) = errors_for_req.values().next() {
+                Err(response.clone())
+            } else {
+                Ok(())
+            }
+        };
+        self.audit(
+            idata_op.client(),
+            OpType::Delete,
+            idata_address,
+            response.as_ref().map(|_| ()),
+        );
+        Some(Action::RespondToClientHandlers {
+            sender: *idata_address.name(),
+            rpc: Rpc::Response {
+                requester: idata_op.client().clone(),
+                response: Response::Mutation(response),
+                message_id,
+                // Deleting data is free so, no refund
+                // This field can be put to use when deletion is incentivised
+                refund: None,
+            },
+        })
     }
 
     pub(super) fn handle_get_idata_resp(
@@ -362,16 +779,358 @@ impl IDataHandler {
         result: NdResult<IData>,
         message_id: MessageId,
     ) -> Option<Action> {
+        if !self.verify_responder(&message_id, &sender) {
+            return None;
+        }
         let own_id = format!("{}", self);
+        if self.is_expected_holder(&message_id, &sender) {
+            self.update_holder_score(&sender, result.as_ref().err());
+            if self.reconcile_metadata {
+                if let Some(address) = self.idata_ops.get(&message_id).map(IDataOp::address) {
+                    self.reconcile(address, sender, &result, message_id);
+                }
+            }
+        }
+        let replica = match result {
+            Ok(ref data) if self.under_replicated.contains(data.address()) => Some(data.clone()),
+            _ => None,
+        };
+        let already_answered = self
+            .idata_ops
+            .get(&message_id)
+            .map(IDataOp::is_any_actioned)
+            .unwrap_or(false);
+        if already_answered {
+            self.redundant_get_responses += 1;
+        }
         let action = self.idata_op_mut(&message_id).and_then(|idata_op| {
             idata_op.handle_get_idata_resp(sender, result, &own_id, message_id)
         });
+        if let Some(Action::RespondToClientHandlers {
+            rpc:
+                Rpc::Response {
+                    ref requester,
+                    response: Response::GetIData(ref result),
+                    ..
+                },
+            ..
+        }) = action
+        {
+            if let Some(idata_op) = self.idata_ops.get(&message_id) {
+                self.audit(
+                    requester,
+                    OpType::Get,
+                    idata_op.address(),
+                    result.as_ref().map(|_| ()),
+                );
+            }
+        }
         let _ = self.remove_idata_op_if_concluded(&message_id);
-        action
+        // Only one action can be returned, so a chunk returned to the client is replicated from a
+        // later copy instead.
+        match action {
+            Some(action) => Some(action),
+            None => replica.and_then(|data| self.replicate(data)),
+        }
+    }
+
+    // Corrects the metadata of the chunk at `address` from `holder`'s response to a get: listing
+    // the holder if it returned the chunk, or dropping it if it reported not having it.  Chunks
+    // with a put or delete in flight are left alone, as are holders which a trim is dropping.  The
+    // last listed holder is never dropped, so that an operator can still purge the metadata.
+    fn reconcile(
+        &mut self,
+        address: IDataAddress,
+        holder: XorName,
+        result: &NdResult<IData>,
+        message_id: MessageId,
+    ) {
+        if self.locked_addresses.contains(&address)
+            || self.trims.values().any(|dropped| dropped.contains(&holder))
+        {
+            return;
+        }
+        let mut metadata = match self.get_metadata_for(address) {
+            Ok(metadata) => metadata,
+            Err(_) => return,
+        };
+        let listed = metadata.holders.contains(&holder);
+        match result {
+            Ok(_) if !listed => {
+                op_log!(
+                    info,
+                    self,
+                    message_id,
+                    "Listing {} as a holder of {:?}, which it returned",
+                    holder,
+                    address
+                );
+                let _ = metadata.holders.insert(holder);
+            }
+            Err(NdError::NoSuchData) if listed => {
+                if metadata.holders.len() == 1 {
+                    op_log!(
+                        warn,
+                        self,
+                        message_id,
+                        "{}, the only listed holder of {:?}, reports not having it",
+                        holder,
+                        address
+                    );
+                    return;
+                }
+                op_log!(
+                    info,
+                    self,
+                    message_id,
+                    "Dropping {} as a holder of {:?}, which it reports not having",
+                    holder,
+                    address
+                );
+                let _ = metadata.holders.remove(&holder);
+            }
+            _ => return,
+        }
+        if let Err(error) = self.metadata.set(&address.to_db_key(), &metadata) {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Failed to write reconciled metadata to DB: {:?}",
+                error
+            );
+            return;
+        }
+        if listed {
+            let _ = self.under_replicated.insert(address);
+        }
+    }
+
+    // Sends `data` to enough new holders to make up for those its chunk lost to reconciliation.
+    // The chunk stays under-replicated if there are no other holders to choose from.
+    fn replicate(&mut self, data: IData) -> Option<Action> {
+        let address = *data.address();
+        if self.locked_addresses.contains(&address) {
+            return None;
+        }
+        let holders = match self.get_metadata_for(address) {
+            Ok(metadata) => metadata.holders,
+            Err(_) => {
+                let _ = self.under_replicated.remove(&address);
+                return None;
+            }
+        };
+        let missing = IMMUTABLE_DATA_COPY_COUNT.saturating_sub(holders.len());
+        let targets: BTreeSet<_> = self
+            .make_holder_list_for_idata(address.name())
+            .into_iter()
+            .filter(|candidate| !holders.contains(candidate))
+            .take(missing)
+            .collect();
+        if missing > 0 && targets.is_empty() {
+            return None;
+        }
+        let _ = self.under_replicated.remove(&address);
+        if targets.is_empty() {
+            return None;
+        }
+
+        let message_id = MessageId::new();
+        op_log!(
+            info,
+            self,
+            message_id,
+            "Replicating {:?} to {} new holder(s)",
+            address,
+            targets.len()
+        );
+        let _ = self.locked_addresses.insert(address);
+        let _ = self
+            .replications
+            .insert(message_id, (address, targets.clone()));
+        Some(Action::SendToPeers {
+            sender: *address.name(),
+            targets,
+            rpc: Rpc::Request {
+                request: Request::PutIData(data),
+                requester: PublicId::Node(self.id.clone()),
+                message_id,
+            },
+        })
+    }
+
+    // Lists `sender` as a holder of the replicated chunk if it stored it.  The chunk is unlocked
+    // once every new holder has responded.
+    fn handle_replication_resp(
+        &mut self,
+        sender: XorName,
+        result: NdResult<()>,
+        message_id: MessageId,
+    ) -> Option<Action> {
+        let (address, expected, concluded) = {
+            let (address, pending) = self.replications.get_mut(&message_id)?;
+            let expected = pending.remove(&sender);
+            (*address, expected, pending.is_empty())
+        };
+        if !expected {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Received replication response from {} that we didn't expect.",
+                sender
+            );
+            return None;
+        }
+        self.update_holder_score(&sender, result.as_ref().err());
+        match result {
+            Ok(()) => self.add_holder(address, sender, message_id),
+            Err(error) => op_log!(
+                warn,
+                self,
+                message_id,
+                "{} failed to store a replica of {:?}: {}",
+                sender,
+                address,
+                error
+            ),
+        }
+        if concluded {
+            let _ = self.replications.remove(&message_id);
+            let _ = self.locked_addresses.remove(&address);
+        }
+        None
+    }
+
+    /// Sets a channel on which each client op is reported as it concludes, for an audit trail.
+    #[cfg(feature = "audit")]
+    pub(super) fn set_audit_sink(&mut self, sink: Sender<AuditEvent>) {
+        self.audit_sink = Some(sink);
+    }
+
+    // Reports to the audit sink, if any, that `client`'s op on `address` concluded with `result`.
+    #[cfg(feature = "audit")]
+    fn audit(
+        &self,
+        client: &PublicId,
+        op: OpType,
+        address: IDataAddress,
+        result: Result<(), &NdError>,
+    ) {
+        let sink = match self.audit_sink {
+            Some(ref sink) => sink,
+            None => return,
+        };
+        let event = AuditEvent {
+            requester: *client.name(),
+            op,
+            address,
+            result: result.map_err(Clone::clone),
+            timestamp: SystemTime::now(),
+        };
+        // The event loop mustn't block on a slow consumer, so events it isn't keeping up with are
+        // dropped.
+        if let Err(error) = sink.try_send(event) {
+            warn!("{}: Failed to record audit event: {}", self, error);
+        }
+    }
+
+    #[cfg(not(feature = "audit"))]
+    fn audit(
+        &self,
+        _client: &PublicId,
+        _op: OpType,
+        _address: IDataAddress,
+        _result: Result<(), &NdError>,
+    ) {
+    }
+
+    // Drops the holders of the chunk at `address` beyond `IMMUTABLE_DATA_COPY_COUNT`, chosen by
+    // `trim_strategy`, and returns the discard telling them to delete it.  Chunks with an op in
+    // flight are left for a later scan, and pinned chunks are never trimmed.
+    fn trim(&mut self, address: IDataAddress) -> Option<Action> {
+        if self.locked_addresses.contains(&address) {
+            return None;
+        }
+        let mut metadata = self.get_metadata_for(address).ok()?;
+        if metadata.pinned || metadata.holders.len() <= IMMUTABLE_DATA_COPY_COUNT {
+            return None;
+        }
+
+        let mut ranked: Vec<_> = metadata.holders.iter().cloned().collect();
+        match self.trim_strategy {
+            TrimStrategy::Farthest => {
+                ranked.sort_by(|lhs, rhs| cmp_distance(address.name(), lhs, rhs))
+            }
+            TrimStrategy::LeastReliable => ranked.sort_by(|lhs, rhs| {
+                // Most reliable first.
+                self.holder_scores
+                    .score(rhs)
+                    .cmp(&self.holder_scores.score(lhs))
+                    .then_with(|| cmp_distance(address.name(), lhs, rhs))
+            }),
+        }
+        let dropped: BTreeSet<_> = ranked
+            .split_off(IMMUTABLE_DATA_COPY_COUNT)
+            .into_iter()
+            .collect();
+        metadata.holders = ranked.into_iter().collect();
+        if let Err(error) = self.metadata.set(&address.to_db_key(), &metadata) {
+            warn!(
+                "{}: Failed to write trimmed metadata of {:?} to DB: {:?}",
+                self, address, error
+            );
+            return None;
+        }
+
+        let message_id = MessageId::new();
+        op_log!(
+            info,
+            self,
+            message_id,
+            "Trimming {:?} from {} surplus holder(s) by {:?}",
+            address,
+            dropped.len(),
+            self.trim_strategy
+        );
+        let _ = self.trims.insert(message_id, dropped.clone());
+        Some(Action::SendToPeers {
+            sender: *address.name(),
+            targets: dropped,
+            rpc: Rpc::DiscardIData {
+                address,
+                requester: PublicId::Node(self.id.clone()),
+                message_id,
+            },
+        })
+    }
+
+    // Decodes a key of the metadata db to the chunk address it was made from.
+    fn metadata_key_address(&self, key: &str) -> Option<IDataAddress> {
+        let address = base64::decode(key)
+            .ok()
+            .and_then(|key| bincode::deserialize::<IDataAddress>(&key).ok());
+        if address.is_none() {
+            warn!("{}: Skipping invalid metadata key {}", self, key);
+        }
+        address
+    }
+
+    // Reads the metadata stored under `db_key`.  Metadata written before chunks could be pinned
+    // only lists holders, and is read as unpinned.
+    fn load_metadata(&self, db_key: &str) -> Option<ChunkMetadata> {
+        self.metadata.get::<ChunkMetadata>(db_key).or_else(|| {
+            self.metadata
+                .get::<BTreeSet<XorName>>(db_key)
+                .map(|holders| ChunkMetadata {
+                    holders,
+                    pinned: false,
+                })
+        })
     }
 
     fn get_metadata_for(&self, address: IDataAddress) -> NdResult<ChunkMetadata> {
-        match self.metadata.get::<ChunkMetadata>(&address.to_db_key()) {
+        match self.load_metadata(&address.to_db_key()) {
             Some(metadata) => {
                 if metadata.holders.is_empty() {
                     warn!("{}: Metadata holders is empty for: {:?}", self, address);
@@ -389,10 +1148,7 @@ impl IDataHandler {
 
     pub(super) fn idata_op(&self, message_id: &MessageId) -> Option<&IDataOp> {
         self.idata_ops.get(message_id).or_else(|| {
-            warn!(
-                "{}: No current ImmutableData operation for {:?}",
-                self, message_id
-            );
+            op_log!(warn, self, message_id, "No current ImmutableData operation");
             None
         })
     }
@@ -400,9 +1156,11 @@ impl IDataHandler {
     pub(super) fn idata_op_mut(&mut self, message_id: &MessageId) -> Option<&mut IDataOp> {
         let own_id = format!("{}", self);
         self.idata_ops.get_mut(message_id).or_else(|| {
-            warn!(
-                "{}: No current ImmutableData operation for {:?}",
-                own_id, message_id
+            op_log!(
+                warn,
+                own_id,
+                message_id,
+                "No current ImmutableData operation"
             );
             None
         })
@@ -415,11 +1173,378 @@ impl IDataHandler {
             .map(IDataOp::concluded)
             .unwrap_or(false);
         if is_concluded {
-            return self.idata_ops.remove(message_id);
+            self.seen_messages.insert(*message_id);
+            let idata_op = self.idata_ops.remove(message_id)?;
+            op_log!(trace, self, message_id, "Concluded: {}", idata_op.counts());
+            if idata_op.op_type() != OpType::Get {
+                let _ = self.locked_addresses.remove(&idata_op.address());
+            }
+            if let Some(started) = self.op_started.remove(message_id) {
+                let latencies = match idata_op.op_type() {
+                    OpType::Put => &mut self.op_latencies.put,
+                    OpType::Get => &mut self.op_latencies.get,
+                    OpType::Delete => &mut self.op_latencies.delete,
+                };
+                latencies.record(started.elapsed());
+            }
+            return Some(idata_op);
         }
         None
     }
 
+    /// Removes and returns all in-flight ops, e.g. so that their clients can be told to retry when
+    /// we're shutting down.
+    pub(super) fn drain_pending_ops(&mut self) -> Vec<(MessageId, IDataOp)> {
+        self.locked_addresses.clear();
+        self.op_started.clear();
+        mem::replace(&mut self.idata_ops, Default::default())
+            .into_iter()
+            .collect()
+    }
+
+    /// Removes any in-flight gets requested by `client`, returning how many were removed.  Puts and
+    /// deletes are left to complete, as they've already been paid for or change stored data.
+    pub(super) fn cancel_gets_for(&mut self, client: &PublicId) -> usize {
+        let cancelled: Vec<_> = self
+            .idata_ops
+            .iter()
+            .filter(|(_, idata_op)| {
+                idata_op.op_type() == OpType::Get && idata_op.client() == client
+            })
+            .map(|(message_id, _)| *message_id)
+            .collect();
+        for message_id in &cancelled {
+            let _ = self.idata_ops.remove(message_id);
+            let _ = self.op_started.remove(message_id);
+        }
+        let cancelled = cancelled.len();
+        if cancelled > 0 {
+            info!(
+                "{}: Cancelled {} get(s) for disconnected client {:?}",
+                self, cancelled, client
+            );
+        }
+        cancelled
+    }
+
+    /// Returns the number of ops currently in flight.
+    pub(super) fn in_flight_ops(&self) -> usize {
+        self.idata_ops.len()
+    }
+
+    /// Returns the latencies of the ops which have concluded.
+    pub(super) fn op_latencies(&self) -> &OpLatencies {
+        &self.op_latencies
+    }
+
+    /// Returns how long the oldest op in flight has been running, if any are.
+    pub(super) fn oldest_op_age(&self) -> Option<Duration> {
+        self.op_started.values().map(Instant::elapsed).max()
+    }
+
+    /// Returns whether the metadata db's file can still be found, without reading its contents.
+    pub(super) fn is_metadata_reachable(&self) -> bool {
+        fs::metadata(&self.metadata_path).is_ok()
+    }
+
+    /// Returns whether a put or delete of `address` is in flight.
+    fn is_locked(&self, address: &IDataAddress) -> bool {
+        let locked = self.locked_addresses.contains(address);
+        if locked {
+            info!(
+                "{}: Refusing mutation of {:?} while another is in progress",
+                self, address
+            );
+        }
+        locked
+    }
+
+    fn is_at_op_capacity(&self) -> bool {
+        if self.idata_ops.len() >= self.max_in_flight_ops {
+            warn!(
+                "{}: Refusing new request with {} ops in flight",
+                self,
+                self.idata_ops.len()
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns whether `message_id` belongs to an op which has already concluded.
+    fn is_replayed(&mut self, message_id: &MessageId) -> bool {
+        if self.seen_messages.contains(message_id) {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Dropping replayed message for concluded op"
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Writes the metadata of every chunk to `path`, returning the number of chunks written.  Keys
+    /// which don't decode to an address are skipped.
+    pub(super) fn export_metadata(&self, path: &Path) -> Result<usize> {
+        let metadata: BTreeMap<IDataAddress, ChunkMetadata> = self
+            .metadata
+            .get_all()
+            .into_iter()
+            .filter_map(|key| Some((self.metadata_key_address(&key)?, self.load_metadata(&key)?)))
+            .collect();
+        fs::write(path, utils::serialise(&metadata))?;
+        info!(
+            "{}: Exported metadata of {} chunks to {}",
+            self,
+            metadata.len(),
+            path.display()
+        );
+        Ok(metadata.len())
+    }
+
+    /// Merges the chunk metadata previously exported to `path` into ours, adding its holders to
+    /// those we already know of.  Returns the number of chunks read.
+    pub(super) fn import_metadata(&mut self, path: &Path) -> Result<usize> {
+        let imported: BTreeMap<IDataAddress, ChunkMetadata> =
+            bincode::deserialize(&fs::read(path)?)?;
+        for (address, imported) in &imported {
+            let db_key = address.to_db_key();
+            let mut metadata = self.load_metadata(&db_key).unwrap_or_default();
+            metadata.holders.extend(imported.holders.iter().cloned());
+            metadata.pinned |= imported.pinned;
+            self.metadata.set(&db_key, &metadata)?;
+        }
+        info!(
+            "{}: Imported metadata of {} chunks from {}",
+            self,
+            imported.len(),
+            path.display()
+        );
+        Ok(imported.len())
+    }
+
+    /// Removes the metadata of the chunk at `address`, e.g. once its holders have all gone, so that
+    /// it can be put afresh.  Returns whether any of its holders is still one we'd choose to hold
+    /// data, i.e. whether the chunk was recoverable.  Refused while an op on the chunk is in
+    /// flight.
+    pub(super) fn purge_metadata(&mut self, address: IDataAddress) -> Result<bool> {
+        if self.is_locked(&address) {
+            return Err(utils::operation_in_progress_error().into());
+        }
+        let metadata = self.get_metadata_for(address)?;
+        let recoverable = {
+            let mut live = self
+                .elders_sorted(address.name())
+                .chain(self.non_full_adults_sorted(address.name()));
+            live.any(|candidate| metadata.holders.contains(candidate))
+        };
+        let _ = self.metadata.rem(&address.to_db_key())?;
+        warn!(
+            "{}: Purged metadata of {:?}, listing {} holder(s), recoverable: {}",
+            self,
+            address,
+            metadata.holders.len(),
+            recoverable
+        );
+        Ok(recoverable)
+    }
+
+    /// Pins the chunk at `address`, exempting it from trimming until it's unpinned.
+    pub(super) fn pin(&mut self, address: IDataAddress) -> Result<()> {
+        self.set_pinned(address, true)
+    }
+
+    /// Unpins the chunk at `address`, so that it's trimmed like any other.
+    pub(super) fn unpin(&mut self, address: IDataAddress) -> Result<()> {
+        self.set_pinned(address, false)
+    }
+
+    fn set_pinned(&mut self, address: IDataAddress, pinned: bool) -> Result<()> {
+        let mut metadata = self.get_metadata_for(address)?;
+        if metadata.pinned != pinned {
+            metadata.pinned = pinned;
+            self.metadata.set(&address.to_db_key(), &metadata)?;
+            info!(
+                "{}: {} {:?}",
+                self,
+                if pinned { "Pinned" } else { "Unpinned" },
+                address
+            );
+        }
+        Ok(())
+    }
+
+    /// Starts a scan trimming every over-replicated chunk back to `IMMUTABLE_DATA_COPY_COUNT`
+    /// holders, unless one is already in progress.
+    pub(super) fn start_trim_scan(&mut self) {
+        if self.trim_scan.is_none() {
+            let addresses = self
+                .metadata
+                .get_all()
+                .into_iter()
+                .filter_map(|key| self.metadata_key_address(&key))
+                .collect();
+            self.trim_scan = Some(addresses);
+        }
+    }
+
+    /// Returns whether a trim scan is in progress.
+    pub(super) fn is_trim_scan_running(&self) -> bool {
+        self.trim_scan.is_some()
+    }
+
+    /// Checks up to `max_chunks` more chunks for the trim scan in progress, returning the discards
+    /// to send to the holders dropped from any which were over-replicated.  The scan ends once
+    /// every chunk has been checked.
+    pub(super) fn continue_trim_scan(&mut self, max_chunks: usize) -> Vec<Action> {
+        let batch = match self.trim_scan {
+            Some(ref mut remaining) => {
+                remaining.split_off(remaining.len().saturating_sub(max_chunks))
+            }
+            None => return Vec::new(),
+        };
+        let actions: Vec<_> = batch
+            .into_iter()
+            .filter_map(|address| self.trim(address))
+            .collect();
+        self.trimmed_count += actions.len();
+        if self.trim_scan.as_ref().map_or(false, Vec::is_empty) {
+            self.trim_scan = None;
+        }
+        actions
+    }
+
+    /// Returns the number of chunks trimmed since this was last called.
+    pub(super) fn take_trimmed_count(&mut self) -> usize {
+        mem::replace(&mut self.trimmed_count, 0)
+    }
+
+    /// Returns the current reliability score of each holder we've heard from.
+    pub(super) fn holder_scores(&self) -> BTreeMap<XorName, i64> {
+        self.holder_scores.all()
+    }
+
+    /// Records the storage usage reported by `holder`, treating it as full once it's nearly out of
+    /// space so that it's avoided when choosing holders for new chunks.
+    pub(super) fn update_holder_capacity(
+        &mut self,
+        holder: XorName,
+        used_space: u64,
+        max_capacity: u64,
+    ) {
+        let db_key = holder.to_db_key();
+        let is_full =
+            used_space.saturating_mul(100) >= max_capacity.saturating_mul(FULL_HOLDER_PERCENTAGE);
+        let result = if is_full {
+            if !self.full_adults.exists(&db_key) {
+                info!(
+                    "{}: {} is nearly full ({}/{} bytes used)",
+                    self, holder, used_space, max_capacity
+                );
+            }
+            self.full_adults.set(&db_key, &(used_space, max_capacity))
+        } else {
+            self.full_adults.rem(&db_key).map(|_| ())
+        };
+        if let Err(error) = result {
+            warn!("{}: Failed to update full adults DB: {:?}", self, error);
+        }
+    }
+
+    // Returns the holders for a new chunk: the closest non-full adults, falling back to elders.
+    // Holders with a poor reliability score or which are nearly full are only chosen once all
+    // others are used, even if they're closer to the chunk.  This is deliberate: a chunk's holders
+    // are found through its metadata rather than by proximity, so placing a copy further away costs
+    // nothing, whereas placing it with an unreliable holder risks having to replicate it again.
+    fn make_holder_list_for_idata(&mut self, target: &XorName) -> BTreeSet<XorName> {
+        self.holder_scores.decay();
+        let candidates: Vec<_> = self
+            .non_full_adults_sorted(target)
+            .chain(self.elders_sorted(target))
+            .cloned()
+            .collect();
+        preferred_first(candidates, IMMUTABLE_DATA_COPY_COUNT, |holder| {
+            self.holder_scores.is_healthy(holder) && !self.full_adults.exists(&holder.to_db_key())
+        })
+        .into_iter()
+        .collect()
+    }
+
+    // Returns at most `max_holders_per_op` of `holders`, preferring healthy ones, to bound how many
+    // messages a single get sends however many holders a chunk has accrued.
+    fn cap_holders(&self, holders: BTreeSet<XorName>, message_id: &MessageId) -> BTreeSet<XorName> {
+        if holders.len() <= self.max_holders_per_op {
+            return holders;
+        }
+        op_log!(
+            info,
+            self,
+            message_id,
+            "Querying only {} of {} holders",
+            self.max_holders_per_op,
+            holders.len()
+        );
+        preferred_first(holders, self.max_holders_per_op, |holder| {
+            self.holder_scores.is_healthy(holder)
+        })
+        .into_iter()
+        .collect()
+    }
+
+    /// Returns false, counting and logging the response as a potential attack, if the op
+    /// `message_id` is in flight but `sender` (as reported by Routing) isn't one of the holders it
+    /// queried.  Responses to ops which aren't in flight are left to be dropped as usual.
+    fn verify_responder(&mut self, message_id: &MessageId, sender: &XorName) -> bool {
+        if !self.idata_ops.contains_key(message_id) || self.is_expected_holder(message_id, sender) {
+            return true;
+        }
+        let count = self.unexpected_responses.entry(*sender).or_insert(0);
+        *count += 1;
+        let count = *count;
+        op_log!(
+            warn,
+            self,
+            message_id,
+            "Rejecting response from {}, which wasn't queried. Potential spoofing: {} response(s) \
+             rejected from it so far.",
+            sender,
+            count
+        );
+        false
+    }
+
+    /// Returns the number of responses rejected from each node which wasn't queried for the op
+    /// they claimed to answer.
+    pub(super) fn unexpected_responses(&self) -> &BTreeMap<XorName, u64> {
+        &self.unexpected_responses
+    }
+
+    /// Returns the number of get responses received after another holder's had already answered
+    /// the client.
+    pub(super) fn redundant_get_responses(&self) -> u64 {
+        self.redundant_get_responses
+    }
+
+    fn is_expected_holder(&self, message_id: &MessageId, sender: &XorName) -> bool {
+        self.idata_ops
+            .get(message_id)
+            .map(|idata_op| idata_op.rpc_states.contains_key(sender))
+            .unwrap_or(false)
+    }
+
+    fn update_holder_score(&mut self, holder: &XorName, error: Option<&NdError>) {
+        match error {
+            None => self.holder_scores.record_success(holder),
+            // The requester isn't permitted to perform the op, which isn't the holder's fault.
+            Some(NdError::AccessDenied) => (),
+            Some(_) => self.holder_scores.record_failure(holder),
+        }
+    }
+
     // Returns an iterator over all of our section's non-full adults' names, sorted by closest to
     // `target`.
     fn non_full_adults_sorted(&self, _target: &XorName) -> impl Iterator<Item = &XorName> {
@@ -437,3 +1562,609 @@ impl Display for IDataHandler {
         write!(formatter, "{}", self.id.name())
     }
 }
+
+// Orders `lhs` and `rhs` by which is closer to `target`.
+fn cmp_distance(target: &XorName, lhs: &XorName, rhs: &XorName) -> Ordering {
+    let distance = |name: &XorName| {
+        let mut distance = name.0;
+        for (byte, target_byte) in distance.iter_mut().zip(target.0.iter()) {
+            *byte ^= *target_byte;
+        }
+        distance
+    };
+    distance(lhs).cmp(&distance(rhs))
+}
+
+impl Rollback {
+    // Returns the response telling the client that its put failed, with a refund.
+    fn into_response(self, message_id: MessageId) -> Action {
+        let result = Err(self.error);
+        let refund = utils::get_refund_for_put(&result);
+        Action::RespondToClientHandlers {
+            sender: *self.address.name(),
+            rpc: Rpc::Response {
+                requester: self.client,
+                response: Response::Mutation(result),
+                message_id,
+                refund,
+            },
+        }
+    }
+}
+
+// Returns whether `data` is unpublished and owned by the client behind `requester`.
+fn is_owner(requester: &PublicId, data: &IData) -> bool {
+    match (data, utils::owner(requester)) {
+        (IData::Unpub(data), Some(owner)) => data.owner() == owner.public_key(),
+        _ => false,
+    }
+}
+
+// Returns whether `data` is neither empty nor larger than we accept for storage.  Its address is
+// derived from its content, so needn't be checked.
+fn is_valid_size(data: &IData) -> bool {
+    let size = match data {
+        IData::Pub(data) => data.value().len(),
+        IData::Unpub(data) => data.value().len(),
+    } as u64;
+    size > 0 && size <= MAX_IDATA_SIZE
+}
+
+// Returns the first `count` of `holders`, in their original order except that those for which
+// `is_preferred` returns false are moved behind all the others.
+fn preferred_first<I, F>(holders: I, count: usize, is_preferred: F) -> Vec<XorName>
+where
+    I: IntoIterator<Item = XorName>,
+    F: Fn(&XorName) -> bool,
+{
+    let (preferred, others): (Vec<_>, Vec<_>) = holders.into_iter().partition(is_preferred);
+    preferred.into_iter().chain(others).take(count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data_handler::idata_op::RpcStateCounts, Error};
+    use rand::Rng;
+    use safe_nd::{ClientFullId, NodeFullId, PubImmutableData, UnpubImmutableData};
+    use tempdir::TempDir;
+    use unwrap::unwrap;
+
+    // Creates a handler with `config`, rooted in a new temporary directory which must outlive it.
+    fn new_handler(mut config: Config) -> (TempDir, IDataHandler) {
+        let root = unwrap!(TempDir::new("test"));
+        config.set_root_dir(root.path());
+        let id = NodeFullId::new(&mut rand::thread_rng()).public_id().clone();
+        let handler = unwrap!(IDataHandler::new(id, &config, Init::New));
+        (root, handler)
+    }
+
+    // Records `holders` as holding the unpinned chunk at `address`.
+    fn seed_metadata(
+        handler: &mut IDataHandler,
+        address: IDataAddress,
+        holders: impl IntoIterator<Item = XorName>,
+    ) {
+        let metadata = ChunkMetadata {
+            holders: holders.into_iter().collect(),
+            pinned: false,
+        };
+        unwrap!(handler.metadata.set(&address.to_db_key(), &metadata));
+    }
+
+    #[test]
+    fn healthy_holders_are_placed_before_closer_unhealthy_ones() {
+        // Candidates sorted by distance to the chunk, the closest two being unhealthy.
+        let candidates: Vec<_> = (0..6).map(|index| XorName([index; 32])).collect();
+        let unhealthy = [candidates[0], candidates[1]];
+        let is_healthy = |holder: &XorName| !unhealthy.contains(holder);
+
+        assert_eq!(
+            preferred_first(candidates.clone(), 4, is_healthy),
+            candidates[2..6].to_vec()
+        );
+        // Unhealthy holders are still used once the healthy ones run out.
+        assert_eq!(
+            preferred_first(candidates[..4].to_vec(), 3, is_healthy),
+            vec![candidates[2], candidates[3], candidates[0]]
+        );
+    }
+
+    #[test]
+    fn get_queries_at_most_max_holders() {
+        let mut config = Config::default();
+        config.set_max_holders_per_op(Some(4));
+        let (_root, mut handler) = new_handler(config);
+        let id = handler.id.clone();
+
+        // A chunk which has somehow accrued dozens of holders.
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        seed_metadata(
+            &mut handler,
+            *data.address(),
+            (0..40).map(|index| XorName([index; 32])),
+        );
+
+        match handler.handle_get_idata_req(PublicId::Node(id), *data.address(), MessageId::new()) {
+            Some(Action::SendToPeers { targets, .. }) => assert_eq!(targets.len(), 4),
+            action => panic!("Unexpected {:?}", action),
+        }
+    }
+
+    #[test]
+    fn only_first_of_several_get_responses_reaches_client() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let id = handler.id.clone();
+
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let holders: BTreeSet<_> = (0..3).map(|index| XorName([index; 32])).collect();
+        seed_metadata(&mut handler, *data.address(), holders.clone());
+
+        let message_id = MessageId::new();
+        assert!(handler
+            .handle_get_idata_req(PublicId::Node(id), *data.address(), message_id)
+            .is_some());
+
+        let responses: Vec<_> = holders
+            .iter()
+            .map(|holder| handler.handle_get_idata_resp(*holder, Ok(data.clone()), message_id))
+            .collect();
+        match &responses[0] {
+            Some(Action::RespondToClientHandlers {
+                rpc:
+                    Rpc::Response {
+                        response: Response::GetIData(Ok(got)),
+                        ..
+                    },
+                ..
+            }) => assert_eq!(*got, data),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert!(responses[1..].iter().all(Option::is_none));
+        assert!(!handler.idata_ops.contains_key(&message_id));
+        assert_eq!(handler.redundant_get_responses(), 2);
+    }
+
+    #[test]
+    fn get_during_put_only_queries_confirmed_holders() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let id = handler.id.clone();
+
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let address = *data.address();
+        let holders: BTreeSet<_> = (0..3).map(|index| XorName([index; 32])).collect();
+        let put_id = MessageId::new();
+        let _ = handler.idata_ops.insert(
+            put_id,
+            IDataOp::new(
+                PublicId::Node(id.clone()),
+                IDataRequest::PutIData(data),
+                holders,
+            ),
+        );
+        let _ = handler.locked_addresses.insert(address);
+
+        // No holder has confirmed yet, so there's none to query.
+        match handler.handle_get_idata_req(PublicId::Node(id.clone()), address, MessageId::new()) {
+            Some(Action::RespondToClientHandlers {
+                rpc:
+                    Rpc::Response {
+                        response: Response::GetIData(Err(NdError::NoSuchData)),
+                        ..
+                    },
+                ..
+            }) => (),
+            action => panic!("Unexpected {:?}", action),
+        }
+
+        let confirmed = XorName([0; 32]);
+        assert!(handler
+            .handle_mutation_resp(confirmed, Ok(()), put_id)
+            .is_none());
+        match handler.handle_get_idata_req(PublicId::Node(id), address, MessageId::new()) {
+            Some(Action::SendToPeers { targets, .. }) => {
+                assert_eq!(targets, iter::once(confirmed).collect())
+            }
+            action => panic!("Unexpected {:?}", action),
+        }
+    }
+
+    #[test]
+    fn empty_put_is_rejected_and_refunded() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let id = handler.id.clone();
+
+        let data = IData::Pub(PubImmutableData::new(vec![]));
+        match handler.handle_put_idata_req(PublicId::Node(id), data.clone(), MessageId::new()) {
+            Some(Action::RespondToClientHandlers {
+                rpc:
+                    Rpc::Response {
+                        response: Response::Mutation(Err(NdError::InvalidOperation)),
+                        refund: Some(_),
+                        ..
+                    },
+                ..
+            }) => (),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert_eq!(handler.in_flight_ops(), 0);
+        assert!(!handler.metadata.exists(&(*data.address()).to_db_key()));
+    }
+
+    #[test]
+    fn failed_put_is_rolled_back_before_refund() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let id = handler.id.clone();
+
+        // One of three holders stores the chunk, and the other two fail to.
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let address = *data.address();
+        let stored = XorName([1; 32]);
+        let failed = vec![XorName([2; 32]), XorName([3; 32])];
+        let message_id = MessageId::new();
+        let holders = iter::once(stored).chain(failed.iter().cloned()).collect();
+        let _ = handler.idata_ops.insert(
+            message_id,
+            IDataOp::new(PublicId::Node(id), IDataRequest::PutIData(data), holders),
+        );
+        let _ = handler.locked_addresses.insert(address);
+
+        assert!(handler
+            .handle_mutation_resp(stored, Ok(()), message_id)
+            .is_none());
+        assert!(handler
+            .handle_mutation_resp(failed[0], Err(NdError::InsufficientBalance), message_id)
+            .is_none());
+        match handler.handle_mutation_resp(failed[1], Err(NdError::InsufficientBalance), message_id)
+        {
+            Some(Action::SendToPeers {
+                targets,
+                rpc:
+                    Rpc::DiscardIData {
+                        address: discarded, ..
+                    },
+                ..
+            }) => {
+                assert_eq!(targets, iter::once(stored).collect::<BTreeSet<_>>());
+                assert_eq!(discarded, address);
+            }
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert!(!handler.metadata.exists(&address.to_db_key()));
+        assert!(handler.is_locked(&address));
+
+        match handler.handle_discard_idata_resp(stored, message_id) {
+            Some(Action::RespondToClientHandlers {
+                rpc:
+                    Rpc::Response {
+                        response: Response::Mutation(Err(NdError::InsufficientBalance)),
+                        refund: Some(_),
+                        ..
+                    },
+                ..
+            }) => (),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert!(!handler.is_locked(&address));
+    }
+
+    #[test]
+    fn delete_is_refused_while_put_of_same_address_is_in_flight() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let holder = *handler.id.name();
+
+        let client = ClientFullId::new_ed25519(&mut rand::thread_rng());
+        let requester = PublicId::Client(client.public_id().clone());
+        let data = IData::Unpub(UnpubImmutableData::new(
+            vec![1, 2, 3],
+            *client.public_id().public_key(),
+        ));
+        let address = *data.address();
+        let put_id = MessageId::new();
+        match handler.handle_put_idata_req(requester.clone(), data, put_id) {
+            Some(Action::SendToPeers { .. }) => (),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert!(handler.is_locked(&address));
+
+        match handler.handle_delete_unpub_idata_req(requester, address, MessageId::new()) {
+            Some(Action::RespondToClientHandlers {
+                rpc:
+                    Rpc::Response {
+                        response: Response::Mutation(Err(error)),
+                        ..
+                    },
+                ..
+            }) => assert_eq!(error, utils::operation_in_progress_error()),
+            action => panic!("Unexpected {:?}", action),
+        }
+
+        let _ = handler.handle_mutation_resp(holder, Ok(()), put_id);
+        assert_eq!(handler.in_flight_ops(), 0);
+        assert!(!handler.is_locked(&address));
+    }
+
+    #[test]
+    fn response_from_unqueried_node_is_rejected() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let id = handler.id.clone();
+        let holder = *id.name();
+
+        let message_id = MessageId::new();
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        assert!(handler
+            .handle_put_idata_req(PublicId::Node(id), data, message_id)
+            .is_some());
+
+        let impostor = XorName([1; 32]);
+        assert!(handler
+            .handle_mutation_resp(impostor, Ok(()), message_id)
+            .is_none());
+        assert_eq!(handler.unexpected_responses().get(&impostor), Some(&1));
+        let counts = unwrap!(handler.idata_op(&message_id)).counts();
+        assert_eq!(
+            counts,
+            RpcStateCounts {
+                sent: 1,
+                ..Default::default()
+            }
+        );
+
+        // The queried holder's response still concludes the op.
+        assert!(handler
+            .handle_mutation_resp(holder, Ok(()), message_id)
+            .is_some());
+        assert_eq!(handler.in_flight_ops(), 0);
+        assert_eq!(handler.unexpected_responses().get(&holder), None);
+    }
+
+    #[test]
+    fn purging_metadata_reports_whether_chunk_was_recoverable() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let own_name = *handler.id.name();
+
+        // One chunk listing only long-gone holders, and one also listing us.
+        let dead = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let live = IData::Pub(PubImmutableData::new(vec![4, 5, 6]));
+        let gone: BTreeSet<_> = (0..3).map(|index| XorName([index; 32])).collect();
+        seed_metadata(&mut handler, *dead.address(), gone.clone());
+        seed_metadata(
+            &mut handler,
+            *live.address(),
+            gone.into_iter().chain(iter::once(own_name)),
+        );
+
+        assert!(!unwrap!(handler.purge_metadata(*dead.address())));
+        assert!(!handler.metadata.exists(&(*dead.address()).to_db_key()));
+        assert!(unwrap!(handler.purge_metadata(*live.address())));
+        assert!(!handler.metadata.exists(&(*live.address()).to_db_key()));
+
+        // There's nothing left to purge.
+        match handler.purge_metadata(*dead.address()) {
+            Err(Error::NetworkData(NdError::NoSuchData)) => (),
+            result => panic!("Unexpected {:?}", result),
+        }
+    }
+
+    #[cfg(feature = "audit")]
+    #[test]
+    fn put_and_get_are_audited_once_each() {
+        let (_root, mut handler) = new_handler(Config::default());
+        let holder = *handler.id.name();
+        let client = PublicId::Node(handler.id.clone());
+        let (sink, events) = crossbeam_channel::unbounded();
+        handler.set_audit_sink(sink);
+
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let address = *data.address();
+        let message_id = MessageId::new();
+        assert!(handler
+            .handle_put_idata_req(client.clone(), data.clone(), message_id)
+            .is_some());
+        assert!(events.try_recv().is_err());
+        assert!(handler
+            .handle_mutation_resp(holder, Ok(()), message_id)
+            .is_some());
+
+        let message_id = MessageId::new();
+        assert!(handler
+            .handle_get_idata_req(client.clone(), address, message_id)
+            .is_some());
+        assert!(handler
+            .handle_get_idata_resp(holder, Ok(data), message_id)
+            .is_some());
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        for (event, op) in events.iter().zip(&[OpType::Put, OpType::Get]) {
+            assert_eq!(event.requester, *client.name());
+            assert_eq!(event.op, *op);
+            assert_eq!(event.address, address);
+            assert_eq!(event.result, Ok(()));
+        }
+    }
+
+    #[test]
+    fn over_replicated_chunk_is_trimmed_to_target() {
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let address = *data.address();
+        // Six holders, each farther from the chunk than the last.
+        let holders: Vec<_> = (1..=6)
+            .map(|distance| {
+                let mut name = *address.name();
+                name.0[31] ^= distance;
+                name
+            })
+            .collect();
+
+        let trim = |strategy: TrimStrategy, reliable: &[XorName]| {
+            let mut config = Config::default();
+            config.set_trim_strategy(Some(strategy));
+            let (_root, mut handler) = new_handler(config);
+            for holder in reliable {
+                handler.holder_scores.record_success(holder);
+            }
+            seed_metadata(&mut handler, address, holders.iter().cloned());
+
+            handler.start_trim_scan();
+            let mut actions = handler.continue_trim_scan(16);
+            assert!(!handler.is_trim_scan_running());
+            assert_eq!(handler.take_trimmed_count(), 1);
+            assert_eq!(actions.len(), 1);
+            let (dropped, message_id) = match actions.pop() {
+                Some(Action::SendToPeers {
+                    targets,
+                    rpc:
+                        Rpc::DiscardIData {
+                            address: discarded,
+                            message_id,
+                            ..
+                        },
+                    ..
+                }) => {
+                    assert_eq!(discarded, address);
+                    (targets, message_id)
+                }
+                action => panic!("Unexpected {:?}", action),
+            };
+            for holder in &dropped {
+                assert!(handler
+                    .handle_discard_idata_resp(*holder, message_id)
+                    .is_none());
+            }
+            assert!(handler.trims.is_empty());
+
+            let kept = unwrap!(handler.get_metadata_for(address)).holders;
+            assert!(kept.is_disjoint(&dropped));
+            kept
+        };
+
+        let expected = |indices: &[usize]| -> BTreeSet<_> {
+            indices.iter().map(|&index| holders[index]).collect()
+        };
+        assert_eq!(trim(TrimStrategy::Farthest, &[]), expected(&[0, 1, 2]));
+        assert_eq!(
+            trim(TrimStrategy::LeastReliable, &[holders[4], holders[5]]),
+            expected(&[0, 4, 5])
+        );
+    }
+
+    #[test]
+    fn pinned_chunk_is_not_trimmed() {
+        let (_root, mut handler) = new_handler(Config::default());
+
+        // Two over-replicated chunks, their metadata written in the format predating pinning.
+        let pinned = *IData::Pub(PubImmutableData::new(vec![1])).address();
+        let unpinned = *IData::Pub(PubImmutableData::new(vec![2])).address();
+        let holders: BTreeSet<_> = (0..6).map(|index| XorName([index; 32])).collect();
+        for address in &[pinned, unpinned] {
+            unwrap!(handler.metadata.set(&address.to_db_key(), &holders));
+            assert!(!unwrap!(handler.get_metadata_for(*address)).pinned);
+        }
+        unwrap!(handler.pin(pinned));
+        assert!(unwrap!(handler.get_metadata_for(pinned)).pinned);
+
+        handler.start_trim_scan();
+        let actions = handler.continue_trim_scan(16);
+        assert_eq!(handler.take_trimmed_count(), 1);
+        match actions.as_slice() {
+            [Action::SendToPeers {
+                rpc: Rpc::DiscardIData { address, .. },
+                ..
+            }] => assert_eq!(*address, unpinned),
+            actions => panic!("Unexpected {:?}", actions),
+        }
+        assert_eq!(unwrap!(handler.get_metadata_for(pinned)).holders, holders);
+        assert_eq!(
+            unwrap!(handler.get_metadata_for(unpinned)).holders.len(),
+            IMMUTABLE_DATA_COPY_COUNT
+        );
+
+        unwrap!(handler.unpin(pinned));
+        handler.start_trim_scan();
+        let _ = handler.continue_trim_scan(16);
+        assert_eq!(handler.take_trimmed_count(), 1);
+        assert_eq!(
+            unwrap!(handler.get_metadata_for(pinned)).holders.len(),
+            IMMUTABLE_DATA_COPY_COUNT
+        );
+
+        let missing = *IData::Pub(PubImmutableData::new(vec![3])).address();
+        assert!(handler.pin(missing).is_err());
+    }
+
+    #[test]
+    fn holder_without_chunk_is_dropped_and_replaced() {
+        let mut config = Config::default();
+        config.set_reconcile_metadata(true);
+        let (_root, mut handler) = new_handler(config);
+        let own_name = *handler.id.name();
+        let client = PublicId::Node(handler.id.clone());
+
+        let data = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        let address = *data.address();
+        let lost = XorName([1; 32]);
+        let kept = XorName([2; 32]);
+        seed_metadata(&mut handler, address, vec![lost, kept]);
+
+        let message_id = MessageId::new();
+        assert!(handler
+            .handle_get_idata_req(client, address, message_id)
+            .is_some());
+        assert!(handler
+            .handle_get_idata_resp(lost, Err(NdError::NoSuchData), message_id)
+            .is_some());
+        assert_eq!(
+            unwrap!(handler.get_metadata_for(address)).holders,
+            iter::once(kept).collect::<BTreeSet<_>>()
+        );
+
+        // The client has been answered, so the copy returned by the remaining holder replicates
+        // the chunk to us, as the only other candidate.
+        let replication_id = match handler.handle_get_idata_resp(kept, Ok(data.clone()), message_id)
+        {
+            Some(Action::SendToPeers {
+                targets,
+                rpc:
+                    Rpc::Request {
+                        request: Request::PutIData(replica),
+                        message_id,
+                        ..
+                    },
+                ..
+            }) => {
+                assert_eq!(targets, iter::once(own_name).collect::<BTreeSet<_>>());
+                assert_eq!(replica, data);
+                message_id
+            }
+            action => panic!("Unexpected {:?}", action),
+        };
+        assert!(handler.is_locked(&address));
+        assert!(handler
+            .handle_mutation_resp(own_name, Ok(()), replication_id)
+            .is_none());
+        assert!(!handler.is_locked(&address));
+        assert!(handler.replications.is_empty());
+        assert!(handler.under_replicated.is_empty());
+        assert_eq!(
+            unwrap!(handler.get_metadata_for(address)).holders,
+            vec![kept, own_name].into_iter().collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn chunk_metadata_survives_a_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let metadata = ChunkMetadata {
+                holders: (0..rng.gen_range(0, 40))
+                    .map(|_| XorName(rng.gen()))
+                    .collect(),
+                pinned: rng.gen(),
+            };
+            let read = utils::round_trip(&metadata);
+            assert_eq!(read.holders, metadata.holders);
+            assert_eq!(read.pinned, metadata.pinned);
+        }
+    }
+}
@@ -7,21 +7,57 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    action::Action, chunk_store::ImmutableChunkStore, rpc::Rpc, utils, vault::Init, Config, Result,
+    action::Action,
+    chunk_store::ImmutableChunkStore,
+    rpc::Rpc,
+    self_test::SelfTestReport,
+    storage_check::StorageReport,
+    utils::{self, Instant},
+    vault::Init,
+    Config, Result,
 };
-use log::{error, info};
 
-use safe_nd::{Error as NdError, IData, IDataAddress, MessageId, NodePublicId, PublicId, Response};
+use log::warn;
+use safe_nd::{
+    Error as NdError, IData, IDataAddress, MessageId, NodePublicId, PubImmutableData, PublicId,
+    Response, UnpubImmutableData,
+};
 
 use std::{
     cell::Cell,
+    collections::BTreeMap,
     fmt::{self, Display, Formatter},
     rc::Rc,
+    time::Duration,
 };
 
+// How long to wait for all the segments of an ImmutableData chunk before discarding the partial
+// transfer.
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60);
+// Most bytes to buffer across all incomplete transfers.  A segment which would take us past this is
+// rejected as if the section were busy, so the transfer can be retried once others have completed.
+pub(super) const MAX_REASSEMBLY_BYTES: u64 = 64 * 1024 * 1024;
+
+// The segments of an ImmutableData chunk received so far.
+struct Reassembly {
+    requester: PublicId,
+    count: u32,
+    segments: BTreeMap<u32, Vec<u8>>,
+    started: Instant,
+}
+
+impl Reassembly {
+    fn buffered_bytes(&self) -> u64 {
+        self.segments.values().map(|bytes| bytes.len() as u64).sum()
+    }
+}
+
 pub(super) struct IDataHolder {
     id: NodePublicId,
     chunks: ImmutableChunkStore,
+    reassemblies: BTreeMap<(IDataAddress, MessageId), Reassembly>,
+    // Chunks still to be checked by the storage scan in progress, and its findings so far.
+    storage_scan: Option<(Vec<IDataAddress>, StorageReport)>,
 }
 
 impl IDataHolder {
@@ -33,13 +69,25 @@ impl IDataHolder {
     ) -> Result<Self> {
         let root_dir = config.root_dir()?;
         let max_capacity = config.max_capacity();
-        let chunks = ImmutableChunkStore::new(
+        let mut chunks = ImmutableChunkStore::new(
             &root_dir,
             max_capacity,
             Rc::clone(total_used_space),
             init_mode,
         )?;
-        Ok(Self { id, chunks })
+        chunks.set_min_free_space(config.min_free_space());
+        Ok(Self {
+            id,
+            chunks,
+            reassemblies: Default::default(),
+            storage_scan: None,
+        })
+    }
+
+    /// Applies the storage limits from a reloaded `config`.
+    pub(super) fn apply_config(&mut self, config: &Config) {
+        self.chunks.set_max_capacity(config.max_capacity());
+        self.chunks.set_min_free_space(config.min_free_space());
     }
 
     pub(super) fn store_idata(
@@ -49,29 +97,173 @@ impl IDataHolder {
         message_id: MessageId,
     ) -> Option<Action> {
         let result = if self.chunks.has(data.address()) {
-            info!(
-                "{}: Immutable chunk already exists, not storing: {:?}",
+            op_log!(
+                info,
                 self,
+                message_id,
+                "Immutable chunk already exists, not storing: {:?}",
                 data.address()
             );
             Ok(())
         } else {
-            self.chunks
-                .put(&data)
-                .map_err(|error| error.to_string().into())
+            self.chunks.put(&data).map_err(utils::chunk_store_put_error)
         };
+        self.respond_to_put(result, requester, message_id)
+    }
+
+    fn respond_to_put(
+        &self,
+        result: Result<(), NdError>,
+        requester: PublicId,
+        message_id: MessageId,
+    ) -> Option<Action> {
         let refund = utils::get_refund_for_put(&result);
         Some(Action::RespondToOurDataHandlers {
             sender: *self.id.name(),
-            rpc: Rpc::Response {
+            rpc: Rpc::PutIDataResponse {
+                result,
                 requester,
-                response: Response::Mutation(result),
                 message_id,
                 refund,
+                used_space: self.chunks.used_space(),
+                max_capacity: self.chunks.max_capacity(),
+            },
+        })
+    }
+
+    /// Deletes the chunk at `address`, stored for a put which has since failed on other holders,
+    /// and tells the data handlers once it's gone.
+    pub(super) fn discard_idata(
+        &mut self,
+        address: IDataAddress,
+        requester: PublicId,
+        message_id: MessageId,
+    ) -> Option<Action> {
+        if self.chunks.has(&address) {
+            if let Err(error) = self.chunks.delete(&address) {
+                op_log!(
+                    error,
+                    self,
+                    message_id,
+                    "Failed to discard {:?} for failed Put: {}",
+                    address,
+                    error
+                );
+            }
+        }
+        Some(Action::RespondToOurDataHandlers {
+            sender: *self.id.name(),
+            rpc: Rpc::DiscardIDataResponse {
+                requester,
+                message_id,
             },
         })
     }
 
+    /// Buffers a segment of a chunk which is being sent to us for storage.  Once all segments have
+    /// been received, the chunk is reconstructed, verified against `address` and stored.
+    ///
+    /// If buffering the segment would exceed the memory allowed for incomplete transfers, the
+    /// transfer is abandoned and the data handlers told that the section is busy.  An invalid
+    /// segment, or segments which don't form the chunk at `address`, fail the put.
+    pub(super) fn store_idata_segment(
+        &mut self,
+        address: IDataAddress,
+        requester: PublicId,
+        message_id: MessageId,
+        index: u32,
+        count: u32,
+        bytes: Vec<u8>,
+    ) -> Option<Action> {
+        if index >= count {
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Invalid segment {}/{} for {:?}",
+                index,
+                count,
+                address
+            );
+            let _ = self.reassemblies.remove(&(address, message_id));
+            return self.respond_to_put(Err(NdError::InvalidOperation), requester, message_id);
+        }
+
+        let key = (address, message_id);
+        if self.buffered_reassembly_bytes() + bytes.len() as u64 > MAX_REASSEMBLY_BYTES {
+            let discarded = self
+                .reassemblies
+                .remove(&key)
+                .map_or(0, |reassembly| reassembly.buffered_bytes());
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Too much data buffered to accept segment {}/{} for {:?}. Discarding {} bytes \
+                 of the transfer.",
+                index,
+                count,
+                address,
+                discarded
+            );
+            return self.respond_to_put(Err(utils::section_busy_error()), requester, message_id);
+        }
+
+        let reassembly = self.reassemblies.entry(key).or_insert_with(|| Reassembly {
+            requester,
+            count,
+            segments: Default::default(),
+            started: Instant::now(),
+        });
+        if reassembly.count != count {
+            let expected_count = reassembly.count;
+            op_log!(
+                warn,
+                self,
+                message_id,
+                "Segment count {} doesn't match the expected {}. Discarding the transfer.",
+                count,
+                expected_count
+            );
+            let reassembly = self.reassemblies.remove(&key)?;
+            return self.respond_to_put(
+                Err(NdError::InvalidOperation),
+                reassembly.requester,
+                message_id,
+            );
+        }
+        let _ = reassembly.segments.insert(index, bytes);
+        if reassembly.segments.len() < count as usize {
+            return None;
+        }
+
+        let reassembly = self.reassemblies.remove(&key)?;
+        let serialised: Vec<u8> = reassembly
+            .segments
+            .into_iter()
+            .flat_map(|(_, bytes)| bytes)
+            .collect();
+        match bincode::deserialize::<IData>(&serialised) {
+            Ok(ref data) if computed_address(data) == address => {
+                self.store_idata(data, reassembly.requester, message_id)
+            }
+            Ok(_) | Err(_) => {
+                op_log!(
+                    warn,
+                    self,
+                    message_id,
+                    "Reassembled segments don't form the expected chunk {:?}",
+                    address
+                );
+                self.respond_to_put(
+                    Err(NdError::InvalidOperation),
+                    reassembly.requester,
+                    message_id,
+                )
+            }
+        }
+    }
+
     pub(super) fn get_idata(
         &self,
         address: IDataAddress,
@@ -127,9 +319,11 @@ impl IDataHolder {
                     }
                 }
                 _ => {
-                    error!(
-                        "{}: Invalid DeleteUnpub(IData::Pub) encountered: {:?}",
-                        self, message_id
+                    op_log!(
+                        error,
+                        self,
+                        message_id,
+                        "Invalid DeleteUnpub(IData::Pub) encountered"
                     );
                     Err(NdError::InvalidOperation)
                 }
@@ -150,6 +344,131 @@ impl IDataHolder {
             },
         })
     }
+
+    /// Returns the space used by stored chunks.
+    pub(super) fn used_space(&self) -> u64 {
+        self.chunks.used_space()
+    }
+
+    /// Stores `data`, reads it back and verifies it, then deletes it.  The chunk is removed again
+    /// whenever it was stored, even if a later step fails.
+    pub(super) fn self_test(&mut self, data: &IData) -> SelfTestReport {
+        let mut report = SelfTestReport::default();
+        let address = *data.address();
+        if self.chunks.has(&address) {
+            // Never touch a chunk we didn't create.
+            report.run_step("put", || Err("Test chunk already exists".to_string()));
+            return report;
+        }
+
+        let chunks = &mut self.chunks;
+        report.run_step("put", || {
+            chunks.put(data).map_err(|error| error.to_string())
+        });
+        if !chunks.has(&address) {
+            return report;
+        }
+        report.run_step("get", || match chunks.get(&address) {
+            Ok(ref stored) if stored == data => Ok(()),
+            Ok(_) => Err("Retrieved chunk doesn't match".to_string()),
+            Err(error) => Err(error.to_string()),
+        });
+        report.run_step("delete", || {
+            chunks.delete(&address).map_err(|error| error.to_string())
+        });
+        report
+    }
+
+    /// Returns the number of chunks we hold.
+    pub(super) fn held_count(&self) -> usize {
+        self.chunks.keys().len()
+    }
+
+    /// Starts a scan re-verifying every chunk we hold, unless one is already in progress.
+    pub(super) fn start_storage_scan(&mut self) {
+        if self.storage_scan.is_none() {
+            self.storage_scan = Some((self.chunks.keys(), StorageReport::default()));
+        }
+    }
+
+    /// Checks up to `max_chunks` more chunks for the scan in progress.  Returns the report once
+    /// every chunk has been checked, which ends the scan.
+    pub(super) fn continue_storage_scan(&mut self, max_chunks: usize) -> Option<StorageReport> {
+        let (remaining, report) = self.storage_scan.as_mut()?;
+        let batch = remaining.split_off(remaining.len().saturating_sub(max_chunks));
+        for address in batch {
+            if !self.chunks.has(&address) {
+                // Deleted since the scan started.
+                continue;
+            }
+            report.checked += 1;
+            let intact = match self.chunks.get(&address) {
+                Ok(data) => computed_address(&data) == address,
+                Err(_) => false,
+            };
+            if !intact {
+                warn!("{}: Stored chunk {:?} is corrupt", self.id.name(), address);
+                report.corrupt.push(address);
+            }
+        }
+
+        if remaining.is_empty() {
+            self.storage_scan.take().map(|(_, report)| report)
+        } else {
+            None
+        }
+    }
+
+    /// Discards the segments of transfers which haven't completed within `REASSEMBLY_TIMEOUT` of
+    /// their first segment arriving.  Returns a failed put response for each transfer discarded,
+    /// so that the data handlers can conclude its op.
+    pub(super) fn remove_stale_reassemblies(&mut self) -> Vec<Action> {
+        let stale = self
+            .reassemblies
+            .iter()
+            .filter(|(_, reassembly)| reassembly.started.elapsed() > REASSEMBLY_TIMEOUT)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        let mut actions = Vec::new();
+        for (address, message_id) in stale {
+            if let Some(reassembly) = self.reassemblies.remove(&(address, message_id)) {
+                op_log!(
+                    warn,
+                    self,
+                    message_id,
+                    "Discarding incomplete transfer of {:?}: {}/{} segments, {} bytes",
+                    address,
+                    reassembly.segments.len(),
+                    reassembly.count,
+                    reassembly.buffered_bytes()
+                );
+                actions.extend(self.respond_to_put(
+                    Err(utils::section_busy_error()),
+                    reassembly.requester,
+                    message_id,
+                ));
+            }
+        }
+        actions
+    }
+
+    fn buffered_reassembly_bytes(&self) -> u64 {
+        self.reassemblies
+            .values()
+            .map(Reassembly::buffered_bytes)
+            .sum()
+    }
+}
+
+// Returns the address `data` should be stored under, derived from its contents rather than from the
+// address it was deserialised with.
+fn computed_address(data: &IData) -> IDataAddress {
+    match data {
+        IData::Pub(data) => *PubImmutableData::new(data.value().clone()).address(),
+        IData::Unpub(data) => {
+            *UnpubImmutableData::new(data.value().clone(), *data.owner()).address()
+        }
+    }
 }
 
 impl Display for IDataHolder {
@@ -157,3 +476,276 @@ impl Display for IDataHolder {
         write!(formatter, "{}", self.id.name())
     }
 }
+
+#[cfg(all(test, feature = "mock_base"))]
+mod tests {
+    use super::*;
+    use crate::data_handler::idata_handler::IDataHandler;
+    use fake_clock::FakeClock;
+    use safe_nd::{NodeFullId, Request};
+    use tempdir::TempDir;
+    use unwrap::unwrap;
+
+    fn new_holder(root: &TempDir) -> IDataHolder {
+        let mut config = Config::default();
+        config.set_root_dir(root.path());
+        let id = NodeFullId::new(&mut rand::thread_rng()).public_id().clone();
+        unwrap!(IDataHolder::new(
+            id,
+            &config,
+            &Rc::new(Cell::new(0)),
+            Init::New
+        ))
+    }
+
+    #[test]
+    fn stalled_transfer_is_discarded() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut holder = new_holder(&root);
+        let requester = PublicId::Node(holder.id.clone());
+        let data = IData::Pub(PubImmutableData::new(vec![7; 100]));
+
+        // Our data handler, whose only holder is ourself, starts the put.
+        let handler_root = unwrap!(TempDir::new("test"));
+        let mut config = Config::default();
+        config.set_root_dir(handler_root.path());
+        let mut handler = unwrap!(IDataHandler::new(holder.id.clone(), &config, Init::New));
+        let message_id = MessageId::new();
+        let rpc = match handler.handle_put_idata_req(requester, data, message_id) {
+            Some(Action::SendToPeers { rpc, .. }) => rpc,
+            action => panic!("Unexpected {:?}", action),
+        };
+        let mut segments = rpc.into_segments(40);
+        assert_eq!(segments.len(), 3);
+        let last_segment = unwrap!(segments.pop());
+
+        // Send all but the last segment, then stall.
+        for segment in segments {
+            if let Rpc::PutIDataSegment {
+                address,
+                requester,
+                message_id,
+                index,
+                count,
+                bytes,
+            } = segment
+            {
+                assert!(holder
+                    .store_idata_segment(address, requester, message_id, index, count, bytes)
+                    .is_none());
+            }
+        }
+        assert!(holder.remove_stale_reassemblies().is_empty());
+        assert_eq!(holder.buffered_reassembly_bytes(), 80);
+
+        // The data handlers are told the put failed, so that its op concludes.
+        FakeClock::advance_time(REASSEMBLY_TIMEOUT.as_millis() as u64 + 1);
+        let mut actions = holder.remove_stale_reassemblies();
+        assert_eq!(holder.buffered_reassembly_bytes(), 0);
+        let result = match actions.pop() {
+            Some(Action::RespondToOurDataHandlers {
+                rpc: Rpc::PutIDataResponse { result, .. },
+                ..
+            }) => result,
+            action => panic!("Unexpected {:?}", action),
+        };
+        assert!(actions.is_empty());
+        assert_eq!(result, Err(utils::section_busy_error()));
+        match handler.handle_mutation_resp(*holder.id.name(), result, message_id) {
+            Some(Action::RespondToClientHandlers {
+                rpc:
+                    Rpc::Response {
+                        response: Response::Mutation(Err(_)),
+                        refund: Some(_),
+                        ..
+                    },
+                ..
+            }) => (),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert_eq!(handler.in_flight_ops(), 0);
+
+        // The last segment alone no longer completes the transfer.
+        if let Rpc::PutIDataSegment {
+            address,
+            requester,
+            message_id,
+            index,
+            count,
+            bytes,
+        } = last_segment
+        {
+            assert!(holder
+                .store_idata_segment(address, requester, message_id, index, count, bytes)
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn segment_exceeding_buffer_limit_is_rejected() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut holder = new_holder(&root);
+        let requester = PublicId::Node(holder.id.clone());
+        let data = IData::Pub(PubImmutableData::new(vec![7; 100]));
+
+        let action = holder.store_idata_segment(
+            *data.address(),
+            requester,
+            MessageId::new(),
+            0,
+            2,
+            vec![0; MAX_REASSEMBLY_BYTES as usize + 1],
+        );
+        match action {
+            Some(Action::RespondToOurDataHandlers {
+                rpc: Rpc::PutIDataResponse { result, .. },
+                ..
+            }) => assert_eq!(result, Err(utils::section_busy_error())),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert_eq!(holder.buffered_reassembly_bytes(), 0);
+    }
+
+    // Feeds `bytes` to `holder` as a transfer for `address` split into segments of 40 bytes,
+    // returning the action resulting from the last segment.
+    fn store_segments(
+        holder: &mut IDataHolder,
+        address: IDataAddress,
+        bytes: &[u8],
+    ) -> Option<Action> {
+        let requester = PublicId::Node(holder.id.clone());
+        let message_id = MessageId::new();
+        let count = ((bytes.len() + 39) / 40) as u32;
+        let mut action = None;
+        for (index, segment) in bytes.chunks(40).enumerate() {
+            action = holder.store_idata_segment(
+                address,
+                requester.clone(),
+                message_id,
+                index as u32,
+                count,
+                segment.to_vec(),
+            );
+        }
+        action
+    }
+
+    #[test]
+    fn segmented_transfer_is_stored_only_under_its_computed_address() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut holder = new_holder(&root);
+        let requester = PublicId::Node(holder.id.clone());
+        let data = IData::Pub(PubImmutableData::new(vec![7; 100]));
+        let rpc = Rpc::Request {
+            request: Request::PutIData(data.clone()),
+            requester,
+            message_id: MessageId::new(),
+        };
+
+        let mut action = None;
+        for segment in rpc.into_segments(40) {
+            if let Rpc::PutIDataSegment {
+                address,
+                requester,
+                message_id,
+                index,
+                count,
+                bytes,
+            } = segment
+            {
+                action =
+                    holder.store_idata_segment(address, requester, message_id, index, count, bytes);
+            }
+        }
+        match action {
+            Some(Action::RespondToOurDataHandlers {
+                rpc: Rpc::PutIDataResponse { result, .. },
+                ..
+            }) => assert_eq!(result, Ok(())),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert_eq!(unwrap!(holder.chunks.get(data.address())), data);
+
+        // Serialised data claiming another chunk's address, which its contents don't hash to.
+        let other = IData::Pub(PubImmutableData::new(vec![8; 100]));
+        let source = IData::Pub(PubImmutableData::new(vec![9; 100]));
+        let mut forged = utils::serialise(&source);
+        let name = source.address().name().0;
+        let start = unwrap!(forged
+            .windows(name.len())
+            .position(|window| window == &name[..]));
+        forged[start..start + name.len()].copy_from_slice(&other.address().name().0);
+        let forged_data: IData = unwrap!(bincode::deserialize(&forged));
+        assert_eq!(forged_data.address(), other.address());
+
+        match store_segments(&mut holder, *other.address(), &forged) {
+            Some(Action::RespondToOurDataHandlers {
+                rpc: Rpc::PutIDataResponse { result, .. },
+                ..
+            }) => assert_eq!(result, Err(NdError::InvalidOperation)),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert!(!holder.chunks.has(other.address()));
+    }
+
+    #[test]
+    fn invalid_segment_fails_the_put() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut holder = new_holder(&root);
+        let requester = PublicId::Node(holder.id.clone());
+        let address = *IData::Pub(PubImmutableData::new(vec![7; 100])).address();
+        let expect_failure = |action: Option<Action>| match action {
+            Some(Action::RespondToOurDataHandlers {
+                rpc: Rpc::PutIDataResponse { result, .. },
+                ..
+            }) => assert_eq!(result, Err(NdError::InvalidOperation)),
+            action => panic!("Unexpected {:?}", action),
+        };
+
+        // An index outside the transfer.
+        expect_failure(holder.store_idata_segment(
+            address,
+            requester.clone(),
+            MessageId::new(),
+            3,
+            3,
+            vec![0; 10],
+        ));
+
+        // A segment count differing from that of the transfer's earlier segments.
+        let message_id = MessageId::new();
+        assert!(holder
+            .store_idata_segment(address, requester.clone(), message_id, 0, 3, vec![0; 10])
+            .is_none());
+        expect_failure(holder.store_idata_segment(
+            address,
+            requester,
+            message_id,
+            1,
+            2,
+            vec![0; 10],
+        ));
+        assert_eq!(holder.buffered_reassembly_bytes(), 0);
+    }
+
+    #[test]
+    fn discarded_chunk_is_deleted() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut holder = new_holder(&root);
+        let requester = PublicId::Node(holder.id.clone());
+        let data = IData::Pub(PubImmutableData::new(vec![7; 100]));
+        let message_id = MessageId::new();
+        let _ = holder.store_idata(&data, requester.clone(), message_id);
+        assert!(holder.chunks.has(data.address()));
+
+        match holder.discard_idata(*data.address(), requester, message_id) {
+            Some(Action::RespondToOurDataHandlers {
+                rpc: Rpc::DiscardIDataResponse { .. },
+                ..
+            }) => (),
+            action => panic!("Unexpected {:?}", action),
+        }
+        assert!(!holder.chunks.has(data.address()));
+        assert_eq!(holder.used_space(), 0);
+    }
+}
@@ -7,13 +7,15 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{action::Action, rpc::Rpc};
-use log::warn;
 use safe_nd::{
     Error as NdError, IData, IDataAddress, MessageId, PublicId, Request, Response,
     Result as NdResult, XorName,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+};
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize, Debug)]
 pub(crate) enum RpcState {
@@ -28,6 +30,31 @@ pub(crate) enum RpcState {
     TimedOut,
 }
 
+/// The number of holders of an op in each `RpcState`.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub(crate) struct RpcStateCounts {
+    /// Holders which haven't responded yet.
+    pub sent: usize,
+    /// Holders which responded with success.
+    pub actioned_ok: usize,
+    /// Holders which responded with an error.
+    pub actioned_err: usize,
+    /// Holders which left the section without responding.
+    pub gone: usize,
+    /// Holders which didn't respond in time.
+    pub timed_out: usize,
+}
+
+impl Display for RpcStateCounts {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} sent, {} ok, {} failed, {} gone, {} timed out",
+            self.sent, self.actioned_ok, self.actioned_err, self.gone, self.timed_out
+        )
+    }
+}
+
 /// Request type where only ImmutableData requests are allowed.
 // TODO: move to safe-nd?
 #[derive(Hash, Eq, PartialEq, PartialOrd, Ord, Clone, Serialize, Deserialize, Debug)]
@@ -53,9 +80,12 @@ impl Into<Request> for &IDataRequest {
 
 /// The type of ImmutableData operation.
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize, Debug)]
-pub(crate) enum OpType {
+pub enum OpType {
+    /// Put ImmutableData.
     Put,
+    /// Get ImmutableData.
     Get,
+    /// Delete unpublished ImmutableData.
     Delete,
 }
 
@@ -87,6 +117,13 @@ impl IDataOp {
         (&self.request).into()
     }
 
+    pub fn address(&self) -> IDataAddress {
+        match self.request {
+            IDataRequest::PutIData(ref data) => *data.address(),
+            IDataRequest::GetIData(address) | IDataRequest::DeleteUnpubIData(address) => address,
+        }
+    }
+
     pub fn is_any_actioned(&self) -> bool {
         self.rpc_states.values().any(|rpc_state| match rpc_state {
             RpcState::Actioned(_) => true,
@@ -102,6 +139,21 @@ impl IDataOp {
         }
     }
 
+    /// Returns how many holders are in each state.
+    pub fn counts(&self) -> RpcStateCounts {
+        let mut counts = RpcStateCounts::default();
+        for state in self.rpc_states.values() {
+            match state {
+                RpcState::Sent => counts.sent += 1,
+                RpcState::Actioned(None) => counts.actioned_ok += 1,
+                RpcState::Actioned(Some(_)) => counts.actioned_err += 1,
+                RpcState::HolderGone => counts.gone += 1,
+                RpcState::TimedOut => counts.timed_out += 1,
+            }
+        }
+        counts
+    }
+
     /// Returns true if no `rpc_states` are still `RpcState::Sent`.
     pub fn concluded(&self) -> bool {
         !self
@@ -128,14 +180,16 @@ impl IDataOp {
         message_id: MessageId,
     ) -> Option<IDataAddress> {
         if let IDataRequest::GetIData(_) = self.request {
-            warn!(
-                "{}: Expected PutIData or DeleteUnpubIData for {:?}, but found GetIData",
-                own_id, message_id
+            op_log!(
+                warn,
+                own_id,
+                message_id,
+                "Expected PutIData or DeleteUnpubIData, but found GetIData"
             );
             return None;
         }
 
-        self.set_to_actioned(&sender, result.err(), &own_id)?;
+        self.set_to_actioned(&sender, result.err(), &own_id, message_id)?;
 
         match self.request {
             IDataRequest::PutIData(ref data) => Some(*data.address()),
@@ -155,9 +209,12 @@ impl IDataOp {
         let address = if let IDataRequest::GetIData(address) = self.request {
             address
         } else {
-            warn!(
-                "{}: Expected GetIData to correspond to GetIData from {}:",
-                own_id, sender,
+            op_log!(
+                warn,
+                own_id,
+                message_id,
+                "Expected GetIData to correspond to GetIData from {}",
+                sender
             );
             // TODO - Instead of returning None here, take action by treating the vault as
             //        failing.
@@ -165,7 +222,7 @@ impl IDataOp {
         };
 
         let response = Response::GetIData(result.clone());
-        self.set_to_actioned(&sender, result.err(), &own_id)?;
+        self.set_to_actioned(&sender, result.err(), &own_id, message_id)?;
         if is_already_actioned {
             None
         } else {
@@ -186,16 +243,69 @@ impl IDataOp {
         sender: &XorName,
         got_error_response: Option<NdError>,
         own_id: &str,
+        message_id: MessageId,
     ) -> Option<()> {
         self.rpc_states
             .get_mut(sender)
             .or_else(|| {
-                warn!(
-                    "{}: Received response from {} that we didn't expect.",
-                    own_id, sender
+                op_log!(
+                    warn,
+                    own_id,
+                    message_id,
+                    "Received response from {} that we didn't expect.",
+                    sender
                 );
                 None
             })
             .map(|rpc_state| *rpc_state = RpcState::Actioned(got_error_response))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use rand::Rng;
+    use safe_nd::{ClientFullId, PubImmutableData, UnpubImmutableData};
+
+    #[test]
+    fn ops_and_rpc_states_survive_a_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let client = ClientFullId::new_ed25519(&mut rng).public_id().clone();
+            let size = rng.gen_range(0, 1024);
+            let pub_data = IData::Pub(PubImmutableData::new(utils::random_vec(&mut rng, size)));
+            let unpub_data = IData::Unpub(UnpubImmutableData::new(
+                utils::random_vec(&mut rng, size),
+                *client.public_key(),
+            ));
+            let requests = vec![
+                IDataRequest::PutIData(pub_data),
+                IDataRequest::GetIData(*unpub_data.address()),
+                IDataRequest::DeleteUnpubIData(*unpub_data.address()),
+            ];
+            let rpc_states = vec![
+                RpcState::Sent,
+                RpcState::Actioned(None),
+                RpcState::Actioned(Some(NdError::NoSuchData)),
+                RpcState::Actioned(Some(utils::section_busy_error())),
+                RpcState::HolderGone,
+                RpcState::TimedOut,
+            ];
+
+            for rpc_state in &rpc_states {
+                assert_eq!(utils::round_trip(rpc_state), *rpc_state);
+            }
+            for request in requests {
+                let holders = (0..rng.gen_range(0, 8))
+                    .map(|_| XorName(rng.gen()))
+                    .collect();
+                let mut idata_op = IDataOp::new(PublicId::Client(client.clone()), request, holders);
+                for (rpc_state, new_state) in idata_op.rpc_states.values_mut().zip(&rpc_states) {
+                    *rpc_state = new_state.clone();
+                }
+                assert_eq!(utils::round_trip(&idata_op), idata_op);
+            }
+        }
+    }
+}
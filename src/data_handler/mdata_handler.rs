@@ -41,15 +41,22 @@ impl MDataHandler {
     ) -> Result<Self> {
         let root_dir = config.root_dir()?;
         let max_capacity = config.max_capacity();
-        let chunks = MutableChunkStore::new(
+        let mut chunks = MutableChunkStore::new(
             &root_dir,
             max_capacity,
             Rc::clone(total_used_space),
             init_mode,
         )?;
+        chunks.set_min_free_space(config.min_free_space());
         Ok(Self { id, chunks })
     }
 
+    /// Applies the storage limits from a reloaded `config`.
+    pub(super) fn apply_config(&mut self, config: &Config) {
+        self.chunks.set_max_capacity(config.max_capacity());
+        self.chunks.set_min_free_space(config.min_free_space());
+    }
+
     /// Get `MData` from the chunk store and check permissions.
     /// Returns `Some(Result<..>)` if the flow should be continued, returns
     /// `None` if there was a logic error encountered and the flow should be
@@ -104,7 +111,7 @@ impl MDataHandler {
             .and_then(move |mdata| {
                 self.chunks
                     .put(&mdata)
-                    .map_err(|error| error.to_string().into())
+                    .map_err(utils::chunk_store_put_error)
             });
         let refund = utils::get_refund_for_put(&result);
         Some(Action::RespondToClientHandlers {
@@ -128,9 +135,7 @@ impl MDataHandler {
         let result = if self.chunks.has(data.address()) {
             Err(NdError::DataExists)
         } else {
-            self.chunks
-                .put(&data)
-                .map_err(|error| error.to_string().into())
+            self.chunks.put(&data).map_err(utils::chunk_store_put_error)
         };
         let refund = utils::get_refund_for_put(&result);
         Some(Action::RespondToClientHandlers {
@@ -0,0 +1,67 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::utils::Instant;
+use safe_nd::MessageId;
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+};
+
+/// A bounded record of the message IDs of operations which have already concluded.
+///
+/// Once an op is removed, a replayed message carrying the same ID would otherwise be treated as a
+/// brand new request.  Entries are dropped once they're older than `expiry`, or when more than
+/// `capacity` entries are held (oldest first).
+pub(super) struct SeenMessages {
+    capacity: usize,
+    expiry: Duration,
+    ids: HashSet<MessageId>,
+    order: VecDeque<(MessageId, Instant)>,
+}
+
+impl SeenMessages {
+    pub fn new(capacity: usize, expiry: Duration) -> Self {
+        Self {
+            capacity,
+            expiry,
+            ids: Default::default(),
+            order: Default::default(),
+        }
+    }
+
+    /// Records `message_id` as seen.
+    pub fn insert(&mut self, message_id: MessageId) {
+        self.prune();
+        if !self.ids.insert(message_id) {
+            return;
+        }
+        self.order.push_back((message_id, Instant::now()));
+        while self.order.len() > self.capacity {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                let _ = self.ids.remove(&oldest);
+            }
+        }
+    }
+
+    /// Returns whether `message_id` has been seen and hasn't yet expired.
+    pub fn contains(&mut self, message_id: &MessageId) -> bool {
+        self.prune();
+        self.ids.contains(message_id)
+    }
+
+    fn prune(&mut self) {
+        while let Some((message_id, time)) = self.order.front().cloned() {
+            if time.elapsed() < self.expiry {
+                break;
+            }
+            let _ = self.order.pop_front();
+            let _ = self.ids.remove(&message_id);
+        }
+    }
+}
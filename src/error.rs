@@ -81,8 +81,39 @@ quick_error! {
         NoSuchAccount {}
         /// Logic error.
         Logic {}
+        /// The operation is only possible while we're an Elder.
+        NotElder {}
+        /// A configuration value is invalid.
+        InvalidConfig(message: String) {
+            display("Invalid config: {}", message)
+        }
     }
 }
 
+/// The message carried by `safe_nd::Error::NetworkOther` when the vault is temporarily shedding
+/// load, for example because it has run out of storage space.
+///
+/// Unlike other errors this isn't a permanent failure: clients should back off and retry the
+/// request later.  Any cost charged for the request is refunded.
+pub const SECTION_BUSY: &str = "Section busy, please retry later";
+
+/// The message carried by `safe_nd::Error::NetworkOther` when a request is refused because another
+/// request for the same data is still being handled, e.g. when a client retries a `PutIData` with
+/// a new message ID before the original has completed.
+///
+/// The original request will still be answered, so clients need only retry if that fails.  Any
+/// cost charged for the refused request is refunded.
+pub const OPERATION_IN_PROGRESS: &str = "Operation already in progress for this data";
+
+/// The message carried by `safe_nd::Error::NetworkOther` when a request is refused because the
+/// vault is in maintenance mode.  It isn't a permanent failure: clients should back off and retry
+/// the request later.
+pub const MAINTENANCE: &str = "Vault under maintenance, please retry later";
+
+/// The message carried by `safe_nd::Error::NetworkOther` when a mutation or coin transaction is
+/// refused because the vault is configured as read-only, e.g. an archive or standby vault.  Gets
+/// are still served.  Any cost charged for the request is refunded.
+pub const READ_ONLY: &str = "Vault is read-only";
+
 /// Specialisation of `std::Result` for Vault.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,50 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+/// A snapshot of a vault's health, as returned by `Vault::health_check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The vault's current role, e.g. "Elder".
+    pub role: &'static str,
+    /// Number of ImmutableData ops in flight.
+    pub in_flight_ops: usize,
+    /// Bytes used by stored chunks.
+    pub used_space: u64,
+    /// How long the oldest ImmutableData op in flight has been running, if any are.
+    pub oldest_op_age: Option<Duration>,
+    /// Whether the ImmutableData metadata db is still present on disk.
+    pub db_reachable: bool,
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}, {} ops in flight",
+            self.role, self.in_flight_ops
+        )?;
+        if let Some(age) = self.oldest_op_age {
+            write!(formatter, " (oldest {:?})", age)?;
+        }
+        write!(
+            formatter,
+            ", {} bytes used, db {}",
+            self.used_space,
+            if self.db_reachable {
+                "reachable"
+            } else {
+                "unreachable"
+            }
+        )
+    }
+}
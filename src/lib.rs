@@ -29,18 +29,33 @@
 // For quick_error
 #![recursion_limit = "128"]
 
+#[macro_use]
+mod macros;
+
 mod action;
+#[cfg(feature = "admin_listener")]
+mod admin_listener;
 mod adult;
+#[cfg(feature = "audit")]
+mod audit;
 mod chunk_store;
 mod client_handler;
+mod client_queue;
 mod coins_handler;
 mod config_handler;
 mod data_handler;
 mod error;
+mod health;
+mod metrics;
+mod op_latency;
+mod routing_api;
 mod rpc;
+mod self_test;
+mod storage_check;
 mod to_db_key;
 mod utils;
 mod vault;
+mod watchdog;
 
 pub(crate) use to_db_key::ToDbKey;
 
@@ -53,10 +68,23 @@ pub use crate::mock_routing as routing;
 #[cfg(not(feature = "mock"))]
 pub use routing;
 
+#[cfg(feature = "admin_listener")]
+pub use crate::admin_listener::start_admin_listener;
+#[cfg(feature = "mock_base")]
+pub use crate::rpc::SentRpc;
+#[cfg(feature = "audit")]
+pub use crate::{audit::AuditEvent, data_handler::OpType};
 pub use crate::{
     chunk_store::error::Error as ChunkStoreError,
-    client_handler::COST_OF_PUT,
-    config_handler::{write_connection_info, Config},
-    error::{Error, Result},
-    vault::{Command, Vault},
+    client_handler::{RefundReason, COST_OF_PUT},
+    config_handler::{write_connection_info, Config, TrimStrategy},
+    error::{Error, Result, MAINTENANCE, OPERATION_IN_PROGRESS, READ_ONLY, SECTION_BUSY},
+    health::HealthReport,
+    metrics::{Metrics, RoutingEventCounts},
+    op_latency::{LatencyHistogram, OpLatencies},
+    self_test::{SelfTestReport, SelfTestStep},
+    storage_check::StorageReport,
+    vault::{
+        bounded_command_channel, AdminCommand, Command, CommandSender, OperatorCapability, Vault,
+    },
 };
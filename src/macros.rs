@@ -0,0 +1,18 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+/// Logs a message about the operation identified by a `MessageId`, at the given level.
+///
+/// Every line is written as `<node>: [<message ID>] <message>`, so that an operation can be
+/// followed through the vault by searching the logs for its message ID, e.g.
+/// `op_log!(warn, self, message_id, "Failed to write metadata to DB: {:?}", error)`.
+macro_rules! op_log {
+    ($level:ident, $node:expr, $message_id:expr, $($arg:tt)+) => {
+        log::$level!("{}: [{:?}] {}", $node, $message_id, format_args!($($arg)+))
+    };
+}
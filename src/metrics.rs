@@ -0,0 +1,98 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{client_handler::RefundReason, op_latency::OpLatencies};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// The vault's counters and histograms, as returned by `Vault::metrics`.  This is displayed as a
+/// single line of `name=value` pairs, so that it can be logged and parsed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Number of ImmutableData ops in flight.
+    pub in_flight_ops: usize,
+    /// Number of responses held for client requests which haven't reached us.
+    pub unclaimed_client_responses: usize,
+    /// Number of responses dropped because their client didn't reconnect in time to receive them.
+    pub expired_client_responses: u64,
+    /// Number of put refunds made for each reason.
+    pub refunds: BTreeMap<RefundReason, u64>,
+    /// Number of ImmutableData responses rejected from nodes which weren't queried.
+    pub unexpected_idata_responses: u64,
+    /// Number of ImmutableData get responses received after another holder's had already answered.
+    pub redundant_idata_responses: u64,
+    /// Latencies of the ImmutableData ops which have concluded.
+    pub idata_op_latencies: OpLatencies,
+    /// Number of routing events of each kind handled.
+    pub routing_events: RoutingEventCounts,
+    /// Number of consensused actions which failed to decode, suggesting that members of the
+    /// section run incompatible versions.
+    pub undecodable_consensus_actions: u64,
+}
+
+/// Number of routing events of each kind a vault has handled.  Members leaving much more often than
+/// joining signals an unstable section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoutingEventCounts {
+    /// `Promoted` events.
+    pub promoted: u64,
+    /// `Connected` events.
+    pub connected: u64,
+    /// `MemberJoined` events.
+    pub member_joined: u64,
+    /// `MemberLeft` events.
+    pub member_left: u64,
+    /// `MessageReceived` events.
+    pub message_received: u64,
+    /// `Consensus` events, including those which failed to decode.
+    pub consensus: u64,
+    /// Events of any other kind, all of which are ignored.
+    pub other: u64,
+}
+
+impl Display for Metrics {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "in_flight_ops={} unclaimed_client_responses={} expired_client_responses={} \
+             unexpected_idata_responses={} redundant_idata_responses={}",
+            self.in_flight_ops,
+            self.unclaimed_client_responses,
+            self.expired_client_responses,
+            self.unexpected_idata_responses,
+            self.redundant_idata_responses
+        )?;
+        for (reason, count) in &self.refunds {
+            write!(formatter, " refunds.{:?}={}", reason, count)?;
+        }
+        let latencies = &self.idata_op_latencies;
+        write!(
+            formatter,
+            " idata_put=\"{}\" idata_get=\"{}\" idata_delete=\"{}\"",
+            latencies.put, latencies.get, latencies.delete
+        )?;
+        let events = &self.routing_events;
+        write!(
+            formatter,
+            " routing_events.promoted={} routing_events.connected={} \
+             routing_events.member_joined={} routing_events.member_left={} \
+             routing_events.message_received={} routing_events.consensus={} \
+             routing_events.other={} undecodable_consensus_actions={}",
+            events.promoted,
+            events.connected,
+            events.member_joined,
+            events.member_left,
+            events.message_received,
+            events.consensus,
+            events.other,
+            self.undecodable_consensus_actions
+        )
+    }
+}
@@ -15,7 +15,7 @@ use mock_quic_p2p::{self as quic_p2p, Peer, QuicP2p, QuicP2pError};
 use routing::{event::Event, XorName};
 use std::{
     cell::RefCell,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     net::SocketAddr,
     rc::{Rc, Weak},
 };
@@ -29,23 +29,110 @@ pub type ConsensusGroupRef = Rc<RefCell<ConsensusGroup>>;
 /// Token for sending messages
 pub type Token = u64;
 
+// The votes cast for an event in the current round.  A round ends once every member has voted,
+// or when a member votes again after the event was consensused, which starts a new round.
+#[derive(Default)]
+struct Round {
+    // Members which have voted, indexed by `event_channels`.
+    voters: HashSet<usize>,
+    consensused: bool,
+}
+
+// How many distinct members need to vote for an event before it's consensused.
+#[derive(Clone, Copy)]
+enum Quorum {
+    Fixed(usize),
+    // More than two thirds of the current members, as for a section's elders.
+    Supermajority,
+}
+
 /// Consensus
 pub struct ConsensusGroup {
-    consensused: HashSet<Vec<u8>>,
+    quorum: Quorum,
+    rounds: HashMap<Vec<u8>, Round>,
     event_channels: Vec<Sender<Event>>,
+    // Number of events consensused since creation or the last `clear_consensused`.
+    consensused_count: usize,
 }
 
 impl ConsensusGroup {
-    /// Creates a new consensus group.
+    /// Creates a new consensus group where a single vote is enough to reach consensus.
     pub fn new() -> ConsensusGroupRef {
+        Self::new_with_quorum(1)
+    }
+
+    /// Creates a new consensus group where an event is only consensused once `quorum` distinct
+    /// members have voted for it.
+    pub fn new_with_quorum(quorum: usize) -> ConsensusGroupRef {
+        Self::with_quorum(Quorum::Fixed(quorum))
+    }
+
+    /// Creates a new consensus group where an event is consensused once more than two thirds of
+    /// its members have voted.  The quorum follows the group's size as members join.
+    pub fn new_with_supermajority() -> ConsensusGroupRef {
+        Self::with_quorum(Quorum::Supermajority)
+    }
+
+    /// Returns the number of distinct members which currently need to vote for an event.
+    pub fn quorum(&self) -> usize {
+        match self.quorum {
+            Quorum::Fixed(quorum) => quorum,
+            Quorum::Supermajority => self.event_channels.len() * 2 / 3 + 1,
+        }
+    }
+
+    fn with_quorum(quorum: Quorum) -> ConsensusGroupRef {
         Rc::new(RefCell::new(Self {
-            consensused: Default::default(),
+            quorum,
+            rounds: Default::default(),
             event_channels: Vec::new(),
+            consensused_count: 0,
         }))
     }
 
-    fn vote_for(&mut self, event: Vec<u8>) {
-        if self.consensused.insert(event.clone()) {
+    /// Returns the number of events consensused since the group was created or
+    /// `clear_consensused` was last called.  An event consensused in several rounds counts once per
+    /// round.
+    pub fn consensused_count(&self) -> usize {
+        self.consensused_count
+    }
+
+    /// Forgets all votes cast so far and resets `consensused_count`, so that a test can start a new
+    /// phase in which any event needs a full quorum of fresh votes.
+    pub fn clear_consensused(&mut self) {
+        self.rounds.clear();
+        self.consensused_count = 0;
+    }
+
+    fn vote_for(&mut self, voter: usize, event: Vec<u8>) {
+        let member_count = self.event_channels.len();
+        let quorum = self.quorum();
+        let round = self.rounds.entry(event.clone()).or_default();
+        if !round.voters.insert(voter) {
+            if !round.consensused {
+                // Repeated vote within a round which hasn't reached consensus yet.
+                return;
+            }
+            // The member is voting for the event again, so this is a new occurrence of it.
+            *round = Round::default();
+            let _ = round.voters.insert(voter);
+        }
+
+        let fire = !round.consensused && round.voters.len() >= quorum;
+        if !round.consensused && !fire {
+            trace!(
+                "Event has {}/{} votes, not consensused yet",
+                round.voters.len(),
+                quorum
+            );
+        }
+        round.consensused |= fire;
+        if round.consensused && round.voters.len() >= member_count {
+            let _ = self.rounds.remove(&event);
+        }
+
+        if fire {
+            self.consensused_count += 1;
             for channel in &self.event_channels {
                 unwrap!(channel.send(Event::Consensus(event.clone())));
             }
@@ -60,12 +147,16 @@ pub struct Node {
     network_node_rx: Receiver<NetworkEvent>,
     network_node_rx_idx: usize,
     consensus_group: Option<Weak<RefCell<ConsensusGroup>>>,
+    // Our index within the consensus group, used to tell votes of distinct members apart.
+    consensus_group_index: usize,
 }
 
 impl Node {
     /// Creates a new builder to configure and create a `Node`.
     pub fn builder() -> NodeBuilder {
-        NodeBuilder {}
+        NodeBuilder {
+            network_config: None,
+        }
     }
 
     /// Initialise the routing node.
@@ -84,9 +175,11 @@ impl Node {
     /// Vote for an event.
     pub fn vote_for(&mut self, event: Vec<u8>) {
         if let Some(ref consensus_group) = self.consensus_group {
-            let _ = consensus_group
-                .upgrade()
-                .map(|group| group.borrow_mut().vote_for(event));
+            let _ = consensus_group.upgrade().map(|group| {
+                group
+                    .borrow_mut()
+                    .vote_for(self.consensus_group_index, event)
+            });
         } else {
             unwrap!(self.events_tx.send(Event::Consensus(event)));
         }
@@ -147,13 +240,23 @@ impl Node {
 }
 
 /// A builder to configure and create a new `Node`.
-pub struct NodeBuilder {}
+pub struct NodeBuilder {
+    network_config: Option<NetworkConfig>,
+}
 
 impl NodeBuilder {
+    /// Sets the network config used to set up the node's transport.  If not called, the default
+    /// config is used.
+    pub fn network_config(self, config: NetworkConfig) -> Self {
+        Self {
+            network_config: Some(config),
+        }
+    }
+
     /// Creates new `Node`.
     pub fn create(self) -> (Node, Receiver<Event>, Receiver<NetworkEvent>) {
         let (quic_p2p, network_node_rx, network_client_rx) =
-            unwrap!(setup_quic_p2p(&Default::default()));
+            unwrap!(setup_quic_p2p(&self.network_config.unwrap_or_default()));
         let (events_tx, events_rx) = mpmc::unbounded();
 
         (
@@ -163,6 +266,7 @@ impl NodeBuilder {
                 events_tx,
                 network_node_rx_idx: 0,
                 consensus_group: None,
+                consensus_group_index: 0,
             },
             events_rx,
             network_client_rx,
@@ -175,13 +279,14 @@ impl NodeBuilder {
         consensus_group: ConsensusGroupRef,
     ) -> (Node, Receiver<Event>, Receiver<NetworkEvent>) {
         let (quic_p2p, network_node_rx, network_client_rx) =
-            unwrap!(setup_quic_p2p(&Default::default()));
+            unwrap!(setup_quic_p2p(&self.network_config.unwrap_or_default()));
         let (events_tx, events_rx) = mpmc::unbounded();
 
-        consensus_group
-            .borrow_mut()
-            .event_channels
-            .push(events_tx.clone());
+        let consensus_group_index = {
+            let mut group = consensus_group.borrow_mut();
+            group.event_channels.push(events_tx.clone());
+            group.event_channels.len() - 1
+        };
 
         (
             Node {
@@ -190,6 +295,7 @@ impl NodeBuilder {
                 events_tx,
                 network_node_rx_idx: 0,
                 consensus_group: Some(Rc::downgrade(&consensus_group)),
+                consensus_group_index,
             },
             events_rx,
             network_client_rx,
@@ -214,3 +320,101 @@ fn setup_quic_p2p(
         .build()?;
     Ok((quic_p2p, node_receiver, client_receiver))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_event_is_consensused_once_per_round() {
+        let group = ConsensusGroup::new();
+        let receivers: Vec<_> = (0..2)
+            .map(|_| {
+                let (tx, rx) = mpmc::unbounded();
+                group.borrow_mut().event_channels.push(tx);
+                rx
+            })
+            .collect();
+        let event = vec![1, 2, 3];
+
+        // Both members vote in each of two rounds; the event should fire once per round.
+        for _ in 0..2 {
+            group.borrow_mut().vote_for(0, event.clone());
+            group.borrow_mut().vote_for(1, event.clone());
+        }
+
+        for receiver in &receivers {
+            assert_eq!(receiver.try_iter().count(), 2);
+        }
+    }
+
+    #[test]
+    fn event_is_not_consensused_below_quorum() {
+        let group = ConsensusGroup::new_with_quorum(2);
+        let (tx, rx) = mpmc::unbounded();
+        group.borrow_mut().event_channels.push(tx);
+        let event = vec![1, 2, 3];
+
+        group.borrow_mut().vote_for(0, event.clone());
+        group.borrow_mut().vote_for(0, event.clone());
+        assert_eq!(rx.try_iter().count(), 0);
+
+        group.borrow_mut().vote_for(1, event);
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn consensused_events_are_counted_until_cleared() {
+        let group = ConsensusGroup::new_with_quorum(2);
+        let (tx, rx) = mpmc::unbounded();
+        group.borrow_mut().event_channels.push(tx);
+        let _other = {
+            let (tx, rx) = mpmc::unbounded();
+            group.borrow_mut().event_channels.push(tx);
+            rx
+        };
+
+        group.borrow_mut().vote_for(0, vec![1]);
+        group.borrow_mut().vote_for(1, vec![1]);
+        group.borrow_mut().vote_for(0, vec![2]);
+        group.borrow_mut().vote_for(1, vec![2]);
+        assert_eq!(group.borrow().consensused_count(), 2);
+
+        // A vote left over from before clearing doesn't count towards the next phase's quorum.
+        group.borrow_mut().vote_for(0, vec![3]);
+        group.borrow_mut().clear_consensused();
+        assert_eq!(group.borrow().consensused_count(), 0);
+        group.borrow_mut().vote_for(1, vec![3]);
+        assert_eq!(group.borrow().consensused_count(), 0);
+        group.borrow_mut().vote_for(0, vec![3]);
+        assert_eq!(group.borrow().consensused_count(), 1);
+        assert_eq!(rx.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn supermajority_quorum_follows_group_size() {
+        let group = ConsensusGroup::new_with_supermajority();
+        let add_member = || {
+            let (tx, rx) = mpmc::unbounded();
+            group.borrow_mut().event_channels.push(tx);
+            rx
+        };
+        let rx = add_member();
+        let _others: Vec<_> = (0..3).map(|_| add_member()).collect();
+        assert_eq!(group.borrow().quorum(), 3);
+        let event = vec![1, 2, 3];
+
+        group.borrow_mut().vote_for(0, event.clone());
+        group.borrow_mut().vote_for(1, event.clone());
+
+        // The group grows while the vote is in progress, so the third vote is no longer enough.
+        let _joined: Vec<_> = (0..2).map(|_| add_member()).collect();
+        assert_eq!(group.borrow().quorum(), 5);
+        group.borrow_mut().vote_for(2, event.clone());
+        assert_eq!(rx.try_iter().count(), 0);
+
+        group.borrow_mut().vote_for(3, event.clone());
+        group.borrow_mut().vote_for(4, event);
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+// Upper bounds in milliseconds of each histogram bucket but the last, which is unbounded.
+const BUCKET_BOUNDS_MS: [u64; 13] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000,
+];
+const BUCKET_COUNT: usize = 14;
+
+/// A histogram of operation durations, counted in fixed buckets from 1ms to 10s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKET_COUNT],
+}
+
+impl LatencyHistogram {
+    /// Adds a duration to the histogram.
+    pub fn record(&mut self, duration: Duration) {
+        let millis = duration.as_millis();
+        let index = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| millis <= u128::from(bound))
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.counts[index] = self.counts[index].saturating_add(1);
+    }
+
+    /// Returns the number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns an upper bound of the `quantile` (from 0.0 to 1.0) of the recorded durations: the
+    /// upper bound of the bucket it falls in.  This is `None` if nothing has been recorded, or if
+    /// the quantile falls in the unbounded bucket above 10s.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((quantile * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, bucket_count) in self.counts.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                return BUCKET_BOUNDS_MS
+                    .get(index)
+                    .map(|&bound| Duration::from_millis(bound));
+            }
+        }
+        None
+    }
+}
+
+impl Display for LatencyHistogram {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{} ops", self.count())?;
+        if self.count() == 0 {
+            return Ok(());
+        }
+        for &(name, quantile) in &[("p50", 0.5), ("p90", 0.9), ("p99", 0.99)] {
+            match self.quantile(quantile) {
+                Some(bound) => write!(formatter, ", {} <= {:?}", name, bound)?,
+                None => write!(formatter, ", {} > 10s", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Latency histograms of concluded ImmutableData operations, from the data handlers receiving the
+/// request to the last holder responding.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpLatencies {
+    /// Latencies of puts.
+    pub put: LatencyHistogram,
+    /// Latencies of gets.
+    pub get: LatencyHistogram,
+    /// Latencies of deletes.
+    pub delete: LatencyHistogram,
+}
+
+impl Display for OpLatencies {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "put: {}; get: {}; delete: {}",
+            self.put, self.get, self.delete
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles_are_bucket_upper_bounds() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+
+        for _ in 0..90 {
+            histogram.record(Duration::from_millis(3));
+        }
+        for _ in 0..9 {
+            histogram.record(Duration::from_millis(150));
+        }
+        histogram.record(Duration::from_secs(60));
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(5)));
+        assert_eq!(histogram.quantile(0.9), Some(Duration::from_millis(5)));
+        assert_eq!(histogram.quantile(0.99), Some(Duration::from_millis(200)));
+        assert_eq!(histogram.quantile(1.0), None);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::routing::{Node, RoutingError};
+use bytes::Bytes;
+use safe_nd::XorName;
+use std::net::SocketAddr;
+
+/// The parts of a Routing node which the handlers use, so that they can be given a test double
+/// rather than a real node.
+pub(crate) trait RoutingApi {
+    /// Returns whether `name` falls within our section's prefix.
+    fn matches_our_prefix(&self, name: &XorName) -> bool;
+
+    /// Returns the name and address of each of our section's elders, if we know them.
+    fn our_elders(&self) -> Option<Vec<(XorName, SocketAddr)>>;
+
+    /// Returns the name and address of each of the elders we know of closest to `name`.
+    fn closest_known_elders_to(
+        &self,
+        name: &XorName,
+    ) -> Result<Vec<(XorName, SocketAddr)>, RoutingError>;
+
+    /// Sends `msg` to the client at `peer_addr`, tagged with `token` to correlate the delivery
+    /// event.
+    fn send_message_to_client(
+        &mut self,
+        peer_addr: SocketAddr,
+        msg: Bytes,
+        token: u64,
+    ) -> Result<(), RoutingError>;
+
+    /// Drops our connection to the client at `peer_addr`.
+    fn disconnect_from_client(&mut self, peer_addr: SocketAddr) -> Result<(), RoutingError>;
+}
+
+impl RoutingApi for Node {
+    fn matches_our_prefix(&self, name: &XorName) -> bool {
+        Node::matches_our_prefix(self, &routing::XorName(name.0)).unwrap_or(false)
+    }
+
+    fn our_elders(&self) -> Option<Vec<(XorName, SocketAddr)>> {
+        self.our_elders_info().map(|iter| {
+            iter.map(|p2p_node| (XorName(p2p_node.name().0), *p2p_node.peer_addr()))
+                .collect()
+        })
+    }
+
+    fn closest_known_elders_to(
+        &self,
+        name: &XorName,
+    ) -> Result<Vec<(XorName, SocketAddr)>, RoutingError> {
+        Node::closest_known_elders_to(self, &routing::XorName(name.0)).map(|iter| {
+            iter.map(|p2p_node| (XorName(p2p_node.name().0), *p2p_node.peer_addr()))
+                .collect()
+        })
+    }
+
+    fn send_message_to_client(
+        &mut self,
+        peer_addr: SocketAddr,
+        msg: Bytes,
+        token: u64,
+    ) -> Result<(), RoutingError> {
+        Node::send_message_to_client(self, peer_addr, msg, token)
+    }
+
+    fn disconnect_from_client(&mut self, peer_addr: SocketAddr) -> Result<(), RoutingError> {
+        Node::disconnect_from_client(self, peer_addr)
+    }
+}
+
+/// A `RoutingApi` whose section is set by the test, recording what it's asked to send.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MockRoutingApi {
+    /// Whether every name is treated as within our prefix.
+    pub matches_our_prefix: bool,
+    /// Our section's elders, if known.
+    pub our_elders: Option<Vec<(XorName, SocketAddr)>>,
+    /// The elders closest to any name outside our prefix.
+    pub closest_known_elders: Vec<(XorName, SocketAddr)>,
+    /// Messages sent to clients, with their recipients.
+    pub sent: Vec<(SocketAddr, Bytes)>,
+    /// Clients disconnected from.
+    pub disconnected: Vec<SocketAddr>,
+}
+
+#[cfg(test)]
+impl RoutingApi for MockRoutingApi {
+    fn matches_our_prefix(&self, _name: &XorName) -> bool {
+        self.matches_our_prefix
+    }
+
+    fn our_elders(&self) -> Option<Vec<(XorName, SocketAddr)>> {
+        self.our_elders.clone()
+    }
+
+    fn closest_known_elders_to(
+        &self,
+        _name: &XorName,
+    ) -> Result<Vec<(XorName, SocketAddr)>, RoutingError> {
+        Ok(self.closest_known_elders.clone())
+    }
+
+    fn send_message_to_client(
+        &mut self,
+        peer_addr: SocketAddr,
+        msg: Bytes,
+        _token: u64,
+    ) -> Result<(), RoutingError> {
+        self.sent.push((peer_addr, msg));
+        Ok(())
+    }
+
+    fn disconnect_from_client(&mut self, peer_addr: SocketAddr) -> Result<(), RoutingError> {
+        self.disconnected.push(peer_addr);
+        Ok(())
+    }
+}
@@ -8,8 +8,15 @@
 
 //! RPC messages internal to Vaults.
 
-use safe_nd::{Coins, MessageId, PublicId, Request, Response};
+use crate::utils;
+#[cfg(feature = "mock_base")]
+use safe_nd::XorName;
+use safe_nd::{
+    Coins, Error as NdError, IDataAddress, MessageId, PublicId, Request, Response,
+    Result as NdResult,
+};
 use serde::{Deserialize, Serialize};
+use std::cmp;
 
 /// RPC messages exchanged between nodes.
 #[allow(clippy::large_enum_variant)]
@@ -28,4 +35,418 @@ pub(crate) enum Rpc {
         message_id: MessageId,
         refund: Option<Coins>,
     },
+    /// A holder's response to a `PutIData` request, sent to DataHandlers along with how much of the
+    /// holder's storage is used after handling it.
+    PutIDataResponse {
+        result: NdResult<()>,
+        requester: PublicId,
+        message_id: MessageId,
+        refund: Option<Coins>,
+        used_space: u64,
+        max_capacity: u64,
+    },
+    /// One segment of the serialised data of a `PutIData` request, sent from DataHandlers to a
+    /// holder when the data is too large to be sent in a single message.
+    PutIDataSegment {
+        address: IDataAddress,
+        requester: PublicId,
+        message_id: MessageId,
+        index: u32,
+        count: u32,
+        bytes: Vec<u8>,
+    },
+    /// Sent from DataHandlers to the holders which stored a chunk for a put which failed on other
+    /// holders, so that they delete it again before the client is refunded.
+    DiscardIData {
+        address: IDataAddress,
+        requester: PublicId,
+        message_id: MessageId,
+    },
+    /// A holder's response to a `DiscardIData` request, once it no longer holds the chunk.
+    DiscardIDataResponse {
+        requester: PublicId,
+        message_id: MessageId,
+    },
+}
+
+impl Rpc {
+    /// Splits a `PutIData` request whose serialised data exceeds `max_segment_size` bytes into
+    /// `PutIDataSegment`s.  Any other RPC is returned unchanged.
+    pub fn into_segments(self, max_segment_size: u64) -> Vec<Rpc> {
+        let (data, requester, message_id) = match self {
+            Rpc::Request {
+                request: Request::PutIData(data),
+                requester,
+                message_id,
+            } => (data, requester, message_id),
+            rpc => return vec![rpc],
+        };
+
+        let serialised = utils::serialise(&data);
+        let max_segment_size = cmp::max(max_segment_size, 1) as usize;
+        if serialised.len() <= max_segment_size {
+            return vec![Rpc::Request {
+                request: Request::PutIData(data),
+                requester,
+                message_id,
+            }];
+        }
+
+        let count = ((serialised.len() + max_segment_size - 1) / max_segment_size) as u32;
+        serialised
+            .chunks(max_segment_size)
+            .enumerate()
+            .map(|(index, bytes)| Rpc::PutIDataSegment {
+                address: *data.address(),
+                requester: requester.clone(),
+                message_id,
+                index: index as u32,
+                count,
+                bytes: bytes.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Makes this RPC fit within `max_size` bytes once serialised.  A `PutIData` request too large
+    /// to send whole is split into segments which each fit, and a response too large to return is
+    /// replaced by an `ExceededSize` error of the same kind.  Returns `Err` with the serialised
+    /// size of any other RPC which doesn't fit.
+    pub fn fit_within(self, max_size: u64) -> Result<Vec<Rpc>, u64> {
+        let size = utils::serialised_size(&self);
+        if size <= max_size {
+            return Ok(vec![self]);
+        }
+        match self {
+            Rpc::Request {
+                request: Request::PutIData(data),
+                requester,
+                message_id,
+            } => {
+                // Every segment has the same overhead as one without any bytes.
+                let overhead = utils::serialised_size(&Rpc::PutIDataSegment {
+                    address: *data.address(),
+                    requester: requester.clone(),
+                    message_id,
+                    index: 0,
+                    count: 0,
+                    bytes: Vec::new(),
+                });
+                if overhead >= max_size {
+                    return Err(size);
+                }
+                let rpc = Rpc::Request {
+                    request: Request::PutIData(data),
+                    requester,
+                    message_id,
+                };
+                Ok(rpc.into_segments(max_size - overhead))
+            }
+            Rpc::Response {
+                response,
+                requester,
+                message_id,
+                refund,
+            } => {
+                let rpc = Rpc::Response {
+                    response: exceeded_size_response(&response),
+                    requester,
+                    message_id,
+                    refund,
+                };
+                if utils::serialised_size(&rpc) > max_size {
+                    return Err(size);
+                }
+                Ok(vec![rpc])
+            }
+            _ => Err(size),
+        }
+    }
+}
+
+// Returns an `ExceededSize` error of the same kind as `response`.
+fn exceeded_size_response(response: &Response) -> Response {
+    let error = NdError::ExceededSize;
+    match response {
+        Response::GetIData(_) => Response::GetIData(Err(error)),
+        Response::GetMData(_) => Response::GetMData(Err(error)),
+        Response::GetMDataShell(_) => Response::GetMDataShell(Err(error)),
+        Response::GetMDataVersion(_) => Response::GetMDataVersion(Err(error)),
+        Response::ListMDataEntries(_) => Response::ListMDataEntries(Err(error)),
+        Response::ListMDataKeys(_) => Response::ListMDataKeys(Err(error)),
+        Response::ListMDataValues(_) => Response::ListMDataValues(Err(error)),
+        Response::ListMDataUserPermissions(_) => Response::ListMDataUserPermissions(Err(error)),
+        Response::ListMDataPermissions(_) => Response::ListMDataPermissions(Err(error)),
+        Response::GetMDataValue(_) => Response::GetMDataValue(Err(error)),
+        Response::GetAData(_) => Response::GetAData(Err(error)),
+        Response::GetADataShell(_) => Response::GetADataShell(Err(error)),
+        Response::GetADataOwners(_) => Response::GetADataOwners(Err(error)),
+        Response::GetADataRange(_) => Response::GetADataRange(Err(error)),
+        Response::GetADataIndices(_) => Response::GetADataIndices(Err(error)),
+        Response::GetADataLastEntry(_) => Response::GetADataLastEntry(Err(error)),
+        Response::GetADataPermissions(_) => Response::GetADataPermissions(Err(error)),
+        Response::GetPubADataUserPermissions(_) => Response::GetPubADataUserPermissions(Err(error)),
+        Response::GetUnpubADataUserPermissions(_) => {
+            Response::GetUnpubADataUserPermissions(Err(error))
+        }
+        Response::GetADataValue(_) => Response::GetADataValue(Err(error)),
+        Response::GetBalance(_) => Response::GetBalance(Err(error)),
+        Response::Transaction(_) => Response::Transaction(Err(error)),
+        Response::GetLoginPacket(_) => Response::GetLoginPacket(Err(error)),
+        Response::ListAuthKeysAndVersion(_) => Response::ListAuthKeysAndVersion(Err(error)),
+        Response::Mutation(_) => Response::Mutation(Err(error)),
+    }
+}
+
+/// An RPC sent by a vault, as reported to the observer set by `Vault::set_sent_rpc_observer`.
+#[cfg(feature = "mock_base")]
+#[derive(Debug, Clone)]
+pub struct SentRpc {
+    /// Name the RPC was sent from.
+    pub src: XorName,
+    /// Name the RPC was sent to.
+    pub dst: XorName,
+    pub(crate) rpc: Rpc,
+}
+
+#[cfg(feature = "mock_base")]
+impl SentRpc {
+    /// Returns the wrapped client request, if this is a request.
+    pub fn request(&self) -> Option<&Request> {
+        match self.rpc {
+            Rpc::Request { ref request, .. } => Some(request),
+            Rpc::Response { .. }
+            | Rpc::PutIDataResponse { .. }
+            | Rpc::PutIDataSegment { .. }
+            | Rpc::DiscardIData { .. }
+            | Rpc::DiscardIDataResponse { .. } => None,
+        }
+    }
+
+    /// Returns the wrapped response, if this is a response.
+    pub fn response(&self) -> Option<&Response> {
+        match self.rpc {
+            Rpc::Response { ref response, .. } => Some(response),
+            Rpc::Request { .. }
+            | Rpc::PutIDataResponse { .. }
+            | Rpc::PutIDataSegment { .. }
+            | Rpc::DiscardIData { .. }
+            | Rpc::DiscardIDataResponse { .. } => None,
+        }
+    }
+
+    /// Returns the coins refunded to the requester, if this is a response carrying a refund.
+    pub fn refund(&self) -> Option<Coins> {
+        match self.rpc {
+            Rpc::Response { refund, .. } => refund,
+            Rpc::Request { .. }
+            | Rpc::PutIDataResponse { .. }
+            | Rpc::PutIDataSegment { .. }
+            | Rpc::DiscardIData { .. }
+            | Rpc::DiscardIDataResponse { .. } => None,
+        }
+    }
+
+    /// Returns the ID of the client message this RPC relates to.
+    pub fn message_id(&self) -> MessageId {
+        match self.rpc {
+            Rpc::Request { message_id, .. }
+            | Rpc::Response { message_id, .. }
+            | Rpc::PutIDataResponse { message_id, .. }
+            | Rpc::PutIDataSegment { message_id, .. }
+            | Rpc::DiscardIData { message_id, .. }
+            | Rpc::DiscardIDataResponse { message_id, .. } => message_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{CryptoRng, Rng};
+    use safe_nd::{ClientFullId, IData, NodeFullId, PubImmutableData, UnpubImmutableData};
+    use unwrap::unwrap;
+
+    fn random_rpcs<R: CryptoRng + Rng>(rng: &mut R) -> Vec<Rpc> {
+        let client = ClientFullId::new_ed25519(rng).public_id().clone();
+        let node = PublicId::Node(NodeFullId::new(rng).public_id().clone());
+        let size = rng.gen_range(0, 1024);
+        let pub_data = IData::Pub(PubImmutableData::new(utils::random_vec(rng, size)));
+        let unpub_data = IData::Unpub(UnpubImmutableData::new(
+            utils::random_vec(rng, size),
+            *client.public_key(),
+        ));
+        let client = PublicId::Client(client);
+        let refund = Some(Coins::from_nano(rng.gen()));
+
+        vec![
+            Rpc::Request {
+                request: Request::PutIData(pub_data.clone()),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+            },
+            Rpc::Request {
+                request: Request::GetIData(*unpub_data.address()),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+            },
+            Rpc::Request {
+                request: Request::DeleteUnpubIData(*unpub_data.address()),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+            },
+            Rpc::Response {
+                response: Response::GetIData(Ok(unpub_data.clone())),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+                refund: None,
+            },
+            Rpc::Response {
+                response: Response::GetIData(Err(NdError::NoSuchData)),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+                refund: None,
+            },
+            Rpc::Response {
+                response: Response::Mutation(Err(NdError::NetworkOther(format!(
+                    "{:x}",
+                    rng.gen::<u64>()
+                )))),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+                refund,
+            },
+            Rpc::PutIDataResponse {
+                result: Ok(()),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+                refund: None,
+                used_space: rng.gen(),
+                max_capacity: rng.gen(),
+            },
+            Rpc::PutIDataResponse {
+                result: Err(utils::section_busy_error()),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+                refund,
+                used_space: rng.gen(),
+                max_capacity: rng.gen(),
+            },
+            Rpc::PutIDataSegment {
+                address: *pub_data.address(),
+                requester: client.clone(),
+                message_id: MessageId::new(),
+                index: rng.gen(),
+                count: rng.gen(),
+                bytes: utils::random_vec(rng, size),
+            },
+            Rpc::DiscardIData {
+                address: *unpub_data.address(),
+                requester: node.clone(),
+                message_id: MessageId::new(),
+            },
+            Rpc::DiscardIDataResponse {
+                requester: node,
+                message_id: MessageId::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn every_rpc_survives_a_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            for rpc in random_rpcs(&mut rng) {
+                let _ = utils::round_trip(&rpc);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_rpcs_are_fitted_or_rejected() {
+        let mut rng = rand::thread_rng();
+        let client = PublicId::Client(ClientFullId::new_ed25519(&mut rng).public_id().clone());
+        let data = IData::Pub(PubImmutableData::new(utils::random_vec(&mut rng, 1024)));
+        let max_size = 256;
+
+        let response = Rpc::Response {
+            response: Response::GetIData(Ok(data.clone())),
+            requester: client.clone(),
+            message_id: MessageId::new(),
+            refund: None,
+        };
+        match unwrap!(response.fit_within(max_size)).as_slice() {
+            [Rpc::Response {
+                response: Response::GetIData(Err(NdError::ExceededSize)),
+                ..
+            }] => (),
+            rpcs => panic!("Unexpected {:?}", rpcs),
+        }
+
+        let put = Rpc::Request {
+            request: Request::PutIData(data.clone()),
+            requester: client.clone(),
+            message_id: MessageId::new(),
+        };
+        let segments = unwrap!(put.fit_within(max_size));
+        assert!(segments.len() > 1);
+        let mut bytes = Vec::new();
+        for segment in &segments {
+            assert!(utils::serialised_size(segment) <= max_size);
+            match segment {
+                Rpc::PutIDataSegment {
+                    bytes: segment_bytes,
+                    ..
+                } => bytes.extend_from_slice(segment_bytes),
+                rpc => panic!("Unexpected {:?}", rpc),
+            }
+        }
+        assert_eq!(bytes, utils::serialise(&data));
+
+        // Responses other than to a `GetIData` are replaced by an error of their own kind.
+        let error = Rpc::Response {
+            response: Response::Mutation(Err(NdError::NetworkOther("x".repeat(1024)))),
+            requester: client.clone(),
+            message_id: MessageId::new(),
+            refund: None,
+        };
+        match unwrap!(error.fit_within(max_size)).as_slice() {
+            [Rpc::Response {
+                response: Response::Mutation(Err(NdError::ExceededSize)),
+                ..
+            }] => (),
+            rpcs => panic!("Unexpected {:?}", rpcs),
+        }
+        let value = Rpc::Response {
+            response: Response::GetADataValue(Ok(utils::random_vec(&mut rng, 1024))),
+            requester: client.clone(),
+            message_id: MessageId::new(),
+            refund: None,
+        };
+        match unwrap!(value.fit_within(max_size)).as_slice() {
+            [Rpc::Response {
+                response: Response::GetADataValue(Err(NdError::ExceededSize)),
+                ..
+            }] => (),
+            rpcs => panic!("Unexpected {:?}", rpcs),
+        }
+
+        // Nor can a segment which is itself too large.
+        let segment = Rpc::PutIDataSegment {
+            address: *data.address(),
+            requester: client.clone(),
+            message_id: MessageId::new(),
+            index: 0,
+            count: 1,
+            bytes: utils::random_vec(&mut rng, 1024),
+        };
+        let size = utils::serialised_size(&segment);
+        assert_eq!(unwrap!(segment.fit_within(max_size).err()), size);
+
+        let get = Rpc::Request {
+            request: Request::GetIData(*data.address()),
+            requester: client,
+            message_id: MessageId::new(),
+        };
+        assert_eq!(unwrap!(get.fit_within(max_size)).len(), 1);
+    }
 }
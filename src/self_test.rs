@@ -0,0 +1,81 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::utils::Instant;
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+/// The outcome of a single step of a vault self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestStep {
+    /// Name of the step, e.g. "put".
+    pub name: &'static str,
+    /// `Ok` if the step passed, otherwise a description of the failure.
+    pub result: Result<(), String>,
+    /// How long the step took.
+    pub latency: Duration,
+}
+
+/// The outcome of a vault self-test, listing each step attempted in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// The steps which were attempted.
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// Returns whether at least one step was attempted and all steps passed.
+    pub fn passed(&self) -> bool {
+        !self.steps.is_empty() && self.steps.iter().all(|step| step.result.is_ok())
+    }
+
+    /// Returns a report for a self-test which couldn't be started.
+    pub(crate) fn not_started(reason: &str) -> Self {
+        Self {
+            steps: vec![SelfTestStep {
+                name: "start",
+                result: Err(reason.to_string()),
+                latency: Duration::default(),
+            }],
+        }
+    }
+
+    /// Runs and times `step`, recording its outcome under `name`.
+    pub(crate) fn run_step<F>(&mut self, name: &'static str, step: F)
+    where
+        F: FnOnce() -> Result<(), String>,
+    {
+        let start = Instant::now();
+        let result = step();
+        self.steps.push(SelfTestStep {
+            name,
+            result,
+            latency: start.elapsed(),
+        });
+    }
+}
+
+impl Display for SelfTestReport {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}",
+            if self.passed() { "passed" } else { "failed" }
+        )?;
+        for step in &self.steps {
+            match step.result {
+                Ok(()) => write!(formatter, ", {}: ok", step.name)?,
+                Err(ref error) => write!(formatter, ", {}: {}", step.name, error)?,
+            }
+            write!(formatter, " ({:?})", step.latency)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use safe_nd::IDataAddress;
+use std::fmt::{self, Display, Formatter};
+
+/// The outcome of re-verifying the chunks held by a vault against their addresses, and of trimming
+/// over-replicated chunks whose metadata it keeps as an elder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageReport {
+    /// Number of chunks which were checked.
+    pub checked: usize,
+    /// Addresses of chunks which couldn't be read back, or whose contents no longer hash to their
+    /// address.  These should be re-fetched from other holders.
+    pub corrupt: Vec<IDataAddress>,
+    /// Number of chunks with more holders than their target, which were deleted from the surplus
+    /// ones.
+    pub trimmed: usize,
+}
+
+impl StorageReport {
+    /// Returns whether every chunk checked was intact.
+    pub fn passed(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+impl Display for StorageReport {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "checked {} chunks, {} corrupt, {} trimmed",
+            self.checked,
+            self.corrupt.len(),
+            self.trimmed
+        )
+    }
+}
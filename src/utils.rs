@@ -7,22 +7,50 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::client_handler::COST_OF_PUT;
-use crate::{rpc::Rpc, vault::Init, Result};
+use crate::{
+    rpc::Rpc, vault::Init, ChunkStoreError, Result, MAINTENANCE, OPERATION_IN_PROGRESS, READ_ONLY,
+    SECTION_BUSY,
+};
 use bincode;
-use log::{error, trace};
-use pickledb::{PickleDb, PickleDbDumpPolicy};
+use log::{error, trace, warn};
+use pickledb::{
+    error::{Error as PickleDbError, ErrorType},
+    PickleDb, PickleDbDumpPolicy,
+};
 use rand::{distributions::Standard, CryptoRng, Rng};
 use safe_nd::{
-    ClientPublicId, Coins, IDataAddress, PublicId, PublicKey, Request, Result as NdResult, XorName,
+    ClientPublicId, Coins, Error as NdError, IDataAddress, PublicId, PublicKey, Request,
+    Result as NdResult, XorName,
 };
+#[cfg(test)]
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::{borrow::Cow, fs, path::Path};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    thread,
+    time::Duration,
+};
 use unwrap::unwrap;
 
+#[cfg(feature = "mock_base")]
+pub(crate) use fake_clock::FakeClock as Instant;
+#[cfg(not(feature = "mock_base"))]
+pub(crate) use std::time::Instant;
+
+// Delay before the first retry of a load which failed transiently, doubled before each further one.
+const LOAD_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Creates or loads the database `db_name` in `db_dir`.  A database which exists but fails to load
+/// with an IO error is retried up to `load_retries` times.
 pub(crate) fn new_db<D: AsRef<Path>, N: AsRef<Path>>(
     db_dir: D,
     db_name: N,
     init_mode: Init,
+    load_retries: u32,
 ) -> Result<PickleDb> {
     let db_path = db_dir.as_ref().join(db_name);
     if init_mode == Init::New {
@@ -35,13 +63,107 @@ pub(crate) fn new_db<D: AsRef<Path>, N: AsRef<Path>>(
         return Ok(db);
     }
     trace!("Loading database at {}", db_path.display());
-    let result = PickleDb::load_bin(db_path.clone(), PickleDbDumpPolicy::AutoDump);
+    let result = retry_load(
+        &db_path,
+        load_retries,
+        |error: &PickleDbError| {
+            db_path.is_file()
+                && match error.get_type() {
+                    ErrorType::Io => true,
+                    ErrorType::Serialization => false,
+                }
+        },
+        || PickleDb::load_bin(db_path.clone(), PickleDbDumpPolicy::AutoDump),
+    );
     if let Err(ref error) = &result {
         error!("Failed to load {}: {}", db_path.display(), error);
     }
     Ok(result?)
 }
 
+/// Calls `load` until it succeeds, fails with an error which `is_transient` rejects, or has been
+/// retried `retries` times, backing off exponentially between attempts.  Returns the last result.
+pub(crate) fn retry_load<T, E, F, L>(
+    path: &Path,
+    retries: u32,
+    is_transient: F,
+    mut load: L,
+) -> std::result::Result<T, E>
+where
+    E: Display,
+    F: Fn(&E) -> bool,
+    L: FnMut() -> std::result::Result<T, E>,
+{
+    let mut backoff = LOAD_RETRY_BACKOFF;
+    for attempt in 0..retries {
+        match load() {
+            Err(ref error) if is_transient(error) => {
+                warn!(
+                    "Failed to load {} (attempt {} of {}), retrying in {:?}: {}",
+                    path.display(),
+                    attempt + 1,
+                    retries + 1,
+                    backoff,
+                    error
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    load()
+}
+
+/// Returns whether `error` may be transient, e.g. a busy or briefly unreachable filesystem, rather
+/// than the file being absent, inaccessible or corrupt.
+pub(crate) fn is_transient_io_error(error: &io::Error) -> bool {
+    match error.kind() {
+        ErrorKind::NotFound
+        | ErrorKind::PermissionDenied
+        | ErrorKind::InvalidInput
+        | ErrorKind::InvalidData
+        | ErrorKind::UnexpectedEof => false,
+        _ => true,
+    }
+}
+
+/// Returns the error sent to clients when we're temporarily unable to handle their request.  See
+/// `SECTION_BUSY` for details.
+pub(crate) fn section_busy_error() -> NdError {
+    NdError::NetworkOther(SECTION_BUSY.to_string())
+}
+
+/// Returns the error sent to clients when we're in maintenance mode.  See `MAINTENANCE` for
+/// details.
+pub(crate) fn maintenance_error() -> NdError {
+    NdError::NetworkOther(MAINTENANCE.to_string())
+}
+
+/// Returns the error sent to clients when we're read-only.  See `READ_ONLY` for details.
+pub(crate) fn read_only_error() -> NdError {
+    NdError::NetworkOther(READ_ONLY.to_string())
+}
+
+/// Returns the error sent to clients when another request for the same data is in progress.  See
+/// `OPERATION_IN_PROGRESS` for details.
+pub(crate) fn operation_in_progress_error() -> NdError {
+    NdError::NetworkOther(OPERATION_IN_PROGRESS.to_string())
+}
+
+/// Converts an error from storing a chunk into the error sent to the client.  Running out of space
+/// is reported as the section being busy, since the client can retry once space is freed or other
+/// holders are chosen.
+pub(crate) fn chunk_store_put_error(error: ChunkStoreError) -> NdError {
+    match error {
+        ChunkStoreError::NotEnoughSpace => {
+            trace!("Not enough space to store chunk; replying section busy");
+            section_busy_error()
+        }
+        error => error.to_string().into(),
+    }
+}
+
 pub(crate) fn random_vec<R: CryptoRng + Rng>(rng: &mut R, size: usize) -> Vec<u8> {
     rng.sample_iter(&Standard).take(size).collect()
 }
@@ -50,6 +172,20 @@ pub(crate) fn serialise<T: Serialize>(data: &T) -> Vec<u8> {
     unwrap!(bincode::serialize(data))
 }
 
+pub(crate) fn serialised_size<T: Serialize>(data: &T) -> u64 {
+    unwrap!(bincode::serialized_size(data))
+}
+
+/// Deserialises the serialised `data`, asserting that the result serialises identically, and
+/// returns it so that callers can compare it with `data` where it's `PartialEq`.
+#[cfg(test)]
+pub(crate) fn round_trip<T: Serialize + DeserializeOwned>(data: &T) -> T {
+    let serialised = serialise(data);
+    let deserialised = unwrap!(bincode::deserialize(&serialised));
+    assert_eq!(serialise(&deserialised), serialised);
+    deserialised
+}
+
 /// Returns the client's public ID, the owner's public ID, or None depending on whether `public_id`
 /// represents a Client, App or Node respectively.
 pub(crate) fn owner(public_id: &PublicId) -> Option<&ClientPublicId> {
@@ -82,9 +218,12 @@ pub(crate) fn own_key(public_id: &PublicId) -> Option<&PublicKey> {
 /// Returns the requester's address.  An App's address is the name of its owner.
 pub(crate) fn requester_address(rpc: &Rpc) -> &XorName {
     match rpc {
-        Rpc::Request { ref requester, .. } | Rpc::Response { ref requester, .. } => {
-            requester.name()
-        }
+        Rpc::Request { ref requester, .. }
+        | Rpc::Response { ref requester, .. }
+        | Rpc::PutIDataResponse { ref requester, .. }
+        | Rpc::PutIDataSegment { ref requester, .. }
+        | Rpc::DiscardIData { ref requester, .. }
+        | Rpc::DiscardIDataResponse { ref requester, .. } => requester.name(),
     }
 }
 
@@ -233,3 +372,30 @@ pub(crate) fn get_refund_for_put<T>(result: &NdResult<T>) -> Option<Coins> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_transient_load_failures_are_retried() {
+        let path = Path::new("state");
+        let fail_then_succeed = |kind: ErrorKind, failures: u32| {
+            let mut attempts = 0;
+            let result = retry_load(path, 2, is_transient_io_error, || {
+                attempts += 1;
+                if attempts <= failures {
+                    Err(io::Error::new(kind, "injected"))
+                } else {
+                    Ok(())
+                }
+            });
+            (result.is_ok(), attempts)
+        };
+
+        assert_eq!(fail_then_succeed(ErrorKind::Interrupted, 1), (true, 2));
+        assert_eq!(fail_then_succeed(ErrorKind::Other, 3), (false, 3));
+        assert_eq!(fail_then_succeed(ErrorKind::NotFound, 1), (false, 1));
+        assert_eq!(fail_then_succeed(ErrorKind::InvalidData, 1), (false, 1));
+    }
+}
@@ -6,33 +6,65 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+#[cfg(feature = "audit")]
+use crate::audit::AuditEvent;
+#[cfg(feature = "mock_base")]
+use crate::rpc::SentRpc;
 use crate::{
-    action::{Action, ConsensusAction},
+    action::{self, Action, ConsensusAction},
     adult::Adult,
-    client_handler::ClientHandler,
+    client_handler::{ClientHandler, RefundReason},
+    client_queue::ClientQueue,
     coins_handler::CoinsHandler,
     data_handler::DataHandler,
+    health::HealthReport,
+    metrics::{Metrics, RoutingEventCounts},
+    op_latency::OpLatencies,
     routing::{event::Event as RoutingEvent, NetworkEvent as ClientEvent, Node},
     rpc::Rpc,
-    utils, Config, Result,
+    self_test::SelfTestReport,
+    storage_check::StorageReport,
+    utils::{self, Instant},
+    watchdog::Watchdog,
+    Config, Error, Result,
 };
 use bincode;
-use crossbeam_channel::{Receiver, Select};
-use log::{error, info, trace, warn};
+use crossbeam_channel::{Receiver, Select, Sender, TrySendError};
+use log::{error, info, trace, warn, LevelFilter};
 use rand::{CryptoRng, Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
-use safe_nd::{NodeFullId, Request, XorName};
+use safe_nd::{
+    ClientFullId, Error as NdError, IData, IDataAddress, MessageId, NodeFullId, NodePublicId,
+    PublicId, Request, Response, Result as NdResult, UnpubImmutableData, XorName,
+};
 use std::borrow::Cow;
 use std::{
     cell::{Cell, RefCell},
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::{self, Display, Formatter},
-    fs,
+    fs::{self, File},
+    io::{self, Write},
     net::SocketAddr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
 };
+#[cfg(feature = "mock_base")]
+use tempdir::TempDir;
 
 const STATE_FILENAME: &str = "state";
+// The state is written here first, then moved over the state file.
+const STATE_TEMP_FILENAME: &str = "state.tmp";
+// Size of the chunk stored and retrieved by a self-test.
+const SELF_TEST_CHUNK_SIZE: usize = 1024;
+// Number of chunks re-verified per pass of the event loop, so that a storage scan doesn't stall it.
+const STORAGE_SCAN_CHUNKS_PER_STEP: usize = 16;
+// Number of consensused actions failing to decode after which, and after each further such number,
+// a version mismatch across the section is reported.
+const UNDECODABLE_CONSENSUS_THRESHOLD: u64 = 10;
+// Number of commands which can be queued via `Vault::command_sender` before further ones are
+// rejected.
+const OWN_COMMAND_CAPACITY: usize = 64;
 
 #[allow(clippy::large_enum_variant)]
 enum State {
@@ -44,6 +76,19 @@ enum State {
     // TODO - remove this
     #[allow(unused)]
     Adult(Adult),
+    // TODO - remove this
+    #[allow(unused)]
+    Infant,
+}
+
+/// Something a vault did while handling events, as returned by `Vault::drain_events`.
+#[cfg(all(test, feature = "mock"))]
+#[derive(Debug)]
+pub(crate) enum CapturedEvent {
+    /// An action handled by the vault.
+    Action(Action),
+    /// An RPC sent by the vault.
+    SentRpc(SentRpc),
 }
 
 /// Specifies whether to try loading cached data from disk, or to just construct a new instance.
@@ -54,10 +99,133 @@ pub(crate) enum Init {
 }
 
 /// Command that the user can send to a running vault to control its execution.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub enum Command {
+    /// Shutdown the vault.  Always handled, as only the process owning the vault's command channel
+    /// can send it.
+    Shutdown,
+    /// A privileged command, only handled if `capability` identifies an operator.
+    Admin {
+        /// The command to run.
+        command: AdminCommand,
+        /// The credentials it was issued with.
+        capability: OperatorCapability,
+    },
+    /// Send to `reply` whether `capability` identifies an operator, e.g. to check a token before
+    /// telling its issuer that a privileged command was accepted.
+    IsOperator {
+        /// The credentials to check.
+        capability: OperatorCapability,
+        /// Channel on which the outcome is sent.
+        reply: Sender<bool>,
+    },
+    /// Log the vault's metrics as a single line, at info level.
+    LogMetrics,
+    /// Run a self-test of the storage path and send the report to `reply`.
+    SelfTest {
+        /// Channel on which the report is sent.
+        reply: Sender<SelfTestReport>,
+    },
+    /// Trim over-replicated chunks back to their target number of holders, then re-verify the
+    /// chunks held by the vault against their addresses, a few at a time between other events, and
+    /// send the report to `reply` once done.
+    VerifyStorage {
+        /// Channel on which the report is sent.
+        reply: Sender<StorageReport>,
+    },
+    /// Apply the tunables of a new config to the running vault.  The config is rejected, and an
+    /// error logged, if it changes any field which only takes effect on restart (see
+    /// `Config::check_reloadable`).  Only handled if `capability` identifies an operator.
+    ReloadConfig {
+        /// The new config.
+        config: Box<Config>,
+        /// The credentials the command was issued with.
+        capability: OperatorCapability,
+    },
+    /// Get the ImmutableData chunk at `address` from `holder` alone, and send that holder's
+    /// response to `reply`, e.g. to compare what each holder stores.
+    GetIDataFromHolder {
+        /// Address of the chunk.
+        address: IDataAddress,
+        /// Name of the holder to query, which must be listed as holding the chunk.
+        holder: XorName,
+        /// Channel on which the holder's response is sent.
+        reply: Sender<NdResult<IData>>,
+    },
+    /// Remove the metadata of the ImmutableData chunk at `address`, e.g. when it only lists holders
+    /// which are long gone, and send to `reply` whether any listed holder could still have served
+    /// the chunk.  Only handled if `capability` identifies an operator.
+    PurgeChunkMetadata {
+        /// Address of the chunk.
+        address: IDataAddress,
+        /// The credentials the command was issued with.
+        capability: OperatorCapability,
+        /// Channel on which the outcome is sent.
+        reply: Sender<Result<bool>>,
+    },
+    /// Pin the ImmutableData chunk at `address` if `pinned` is set, so that it's never trimmed, or
+    /// unpin it otherwise, and send the outcome to `reply`.  Only handled if `capability`
+    /// identifies an operator.
+    SetChunkPinned {
+        /// Address of the chunk.
+        address: IDataAddress,
+        /// Whether to pin or unpin the chunk.
+        pinned: bool,
+        /// The credentials the command was issued with.
+        capability: OperatorCapability,
+        /// Channel on which the outcome is sent.
+        reply: Sender<Result<()>>,
+    },
+}
+
+/// Sending half of a channel created by `bounded_command_channel`.  Sending never blocks.
+#[derive(Clone)]
+pub struct CommandSender {
+    sender: Sender<Command>,
+}
+
+impl CommandSender {
+    /// Queues `command` for the vault.  If the channel is full, the command is rejected and
+    /// returned in `TrySendError::Full`, while those already queued are still handled in order.
+    pub fn send(&self, command: Command) -> std::result::Result<(), TrySendError<Command>> {
+        self.sender.try_send(command)
+    }
+}
+
+/// Creates a channel on which at most `capacity` commands can be queued for a vault, whose
+/// receiver is to be passed to `Vault::new`.  Once the channel is full, further commands are
+/// rejected rather than queued, so a caller flooding the vault with commands can't make its memory
+/// grow without bound.
+pub fn bounded_command_channel(capacity: usize) -> (CommandSender, Receiver<Command>) {
+    let (sender, receiver) = crossbeam_channel::bounded(capacity);
+    (CommandSender { sender }, receiver)
+}
+
+/// Privileged commands, which are only handled when issued by an operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminCommand {
     /// Shutdown the vault
     Shutdown,
+    /// Leave the section and shut down, which is refused while the vault holds immutable data
+    /// that would be lost by leaving.
+    Leave,
+    /// Change the maximum level of messages logged.  Messages are still subject to the logger's
+    /// own filter, so this can't log more than the level the logger was started with.
+    SetLogLevel(LevelFilter),
+    /// Refuse new client gets and mutations with the `MAINTENANCE` error, while staying in the
+    /// section and concluding the ops already in flight.
+    EnterMaintenance,
+    /// Resume handling all client requests after `EnterMaintenance`.
+    ExitMaintenance,
+}
+
+/// The credentials an `AdminCommand` is issued with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperatorCapability {
+    /// Issued by the process owning the vault's command channel, which is always trusted.
+    InProcess,
+    /// Issued with a token, which must be listed in `Config::operator_whitelist`.
+    Token(String),
 }
 
 /// Main vault struct.
@@ -67,14 +235,130 @@ pub struct Vault<R: CryptoRng + Rng> {
     state: State,
     event_receiver: Receiver<RoutingEvent>,
     client_receiver: Receiver<ClientEvent>,
+    client_queue: ClientQueue,
     command_receiver: Receiver<Command>,
+    // A channel for commands sent via `command_sender`, so that the vault can be controlled without
+    // the sender of `command_receiver`.
+    own_command_sender: CommandSender,
+    own_command_receiver: Receiver<Command>,
+    // The config currently applied, updated by `Command::ReloadConfig`.
+    config: Config,
+    // When the metrics were last logged, for `Config::metrics_log_interval`.
+    metrics_logged: Instant,
+    // Reported as our connection info in place of the address we're bound to, if set.
+    advertised_address: Option<SocketAddr>,
+    // Name of our network, shown alongside our ID and stored with our state.
+    network_name: Option<String>,
+    // Tokens accepted as operator credentials for `AdminCommand`s.
+    operator_whitelist: Vec<String>,
+    // Channels awaiting the report of the storage scan in progress.
+    storage_scan_replies: Vec<Sender<StorageReport>>,
+    // Channels awaiting the response to a `Command::GetIDataFromHolder`, by message ID.
+    holder_queries: HashMap<MessageId, Sender<NdResult<IData>>>,
+    // Number of routing events of each kind handled.
+    routing_event_counts: RoutingEventCounts,
+    // Number of consensused actions which failed to decode.
+    undecodable_consensus_actions: u64,
     routing_node: Rc<RefCell<Node>>,
     rng: R,
+    // Size above which immutable data is sent to holders in segments.
+    segment_size_threshold: Option<u64>,
+    // Largest RPC sent, once serialised, if limited.
+    max_rpc_size: Option<u64>,
+    #[cfg(feature = "mock_base")]
+    sent_rpc_observer: Option<Sender<SentRpc>>,
+    // Actions handled and RPCs sent since the last `drain_events`.
+    #[cfg(all(test, feature = "mock"))]
+    captured_events: RefCell<Vec<CapturedEvent>>,
+    // Must stay the last field, so that the directory outlives the databases stored in it.
+    #[cfg(feature = "mock_base")]
+    temp_root: Option<TempDir>,
 }
 
 impl<R: CryptoRng + Rng> Vault<R> {
     /// Create and start vault. This will block until a `Command` to free it is fired.
+    ///
+    /// Commands queued on `command_receiver` are held in memory until handled, so if its sender
+    /// may be used by an untrusted or buggy caller, prefer a bounded channel, e.g. from
+    /// `bounded_command_channel`.
     pub fn new(
+        routing_node: Node,
+        event_receiver: Receiver<RoutingEvent>,
+        client_receiver: Receiver<ClientEvent>,
+        config: &Config,
+        command_receiver: Receiver<Command>,
+        rng: R,
+    ) -> Result<Self> {
+        let vault = Self::create(
+            None,
+            routing_node,
+            event_receiver,
+            client_receiver,
+            config,
+            command_receiver,
+            rng,
+        )?;
+        vault.dump_state()?;
+        info!("{}: Started as {}", vault, vault.state_name());
+        Ok(vault)
+    }
+
+    /// Create and start vault with `id`, e.g. one provisioned by an external key manager, rather
+    /// than the ID from the state file or a freshly generated one.  `id` is written to the state
+    /// file, replacing any ID stored there.  As with `new`, any data in the root dir is loaded, so
+    /// changing a vault's ID should be done with a fresh root dir.
+    pub fn with_id(
+        id: NodeFullId,
+        routing_node: Node,
+        event_receiver: Receiver<RoutingEvent>,
+        client_receiver: Receiver<ClientEvent>,
+        config: &Config,
+        command_receiver: Receiver<Command>,
+        rng: R,
+    ) -> Result<Self> {
+        let vault = Self::create(
+            Some(id),
+            routing_node,
+            event_receiver,
+            client_receiver,
+            config,
+            command_receiver,
+            rng,
+        )?;
+        vault.dump_state()?;
+        info!("{}: Started as {}", vault, vault.state_name());
+        Ok(vault)
+    }
+
+    /// Create a fresh Elder vault for tests, rooted in a new temporary directory which is removed
+    /// when the vault is dropped.  No state file is written, so the vault can't be reloaded.
+    #[cfg(feature = "mock_base")]
+    pub fn new_in_memory(
+        routing_node: Node,
+        event_receiver: Receiver<RoutingEvent>,
+        client_receiver: Receiver<ClientEvent>,
+        command_receiver: Receiver<Command>,
+        rng: R,
+    ) -> Result<Self> {
+        let temp_root = TempDir::new("safe_vault")?;
+        let mut config = Config::default();
+        config.set_root_dir(temp_root.path());
+        let mut vault = Self::create(
+            None,
+            routing_node,
+            event_receiver,
+            client_receiver,
+            &config,
+            command_receiver,
+            rng,
+        )?;
+        vault.temp_root = Some(temp_root);
+        info!("{}: Started as {} (in memory)", vault, vault.state_name());
+        Ok(vault)
+    }
+
+    fn create(
+        id: Option<NodeFullId>,
         routing_node: Node,
         event_receiver: Receiver<RoutingEvent>,
         client_receiver: Receiver<ClientEvent>,
@@ -84,11 +368,37 @@ impl<R: CryptoRng + Rng> Vault<R> {
     ) -> Result<Self> {
         let mut init_mode = Init::Load;
 
-        let (is_elder, id) = Self::read_state(&config)?.unwrap_or_else(|| {
-            let id = NodeFullId::new(&mut rng);
-            init_mode = Init::New;
-            (true, id)
-        });
+        let (is_elder, stored_id, stored_network_name) = Self::read_state(&config)?
+            .map(|(is_elder, id, network_name)| (is_elder, Some(id), network_name))
+            .unwrap_or_else(|| {
+                init_mode = Init::New;
+                (true, None, config.network_name().cloned())
+            });
+        let id = match (id, stored_id) {
+            (Some(id), Some(stored_id)) => {
+                if id.public_id() != stored_id.public_id() {
+                    warn!(
+                        "Replacing stored ID {} with the supplied {}. Data already in the root dir \
+                         {} was stored under the old ID.",
+                        stored_id.public_id().name(),
+                        id.public_id().name(),
+                        config.root_dir()?.display()
+                    );
+                }
+                id
+            }
+            (Some(id), None) | (None, Some(id)) => id,
+            (None, None) => NodeFullId::new(&mut rng),
+        };
+        if stored_network_name.as_ref() != config.network_name() {
+            warn!(
+                "Loading state of network {:?} as a vault of network {:?}. Check the root dir {} \
+                 belongs to this network.",
+                stored_network_name,
+                config.network_name(),
+                config.root_dir()?.display()
+            );
+        }
 
         #[cfg(feature = "mock_parsec")]
         {
@@ -101,6 +411,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
 
         let root_dir = config.root_dir()?;
         let root_dir = root_dir.as_path();
+        let advertised_address = config.advertised_address()?;
 
         let routing_node = Rc::new(RefCell::new(routing_node));
 
@@ -119,7 +430,12 @@ impl<R: CryptoRng + Rng> Vault<R> {
                 &total_used_space,
                 init_mode,
             )?;
-            let coins_handler = CoinsHandler::new(id.public_id().clone(), root_dir, init_mode)?;
+            let coins_handler = CoinsHandler::new(
+                id.public_id().clone(),
+                root_dir,
+                init_mode,
+                config.load_retries(),
+            )?;
             State::Elder {
                 client_handler,
                 data_handler,
@@ -135,22 +451,47 @@ impl<R: CryptoRng + Rng> Vault<R> {
             unimplemented!();
         };
 
+        let (own_command_sender, own_command_receiver) =
+            bounded_command_channel(OWN_COMMAND_CAPACITY);
         let vault = Self {
             id,
             root_dir: root_dir.to_path_buf(),
             state,
             event_receiver,
             client_receiver,
+            client_queue: ClientQueue::default(),
             command_receiver,
+            own_command_sender,
+            own_command_receiver,
+            config: config.clone(),
+            metrics_logged: Instant::now(),
+            advertised_address,
+            network_name: config.network_name().cloned(),
+            operator_whitelist: config.operator_whitelist().to_vec(),
+            storage_scan_replies: Vec::new(),
+            holder_queries: HashMap::new(),
+            routing_event_counts: Default::default(),
+            undecodable_consensus_actions: 0,
             routing_node,
             rng,
+            segment_size_threshold: config.segment_size_threshold(),
+            max_rpc_size: config.max_rpc_size(),
+            #[cfg(feature = "mock_base")]
+            sent_rpc_observer: None,
+            #[cfg(all(test, feature = "mock"))]
+            captured_events: Default::default(),
+            #[cfg(feature = "mock_base")]
+            temp_root: None,
         };
-        vault.dump_state()?;
         Ok(vault)
     }
 
-    /// Returns our connection info.
+    /// Returns our connection info: the configured advertised address if there is one, otherwise
+    /// the address we're bound to.
     pub fn our_connection_info(&mut self) -> Result<SocketAddr> {
+        if let Some(address) = self.advertised_address {
+            return Ok(address);
+        }
         self.routing_node
             .borrow_mut()
             .our_connection_info()
@@ -158,16 +499,253 @@ impl<R: CryptoRng + Rng> Vault<R> {
     }
 
     #[cfg(feature = "mock_parsec")]
-    /// Returns whether routing node is in elder state.
+    /// Returns whether we're an elder, which is only the case once both Routing and our local state
+    /// agree on it.  Routing's view may differ from ours while we're joining or while a promotion
+    /// is being processed, during which this returns false, so that `is_elder` and `is_adult` are
+    /// never both true.
     pub fn is_elder(&mut self) -> bool {
-        self.routing_node.borrow().is_elder()
+        let routing_is_elder = self.routing_node.borrow().is_elder();
+        if routing_is_elder != self.is_elder_state() {
+            trace!(
+                "{}: Routing reports is_elder == {}, but our local state is {}",
+                self,
+                routing_is_elder,
+                self.state_name()
+            );
+        }
+        routing_is_elder && self.is_elder_state()
+    }
+
+    /// Returns whether our local state is Adult.
+    pub fn is_adult(&self) -> bool {
+        match self.state {
+            State::Adult(_) => true,
+            State::Elder { .. } | State::Infant => false,
+        }
+    }
+
+    /// Returns whether our local state is Infant.
+    pub fn is_infant(&self) -> bool {
+        match self.state {
+            State::Infant => true,
+            State::Elder { .. } | State::Adult(_) => false,
+        }
+    }
+
+    /// Returns our public ID.
+    pub fn public_id(&self) -> &NodePublicId {
+        self.id.public_id()
+    }
+
+    /// Returns the name of our local state, for logging.
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            State::Elder { .. } => "Elder",
+            State::Adult(_) => "Adult",
+            State::Infant => "Infant",
+        }
+    }
+
+    /// Returns the reliability score of each ImmutableData holder we've heard from.  This is empty
+    /// unless we're an Elder.
+    pub fn holder_scores(&self) -> BTreeMap<XorName, i64> {
+        self.data_handler()
+            .map(DataHandler::holder_scores)
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of ImmutableData responses we've rejected from each node which wasn't
+    /// queried for the op they claimed to answer, as potential spoofing.  This is empty unless
+    /// we're an Elder.
+    pub fn unexpected_idata_responses(&self) -> BTreeMap<XorName, u64> {
+        self.data_handler()
+            .map(DataHandler::unexpected_idata_responses)
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of ImmutableData get responses we've received after another holder's had
+    /// already answered the client.  This is zero unless we're an Elder.
+    pub fn redundant_idata_responses(&self) -> u64 {
+        self.data_handler()
+            .map(DataHandler::redundant_idata_responses)
+            .unwrap_or(0)
+    }
+
+    /// Writes the metadata of every ImmutableData chunk we know of, i.e. which holders store it, to
+    /// `path`.  Returns the number of chunks written.
+    pub fn export_idata_metadata(&self, path: &Path) -> Result<usize> {
+        self.data_handler()
+            .ok_or(Error::NotElder)?
+            .export_idata_metadata(path)
+    }
+
+    /// Merges ImmutableData chunk metadata written by `export_idata_metadata` into ours, e.g. when
+    /// migrating a vault.  Holders are added to those already known for each chunk, never removed.
+    /// Returns the number of chunks read.
+    pub fn import_idata_metadata(&mut self, path: &Path) -> Result<usize> {
+        self.data_handler_mut()
+            .ok_or(Error::NotElder)?
+            .import_idata_metadata(path)
+    }
+
+    /// Sets a channel on which each client's ImmutableData op is reported as it concludes, with
+    /// its requester, address and outcome but never the chunk's contents.  Events are dropped
+    /// rather than blocking if the channel is full.  Only Elders handle client ops.
+    #[cfg(feature = "audit")]
+    pub fn set_audit_sink(&mut self, sink: Sender<AuditEvent>) -> Result<()> {
+        self.data_handler_mut()
+            .ok_or(Error::NotElder)?
+            .set_audit_sink(sink);
+        Ok(())
+    }
+
+    /// Returns the number of put refunds made to clients for each reason.  This is empty unless
+    /// we're an Elder.
+    pub fn refunds(&self) -> BTreeMap<RefundReason, u64> {
+        self.client_handler()
+            .map(ClientHandler::refunds)
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of responses held for client requests which haven't reached us, and which
+    /// so far have gone nowhere.  This is zero unless we're an Elder.
+    pub fn unclaimed_client_responses(&self) -> usize {
+        self.client_handler()
+            .map(ClientHandler::unclaimed_responses)
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of responses dropped because their client disconnected and didn't
+    /// reconnect in time to receive them.  This is zero unless we're an Elder.
+    pub fn expired_client_responses(&self) -> u64 {
+        self.client_handler()
+            .map(ClientHandler::expired_responses)
+            .unwrap_or(0)
+    }
+
+    /// Returns latency histograms of the ImmutableData ops which have concluded.  These are empty
+    /// unless we're an Elder.
+    pub fn idata_op_latencies(&self) -> OpLatencies {
+        self.data_handler()
+            .map(|data_handler| data_handler.idata_op_latencies().clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns each open client connection, along with the client's ID once it has completed the
+    /// handshake.  This is empty unless we're an Elder.
+    pub fn connected_clients(&self) -> Vec<(SocketAddr, Option<PublicId>)> {
+        self.client_handler()
+            .map(ClientHandler::connected_clients)
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of ImmutableData ops currently in flight.  This is zero unless we're an
+    /// Elder.
+    pub fn in_flight_ops(&self) -> usize {
+        self.data_handler()
+            .map(DataHandler::in_flight_ops)
+            .unwrap_or(0)
+    }
+
+    /// Returns our counters and histograms.  Other than the ops in flight, these are empty unless
+    /// we're an Elder.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            in_flight_ops: self.in_flight_ops(),
+            unclaimed_client_responses: self.unclaimed_client_responses(),
+            expired_client_responses: self.expired_client_responses(),
+            refunds: self.refunds(),
+            unexpected_idata_responses: self.unexpected_idata_responses().values().sum(),
+            redundant_idata_responses: self.redundant_idata_responses(),
+            idata_op_latencies: self.idata_op_latencies(),
+            routing_events: self.routing_event_counts,
+            undecodable_consensus_actions: self.undecodable_consensus_actions,
+        }
+    }
+
+    /// Returns a snapshot of our health without blocking or changing any state, for embedders
+    /// which hold the vault directly rather than going through its command channel.  Vaults which
+    /// aren't Elders hold no data, so report no ops, no used space and no reachable db.
+    pub fn health_check(&self) -> HealthReport {
+        let data_handler = self.data_handler();
+        HealthReport {
+            role: self.state_name(),
+            in_flight_ops: self.in_flight_ops(),
+            used_space: data_handler.map(DataHandler::used_space).unwrap_or(0),
+            oldest_op_age: data_handler.and_then(DataHandler::oldest_idata_op_age),
+            db_reachable: data_handler
+                .map(DataHandler::is_idata_metadata_reachable)
+                .unwrap_or(false),
+        }
+    }
+
+    /// Returns a sender for commands to the vault, e.g. to shut down a vault running on another
+    /// thread.  These are handled exactly as those received on the channel the vault was created
+    /// with.  At most 64 commands can be queued; further ones are rejected until some are handled.
+    pub fn command_sender(&self) -> CommandSender {
+        self.own_command_sender.clone()
+    }
+
+    /// Sets a channel on which every RPC we send is reported, so that tests can assert where RPCs
+    /// were sent.
+    #[cfg(feature = "mock_base")]
+    pub fn set_sent_rpc_observer(&mut self, observer: Sender<SentRpc>) {
+        self.sent_rpc_observer = Some(observer);
+    }
+
+    /// Returns every action handled and RPC sent since this was last called, in the order they
+    /// happened.
+    #[cfg(all(test, feature = "mock"))]
+    pub(crate) fn drain_events(&mut self) -> Vec<CapturedEvent> {
+        self.captured_events.replace(Vec::new())
+    }
+
+    /// Stores a small randomly-generated chunk, reads it back and verifies it, then deletes it,
+    /// reporting the outcome and latency of each step.  The chunk is owned by a throwaway key so it
+    /// can't collide with client data.  Only Elders hold data, so this fails on other vaults.
+    pub fn self_test(&mut self) -> SelfTestReport {
+        let owner = *ClientFullId::new_ed25519(&mut self.rng)
+            .public_id()
+            .public_key();
+        let data = IData::Unpub(UnpubImmutableData::new(
+            utils::random_vec(&mut self.rng, SELF_TEST_CHUNK_SIZE),
+            owner,
+        ));
+        let report = match self.data_handler_mut() {
+            Some(data_handler) => data_handler.self_test(&data),
+            None => SelfTestReport::not_started("Only Elders can run a self-test"),
+        };
+        if report.passed() {
+            info!("{}: Self-test {}", self, report);
+        } else {
+            warn!("{}: Self-test {}", self, report);
+        }
+        report
+    }
+
+    fn is_elder_state(&self) -> bool {
+        match self.state {
+            State::Elder { .. } => true,
+            State::Adult(_) | State::Infant => false,
+        }
     }
 
     /// Runs the main event loop. Blocks until the vault is terminated.
     // FIXME: remove when https://github.com/crossbeam-rs/crossbeam/issues/404 is resolved
     #[allow(clippy::zero_ptr, clippy::drop_copy)]
     pub fn run(&mut self) {
+        let watchdog = self
+            .config
+            .watchdog_interval()
+            .map(|interval| Watchdog::start(interval, self.config.watchdog_abort()));
         loop {
+            if let Some(ref watchdog) = watchdog {
+                watchdog.heartbeat();
+            }
+            self.remove_stale_reassemblies();
+            self.expire_awaiting_reconnect();
+            self.log_metrics_if_due();
+            let scanning = self.continue_storage_scan();
             let mut sel = Select::new();
 
             let mut r_node = self.routing_node.borrow_mut();
@@ -175,8 +753,32 @@ impl<R: CryptoRng + Rng> Vault<R> {
             let routing_event_rx_idx = sel.recv(&self.event_receiver);
             let client_network_rx_idx = sel.recv(&self.client_receiver);
             let command_rx_idx = sel.recv(&self.command_receiver);
+            let own_command_rx_idx = sel.recv(&self.own_command_receiver);
 
-            let selected_operation = sel.ready();
+            // Don't block while a storage scan has more chunks to check, nor for longer than the
+            // watchdog allows between heartbeats or than the metrics are due to be logged.
+            let max_idle = match (
+                watchdog.as_ref().map(Watchdog::max_idle),
+                self.config.metrics_log_interval(),
+            ) {
+                (Some(watchdog_idle), Some(metrics_interval)) => {
+                    Some(cmp::min(watchdog_idle, metrics_interval))
+                }
+                (watchdog_idle, metrics_interval) => watchdog_idle.or(metrics_interval),
+            };
+            let selected_operation = if scanning {
+                match sel.try_ready() {
+                    Ok(selected_operation) => selected_operation,
+                    Err(_) => continue,
+                }
+            } else if let Some(max_idle) = max_idle {
+                match sel.ready_timeout(max_idle) {
+                    Ok(selected_operation) => selected_operation,
+                    Err(_) => continue,
+                }
+            } else {
+                sel.ready()
+            };
             drop(r_node);
 
             match selected_operation {
@@ -185,7 +787,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
                         Ok(ev) => ev,
                         Err(e) => panic!("FIXME: {:?}", e),
                     };
-                    self.step_client(event);
+                    self.step_client_events(event);
                 }
                 idx if idx == routing_event_rx_idx => {
                     let event = match self.event_receiver.recv() {
@@ -194,13 +796,13 @@ impl<R: CryptoRng + Rng> Vault<R> {
                     };
                     self.step_routing(event);
                 }
-                idx if idx == command_rx_idx => {
-                    let command = match self.command_receiver.recv() {
+                idx if idx == command_rx_idx || idx == own_command_rx_idx => {
+                    let command = match self.command_receiver_at(idx, command_rx_idx).recv() {
                         Ok(ev) => ev,
                         Err(e) => panic!("FIXME: {:?}", e),
                     };
-                    match command {
-                        Command::Shutdown => break,
+                    if self.handle_command(command) {
+                        break;
                     }
                 }
                 idx => {
@@ -227,6 +829,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
             let routing_event_rx_idx = sel.recv(&self.event_receiver);
             let client_network_rx_idx = sel.recv(&self.client_receiver);
             let command_rx_idx = sel.recv(&self.command_receiver);
+            let own_command_rx_idx = sel.recv(&self.own_command_receiver);
 
             if let Ok(selected_operation) = sel.try_ready() {
                 drop(r_node);
@@ -237,7 +840,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
                             Ok(ev) => ev,
                             Err(e) => panic!("FIXME: {:?}", e),
                         };
-                        self.step_client(event);
+                        self.step_client_events(event);
                         _processed = true;
                     }
                     idx if idx == routing_event_rx_idx => {
@@ -248,14 +851,12 @@ impl<R: CryptoRng + Rng> Vault<R> {
                         self.step_routing(event);
                         _processed = true;
                     }
-                    idx if idx == command_rx_idx => {
-                        let command = match self.command_receiver.recv() {
+                    idx if idx == command_rx_idx || idx == own_command_rx_idx => {
+                        let command = match self.command_receiver_at(idx, command_rx_idx).recv() {
                             Ok(ev) => ev,
                             Err(e) => panic!("FIXME: {:?}", e),
                         };
-                        match command {
-                            Command::Shutdown => (),
-                        }
+                        let _ = self.handle_command(command);
                         _processed = true;
                     }
                     idx => {
@@ -274,24 +875,349 @@ impl<R: CryptoRng + Rng> Vault<R> {
             }
         }
 
+        self.remove_stale_reassemblies();
+        self.expire_awaiting_reconnect();
+        self.log_metrics_if_due();
+        if self.continue_storage_scan() {
+            _processed = true;
+        }
         _processed
     }
 
+    /// Handles `command` as if it had been received over the command channel.  Returns whether the
+    /// vault should stop running.
+    pub fn handle_command(&mut self, command: Command) -> bool {
+        match command {
+            Command::Shutdown => self.handle_admin_command(AdminCommand::Shutdown),
+            Command::Admin {
+                command,
+                capability,
+            } => {
+                if !self.is_operator(&capability) {
+                    warn!(
+                        "{}: Rejecting {:?} issued without operator credentials",
+                        self, command
+                    );
+                    return false;
+                }
+                self.handle_admin_command(command)
+            }
+            Command::ReloadConfig { config, capability } => {
+                if !self.is_operator(&capability) {
+                    warn!(
+                        "{}: Rejecting config reload issued without operator credentials",
+                        self
+                    );
+                } else if let Err(error) = self.reload_config(*config) {
+                    error!("{}: Failed to reload config: {}", self, error);
+                }
+                false
+            }
+            Command::IsOperator { capability, reply } => {
+                let _ = reply.send(self.is_operator(&capability));
+                false
+            }
+            Command::LogMetrics => {
+                self.log_metrics();
+                false
+            }
+            Command::SelfTest { reply } => {
+                let _ = reply.send(self.self_test());
+                false
+            }
+            Command::VerifyStorage { reply } => {
+                match self.data_handler_mut() {
+                    Some(data_handler) => {
+                        data_handler.start_storage_scan();
+                        self.storage_scan_replies.push(reply);
+                    }
+                    None => {
+                        warn!("{}: Only Elders hold chunks to verify", self);
+                        let _ = reply.send(StorageReport::default());
+                    }
+                }
+                false
+            }
+            Command::GetIDataFromHolder {
+                address,
+                holder,
+                reply,
+            } => {
+                let requester = PublicId::Node(self.id.public_id().clone());
+                let message_id = MessageId::new();
+                let action = match self.data_handler_mut() {
+                    Some(data_handler) => {
+                        data_handler.get_idata_from_holder(requester, address, holder, message_id)
+                    }
+                    None => {
+                        let _ = reply.send(Err(NdError::NetworkOther(
+                            "Only Elders can query holders".to_string(),
+                        )));
+                        return false;
+                    }
+                };
+                let _ = self.holder_queries.insert(message_id, reply);
+                self.drain_actions(action);
+                false
+            }
+            Command::PurgeChunkMetadata {
+                address,
+                capability,
+                reply,
+            } => {
+                let result = if self.is_operator(&capability) {
+                    self.data_handler_mut()
+                        .ok_or(Error::NotElder)
+                        .and_then(|data_handler| data_handler.purge_idata_metadata(address))
+                } else {
+                    warn!(
+                        "{}: Rejecting purge of {:?} issued without operator credentials",
+                        self, address
+                    );
+                    Err(Error::NetworkData(NdError::AccessDenied))
+                };
+                let _ = reply.send(result);
+                false
+            }
+            Command::SetChunkPinned {
+                address,
+                pinned,
+                capability,
+                reply,
+            } => {
+                let result = if self.is_operator(&capability) {
+                    self.data_handler_mut()
+                        .ok_or(Error::NotElder)
+                        .and_then(|data_handler| {
+                            if pinned {
+                                data_handler.pin_idata(address)
+                            } else {
+                                data_handler.unpin_idata(address)
+                            }
+                        })
+                } else {
+                    warn!(
+                        "{}: Rejecting pinning of {:?} issued without operator credentials",
+                        self, address
+                    );
+                    Err(Error::NetworkData(NdError::AccessDenied))
+                };
+                let _ = reply.send(result);
+                false
+            }
+        }
+    }
+
+    /// Applies the tunables of `config` to the running vault, or returns `Error::InvalidConfig` if
+    /// it changes any field which only takes effect on restart.
+    pub fn reload_config(&mut self, config: Config) -> Result<()> {
+        self.config.check_reloadable(&config)?;
+        self.operator_whitelist = config.operator_whitelist().to_vec();
+        self.segment_size_threshold = config.segment_size_threshold();
+        self.max_rpc_size = config.max_rpc_size();
+        if let State::Elder {
+            ref mut client_handler,
+            ref mut data_handler,
+            ..
+        } = self.state
+        {
+            client_handler.apply_config(&config);
+            data_handler.apply_config(&config);
+        }
+        self.config = config;
+        info!("{}: Reloaded config", self);
+        Ok(())
+    }
+
+    // Returns the command channel selected as `idx`: ours if it isn't `command_rx_idx`.
+    fn command_receiver_at(&self, idx: usize, command_rx_idx: usize) -> &Receiver<Command> {
+        if idx == command_rx_idx {
+            &self.command_receiver
+        } else {
+            &self.own_command_receiver
+        }
+    }
+
+    fn set_maintenance(&mut self, maintenance: bool) {
+        if let Some(client_handler) = self.client_handler_mut() {
+            client_handler.set_maintenance(maintenance);
+        }
+    }
+
+    fn log_metrics(&mut self) {
+        info!("{}: Metrics: {}", self, self.metrics());
+        self.metrics_logged = Instant::now();
+    }
+
+    fn log_metrics_if_due(&mut self) {
+        if let Some(interval) = self.config.metrics_log_interval() {
+            if self.metrics_logged.elapsed() >= interval {
+                self.log_metrics();
+            }
+        }
+    }
+
+    // Discards any segmented transfers to our holder which have stalled, telling the data handlers
+    // that their puts failed.
+    fn remove_stale_reassemblies(&mut self) {
+        let actions = match self.data_handler_mut() {
+            Some(data_handler) => data_handler.remove_stale_reassemblies(),
+            None => return,
+        };
+        for action in actions {
+            self.drain_actions(Some(action));
+        }
+    }
+
+    // Drops responses held for disconnected clients which haven't reconnected in time, and cancels
+    // the gets still being done on their behalf.
+    fn expire_awaiting_reconnect(&mut self) {
+        let expired = match self.client_handler_mut() {
+            Some(client_handler) => client_handler.expire_awaiting_reconnect(),
+            None => return,
+        };
+        if let Some(data_handler) = self.data_handler_mut() {
+            for client in &expired {
+                let _ = data_handler.cancel_idata_gets_for(client);
+            }
+        }
+    }
+
+    // Re-verifies the next few chunks of the storage scan in progress, sending the report once it
+    // completes.  Returns whether a scan was in progress.
+    fn continue_storage_scan(&mut self) -> bool {
+        if self.storage_scan_replies.is_empty() {
+            return false;
+        }
+        let (actions, report) = match self.data_handler_mut() {
+            Some(data_handler) => data_handler.continue_storage_scan(STORAGE_SCAN_CHUNKS_PER_STEP),
+            None => (Vec::new(), Some(StorageReport::default())),
+        };
+        for action in actions {
+            self.drain_actions(Some(action));
+        }
+        if let Some(report) = report {
+            if report.passed() {
+                info!("{}: Storage scan {}", self, report);
+            } else {
+                warn!("{}: Storage scan {}", self, report);
+            }
+            for reply in self.storage_scan_replies.drain(..) {
+                let _ = reply.send(report.clone());
+            }
+        }
+        true
+    }
+
+    // Shuts down if no held chunks would be lost by doing so.  There's no mechanism yet to
+    // re-replicate our chunks to other holders, so any held chunk blocks leaving.  Returns whether
+    // the vault should stop running.
+    fn leave(&mut self) -> bool {
+        let held_chunks = self
+            .data_handler_mut()
+            .map(|data_handler| data_handler.held_idata_count())
+            .unwrap_or(0);
+        if held_chunks > 0 {
+            warn!(
+                "{}: Refusing to leave while holding {} immutable data chunks which can't be \
+                 re-replicated",
+                self, held_chunks
+            );
+            return false;
+        }
+        info!("{}: Leaving the section", self);
+        self.cancel_pending_ops();
+        true
+    }
+
+    // Tells the clients of any in-flight ops to retry, rather than leaving them to time out.
+    fn cancel_pending_ops(&mut self) {
+        let actions = match self.data_handler_mut() {
+            Some(data_handler) => data_handler.cancel_pending_ops(),
+            None => return,
+        };
+        if !actions.is_empty() {
+            info!("{}: Cancelling {} pending ops", self, actions.len());
+        }
+        for action in actions {
+            self.drain_actions(Some(action));
+        }
+    }
+
+    // Runs an `AdminCommand` whose issuer has been checked.  Returns whether the vault should stop
+    // running.
+    fn handle_admin_command(&mut self, command: AdminCommand) -> bool {
+        match command {
+            AdminCommand::Shutdown => {
+                self.cancel_pending_ops();
+                true
+            }
+            AdminCommand::Leave => self.leave(),
+            AdminCommand::SetLogLevel(level) => {
+                info!("{}: Setting log level to {}", self, level);
+                log::set_max_level(level);
+                false
+            }
+            AdminCommand::EnterMaintenance => {
+                info!("{}: Entering maintenance", self);
+                self.set_maintenance(true);
+                false
+            }
+            AdminCommand::ExitMaintenance => {
+                info!("{}: Exiting maintenance", self);
+                self.set_maintenance(false);
+                false
+            }
+        }
+    }
+
+    fn is_operator(&self, capability: &OperatorCapability) -> bool {
+        match capability {
+            OperatorCapability::InProcess => true,
+            OperatorCapability::Token(token) => self.operator_whitelist.contains(token),
+        }
+    }
+
     fn step_routing(&mut self, event: RoutingEvent) {
-        let mut maybe_action = self.handle_routing_event(event);
-        while let Some(action) = maybe_action {
-            maybe_action = self.handle_action(action);
+        let maybe_action = self.handle_routing_event(event);
+        self.drain_actions(maybe_action);
+    }
+
+    // Queues `event` along with any other client events already received, then handles them all in
+    // priority order.
+    fn step_client_events(&mut self, event: ClientEvent) {
+        self.client_queue.push(event);
+        while let Ok(event) = self.client_receiver.try_recv() {
+            self.client_queue.push(event);
+        }
+        while let Some(event) = self.client_queue.pop() {
+            self.step_client(event);
         }
     }
 
     fn step_client(&mut self, event: ClientEvent) {
-        let mut maybe_action = self.handle_client_event(event);
-        while let Some(action) = maybe_action {
-            maybe_action = self.handle_action(action);
+        let maybe_action = self.handle_client_event(event);
+        self.drain_actions(maybe_action);
+    }
+
+    // Handles `maybe_action` and every follow-up action it produces, abandoning the chain if it
+    // exceeds `Config::max_action_chain` rather than letting a handler loop forever.
+    fn drain_actions(&mut self, maybe_action: Option<Action>) {
+        let max_depth = self.config.max_action_chain();
+        if let Err(chain) =
+            action::drain(maybe_action, max_depth, |action| self.handle_action(action))
+        {
+            error!(
+                "{}: Abandoning chain of actions longer than {}, dropping its last: {}",
+                self,
+                max_depth,
+                chain.join(" -> ")
+            );
         }
     }
 
     fn handle_routing_event(&mut self, event: RoutingEvent) -> Option<Action> {
+        self.count_routing_event(&event);
         match event {
             RoutingEvent::Consensus(custom_event) => {
                 match bincode::deserialize::<ConsensusAction>(&custom_event) {
@@ -299,8 +1225,22 @@ impl<R: CryptoRng + Rng> Vault<R> {
                         let client_handler = self.client_handler_mut()?;
                         client_handler.handle_consensused_action(consensus_action)
                     }
-                    Err(e) => {
-                        error!("Invalid ConsensusAction passed from Routing: {:?}", e);
+                    Err(error) => {
+                        self.undecodable_consensus_actions += 1;
+                        warn!(
+                            "{}: Invalid ConsensusAction of {} bytes passed from Routing: {:?}",
+                            self,
+                            custom_event.len(),
+                            error
+                        );
+                        if self.undecodable_consensus_actions % UNDECODABLE_CONSENSUS_THRESHOLD == 0
+                        {
+                            error!(
+                                "{}: {} consensused actions have failed to decode. Check that \
+                                 the section's members all run the same version.",
+                                self, self.undecodable_consensus_actions
+                            );
+                        }
                         None
                     }
                 }
@@ -310,6 +1250,20 @@ impl<R: CryptoRng + Rng> Vault<R> {
         }
     }
 
+    fn count_routing_event(&mut self, event: &RoutingEvent) {
+        let counts = &mut self.routing_event_counts;
+        let count = match event {
+            RoutingEvent::Promoted { .. } => &mut counts.promoted,
+            RoutingEvent::Connected { .. } => &mut counts.connected,
+            RoutingEvent::MemberJoined { .. } => &mut counts.member_joined,
+            RoutingEvent::MemberLeft { .. } => &mut counts.member_left,
+            RoutingEvent::MessageReceived { .. } => &mut counts.message_received,
+            RoutingEvent::Consensus { .. } => &mut counts.consensus,
+            _ => &mut counts.other,
+        };
+        *count += 1;
+    }
+
     fn handle_client_event(&mut self, event: ClientEvent) -> Option<Action> {
         use ClientEvent::*;
 
@@ -319,85 +1273,169 @@ impl<R: CryptoRng + Rng> Vault<R> {
         match event {
             ConnectedTo { peer } => client_handler.handle_new_connection(peer.peer_addr()),
             ConnectionFailure { peer, .. } => {
-                client_handler.handle_connection_failure(peer.peer_addr());
+                if let Some(client) = client_handler.handle_connection_failure(peer.peer_addr()) {
+                    let _ = self.data_handler_mut()?.cancel_idata_gets_for(&client);
+                }
             }
             NewMessage { peer, msg } => {
                 return client_handler.handle_client_message(peer.peer_addr(), &msg, &mut rng);
             }
-            SentUserMessage { peer, .. } => {
-                trace!(
-                    "{}: Succesfully sent message to: {}",
-                    self,
-                    peer.peer_addr()
-                );
+            SentUserMessage { peer, token, .. } => {
+                client_handler.handle_sent_user_message(peer.peer_addr(), token);
             }
-            UnsentUserMessage { peer, .. } => {
-                info!("{}: Not sent message to: {}", self, peer.peer_addr());
+            UnsentUserMessage { peer, token, .. } => {
+                if let Some(client) =
+                    client_handler.handle_unsent_user_message(peer.peer_addr(), token)
+                {
+                    let _ = self.data_handler_mut()?.cancel_idata_gets_for(&client);
+                }
             }
             BootstrapFailure | BootstrappedTo { .. } => {
                 error!("unexpected bootstrapping client event")
             }
             Finish => {
                 info!("{}: Received Finish event", self);
+                let clients = client_handler.handle_transport_finished();
+                let data_handler = self.data_handler_mut()?;
+                for client in &clients {
+                    let _ = data_handler.cancel_idata_gets_for(client);
+                }
             }
         }
         None
     }
 
-    #[allow(dead_code)]
+    // Votes for `action`, which is only handled once Routing reports consensus on it (see
+    // `handle_routing_event`).
     fn vote_for_action(&mut self, action: &ConsensusAction) -> Option<Action> {
+        if !self.is_elder_state() {
+            warn!(
+                "{}: Cannot vote for {:?}. Vault is not an elder",
+                self, action
+            );
+            return None;
+        }
         self.routing_node
             .borrow_mut()
             .vote_for(utils::serialise(&action));
         None
     }
 
-    fn handle_action(&mut self, action: Action) -> Option<Action> {
+    // Handles `action`, returning its follow-ups for `drain_actions` to handle in turn.
+    fn handle_action(&mut self, action: Action) -> Vec<Action> {
         trace!("{} handle action {:?}", self, action);
+        #[cfg(all(test, feature = "mock"))]
+        self.captured_events
+            .borrow_mut()
+            .push(CapturedEvent::Action(action.clone()));
         use Action::*;
-        match action {
-            // Bypass client requests
-            // ConsensusVote(action) => self.vote_for_action(&action),
-            ConsensusVote(action) => self.client_handler_mut()?.handle_consensused_action(action),
+        let next_action = match action {
+            ConsensusVote(action) => self.vote_for_action(&action),
             ForwardClientRequest(rpc) => self.forward_client_request(rpc),
             ProxyClientRequest(rpc) => self.proxy_client_request(rpc),
             RespondToOurDataHandlers { sender, rpc } => {
-                // TODO - once Routing is integrated, we'll construct the full message to send
-                //        onwards, and then if we're also part of the data handlers, we'll call that
-                //        same handler which Routing will call after receiving a message.
-
-                self.data_handler_mut()?.handle_vault_rpc(sender, rpc)
-            }
-            RespondToClientHandlers { sender, rpc } => {
-                let client_name = utils::requester_address(&rpc);
-
-                // TODO - once Routing is integrated, we'll construct the full message to send
-                //        onwards, and then if we're also part of the client handlers, we'll call that
-                //        same handler which Routing will call after receiving a message.
-
-                if self.self_is_handler_for(client_name) {
-                    return self.client_handler_mut()?.handle_vault_rpc(sender, rpc);
-                }
-                None
+                self.respond_to_our_data_handlers(sender, rpc)
             }
+            RespondToClientHandlers { sender, rpc } => self.respond_to_client_handlers(sender, rpc),
             SendToPeers {
                 sender,
                 targets,
                 rpc,
-            } => {
-                let mut next_action = None;
-                for target in targets {
-                    if target == *self.id.public_id().name() {
-                        next_action = self
-                            .data_handler_mut()?
-                            .handle_vault_rpc(sender, rpc.clone());
-                        // } else {
-                        //     Send to target
-                    }
-                }
-                next_action
+            } => return self.send_to_peers(sender, targets, rpc),
+        };
+        next_action.into_iter().collect()
+    }
+
+    fn respond_to_our_data_handlers(&mut self, sender: XorName, rpc: Rpc) -> Option<Action> {
+        // Responses are never split.
+        let rpc = self.fit_rpc(rpc).pop()?;
+        self.observe_sent_rpc(sender, *self.id.public_id().name(), &rpc);
+
+        // TODO - once Routing is integrated, we'll construct the full message to send
+        //        onwards, and then if we're also part of the data handlers, we'll call that
+        //        same handler which Routing will call after receiving a message.
+
+        self.data_handler_mut()?.handle_vault_rpc(sender, rpc)
+    }
+
+    fn respond_to_client_handlers(&mut self, sender: XorName, rpc: Rpc) -> Option<Action> {
+        let rpc = self.fit_rpc(rpc).pop()?;
+        let client_name = *utils::requester_address(&rpc);
+        self.observe_sent_rpc(sender, client_name, &rpc);
+
+        if let Rpc::Response {
+            response: Response::GetIData(ref result),
+            message_id,
+            ..
+        } = rpc
+        {
+            if let Some(reply) = self.holder_queries.remove(&message_id) {
+                let _ = reply.send(result.clone());
+                return None;
             }
         }
+
+        // TODO - once Routing is integrated, we'll construct the full message to send
+        //        onwards, and then if we're also part of the client handlers, we'll call that
+        //        same handler which Routing will call after receiving a message.
+
+        if self.self_is_handler_for(&client_name) {
+            return self.client_handler_mut()?.handle_vault_rpc(sender, rpc);
+        }
+        None
+    }
+
+    // Sends `rpc` to each of `targets`, returning the follow-up of each RPC handled locally.
+    fn send_to_peers(
+        &mut self,
+        sender: XorName,
+        targets: BTreeSet<XorName>,
+        rpc: Rpc,
+    ) -> Vec<Action> {
+        let rpcs: Vec<_> = match self.segment_size_threshold {
+            Some(threshold) => rpc.into_segments(threshold),
+            None => vec![rpc],
+        }
+        .into_iter()
+        .flat_map(|rpc| self.fit_rpc(rpc))
+        .collect();
+        let mut next_actions = Vec::new();
+        for target in targets {
+            for rpc in &rpcs {
+                self.observe_sent_rpc(sender, target, rpc);
+            }
+            if target == *self.id.public_id().name() {
+                let data_handler = match self.data_handler_mut() {
+                    Some(data_handler) => data_handler,
+                    None => return next_actions,
+                };
+                next_actions.extend(
+                    rpcs.iter()
+                        .filter_map(|rpc| data_handler.handle_vault_rpc(sender, rpc.clone())),
+                );
+                // } else {
+                //     Send to target
+            }
+        }
+        next_actions
+    }
+
+    // Makes `rpc` fit within `max_rpc_size`, if set, rather than leaving the transport to fail to
+    // send it.  Oversized responses are replaced by errors, while any other RPC which can't be made
+    // to fit is dropped, with an error logged.
+    fn fit_rpc(&self, rpc: Rpc) -> Vec<Rpc> {
+        let max_size = match self.max_rpc_size {
+            Some(max_size) => max_size,
+            None => return vec![rpc],
+        };
+        let requester = *utils::requester_address(&rpc);
+        rpc.fit_within(max_size).unwrap_or_else(|size| {
+            error!(
+                "{}: Dropping an RPC of {} bytes for {}, over the limit of {} bytes",
+                self, size, requester, max_size
+            );
+            Vec::new()
+        })
     }
 
     fn forward_client_request(&mut self, rpc: Rpc) -> Option<Action> {
@@ -427,6 +1465,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
             error!("{}: Logic error - unexpected RPC.", self);
             return None;
         };
+        self.observe_sent_rpc(*self.id.public_id().name(), *dst_address, &rpc);
 
         // TODO - once Routing is integrated, we'll construct the full message to send
         //        onwards, and then if we're also part of the data handlers, we'll call that
@@ -486,6 +1525,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
             error!("{}: Logic error - unexpected RPC.", self);
             return None;
         };
+        self.observe_sent_rpc(*self.id.public_id().name(), dst_address, &rpc);
 
         // TODO - once Routing is integrated, we'll construct the full message to send
         //        onwards, and then if we're also part of the data handlers, we'll call that
@@ -503,14 +1543,31 @@ impl<R: CryptoRng + Rng> Vault<R> {
         true
     }
 
-    // TODO - remove this
-    #[allow(unused)]
+    #[cfg(feature = "mock_base")]
+    fn observe_sent_rpc(&self, src: XorName, dst: XorName, rpc: &Rpc) {
+        let sent_rpc = SentRpc {
+            src,
+            dst,
+            rpc: rpc.clone(),
+        };
+        #[cfg(all(test, feature = "mock"))]
+        self.captured_events
+            .borrow_mut()
+            .push(CapturedEvent::SentRpc(sent_rpc.clone()));
+        if let Some(ref observer) = self.sent_rpc_observer {
+            let _ = observer.send(sent_rpc);
+        }
+    }
+
+    #[cfg(not(feature = "mock_base"))]
+    fn observe_sent_rpc(&self, _src: XorName, _dst: XorName, _rpc: &Rpc) {}
+
     fn client_handler(&self) -> Option<&ClientHandler> {
         match &self.state {
             State::Elder {
                 ref client_handler, ..
             } => Some(client_handler),
-            State::Adult(_) => None,
+            State::Infant | State::Adult(_) => None,
         }
     }
 
@@ -520,7 +1577,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
                 ref mut client_handler,
                 ..
             } => Some(client_handler),
-            State::Adult(_) => None,
+            State::Infant | State::Adult(_) => None,
         }
     }
 
@@ -531,7 +1588,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
             State::Elder {
                 ref data_handler, ..
             } => Some(data_handler),
-            State::Adult(_) => None,
+            State::Infant | State::Adult(_) => None,
         }
     }
 
@@ -541,7 +1598,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
                 ref mut data_handler,
                 ..
             } => Some(data_handler),
-            State::Adult(_) => None,
+            State::Infant | State::Adult(_) => None,
         }
     }
 
@@ -552,7 +1609,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
             State::Elder {
                 ref coins_handler, ..
             } => Some(coins_handler),
-            State::Adult(_) => None,
+            State::Infant | State::Adult(_) => None,
         }
     }
 
@@ -564,7 +1621,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
                 ref mut coins_handler,
                 ..
             } => Some(coins_handler),
-            State::Adult(_) => None,
+            State::Infant | State::Adult(_) => None,
         }
     }
 
@@ -572,7 +1629,7 @@ impl<R: CryptoRng + Rng> Vault<R> {
     #[allow(unused)]
     fn adult(&self) -> Option<&Adult> {
         match &self.state {
-            State::Elder { .. } => None,
+            State::Elder { .. } | State::Infant => None,
             State::Adult(ref adult) => Some(adult),
         }
     }
@@ -581,33 +1638,406 @@ impl<R: CryptoRng + Rng> Vault<R> {
     #[allow(unused)]
     fn adult_mut(&mut self) -> Option<&mut Adult> {
         match &mut self.state {
-            State::Elder { .. } => None,
+            State::Elder { .. } | State::Infant => None,
             State::Adult(ref mut adult) => Some(adult),
         }
     }
 
+    // Writes the state file atomically, so that a crash while writing it leaves the previous state
+    // intact rather than a truncated file.
     fn dump_state(&self) -> Result<()> {
-        let path = self.root_dir.join(STATE_FILENAME);
-        let is_elder = match self.state {
-            State::Elder { .. } => true,
-            State::Adult(_) => false,
-        };
-        Ok(fs::write(path, utils::serialise(&(is_elder, &self.id)))?)
+        let temp_path = self.root_dir.join(STATE_TEMP_FILENAME);
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&utils::serialise(&(
+            self.is_elder_state(),
+            &self.id,
+            &self.network_name,
+        )))?;
+        file.sync_all()?;
+        Ok(fs::rename(temp_path, self.root_dir.join(STATE_FILENAME))?)
     }
 
-    /// Returns Some((is_elder, ID)) or None if file doesn't exist.
-    fn read_state(config: &Config) -> Result<Option<(bool, NodeFullId)>> {
+    /// Returns Some((is_elder, ID, network name)) or None if file doesn't exist.
+    fn read_state(config: &Config) -> Result<Option<(bool, NodeFullId, Option<String>)>> {
         let path = config.root_dir()?.join(STATE_FILENAME);
         if !path.is_file() {
             return Ok(None);
         }
-        let contents = fs::read(path)?;
-        Ok(Some(bincode::deserialize(&contents)?))
+        let contents = match utils::retry_load(
+            &path,
+            config.load_retries(),
+            utils::is_transient_io_error,
+            || fs::read(&path),
+        ) {
+            Ok(contents) => contents,
+            // Removed since we checked, so there's no state to load.
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        // State written before the network name was stored has no name.
+        let state = bincode::deserialize(&contents).or_else(|_| {
+            bincode::deserialize::<(bool, NodeFullId)>(&contents)
+                .map(|(is_elder, id)| (is_elder, id, None))
+        })?;
+        Ok(Some(state))
     }
 }
 
 impl<R: CryptoRng + Rng> Display for Vault<R> {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-        write!(formatter, "{}", self.id.public_id())
+        match self.network_name {
+            Some(ref network_name) => write!(formatter, "{}@{}", self.id.public_id(), network_name),
+            None => write!(formatter, "{}", self.id.public_id()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use unwrap::unwrap;
+
+    #[test]
+    fn state_survives_a_round_trip() {
+        let root = unwrap!(TempDir::new("test"));
+        let mut config = Config::default();
+        config.set_root_dir(root.path());
+        let path = root.path().join(STATE_FILENAME);
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..20 {
+            let is_elder = rng.gen();
+            let id = NodeFullId::new(&mut rng);
+            let network_name = if rng.gen() {
+                Some(format!("{:x}", rng.gen::<u64>()))
+            } else {
+                None
+            };
+            let state = (is_elder, id, network_name);
+            let (read_is_elder, read_id, read_network_name) = utils::round_trip(&state);
+            assert_eq!(read_is_elder, state.0);
+            assert_eq!(read_id.public_id(), state.1.public_id());
+            assert_eq!(read_network_name, state.2);
+
+            unwrap!(fs::write(&path, utils::serialise(&state)));
+            let (read_is_elder, read_id, read_network_name) =
+                unwrap!(unwrap!(Vault::<ChaChaRng>::read_state(&config)));
+            assert_eq!(read_is_elder, state.0);
+            assert_eq!(read_id.public_id(), state.1.public_id());
+            assert_eq!(read_network_name, state.2);
+
+            // State written before the network name was stored.
+            unwrap!(fs::write(&path, utils::serialise(&(state.0, &state.1))));
+            let (read_is_elder, read_id, read_network_name) =
+                unwrap!(unwrap!(Vault::<ChaChaRng>::read_state(&config)));
+            assert_eq!(read_is_elder, state.0);
+            assert_eq!(read_id.public_id(), state.1.public_id());
+            assert_eq!(read_network_name, None);
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn drain_events_returns_actions_and_sent_rpcs() {
+        let _network = mock_quic_p2p::Network::new();
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let mut vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rand::thread_rng(),
+        ));
+        let our_name = *vault.id.public_id().name();
+        let client = ClientFullId::new_ed25519(&mut rand::thread_rng())
+            .public_id()
+            .clone();
+        let data = IData::Pub(safe_nd::PubImmutableData::new(vec![1, 2, 3]));
+
+        vault.drain_actions(Some(Action::ForwardClientRequest(Rpc::Request {
+            request: Request::PutIData(data),
+            requester: PublicId::Client(client),
+            message_id: MessageId::new(),
+        })));
+
+        let events = vault.drain_events();
+        let actions: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                CapturedEvent::Action(action) => Some(action.name()),
+                CapturedEvent::SentRpc(_) => None,
+            })
+            .collect();
+        assert_eq!(actions.first(), Some(&"ForwardClientRequest"));
+        assert!(actions.contains(&"SendToPeers"));
+        assert_eq!(actions.last(), Some(&"RespondToClientHandlers"));
+        // The put is sent on to us, as the only holder.
+        assert!(events.iter().any(|event| match event {
+            CapturedEvent::SentRpc(sent) => match sent.request() {
+                Some(Request::PutIData(_)) => sent.dst == our_name,
+                _ => false,
+            },
+            CapturedEvent::Action(_) => false,
+        }));
+
+        assert!(vault.drain_events().is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn interrupted_state_write_leaves_previous_state() {
+        let _network = mock_quic_p2p::Network::new();
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rand::thread_rng(),
+        ));
+        let mut config = Config::default();
+        config.set_root_dir(&vault.root_dir);
+        let check_state = || {
+            let (is_elder, id, network_name) =
+                unwrap!(unwrap!(Vault::<ChaChaRng>::read_state(&config)));
+            assert!(is_elder);
+            assert_eq!(id.public_id(), vault.id.public_id());
+            assert_eq!(network_name, vault.network_name);
+        };
+        unwrap!(vault.dump_state());
+
+        // A write interrupted before the rename leaves a partial temporary file behind.
+        let temp_path = vault.root_dir.join(STATE_TEMP_FILENAME);
+        unwrap!(fs::write(&temp_path, b"trunc"));
+        check_state();
+
+        // The next write replaces it.
+        unwrap!(vault.dump_state());
+        assert!(!temp_path.exists());
+        check_state();
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn local_state_accessors() {
+        let _network = mock_quic_p2p::Network::new();
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let mut vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rand::thread_rng(),
+        ));
+        assert!(vault.is_elder_state());
+        assert!(!vault.is_adult());
+        assert!(!vault.is_infant());
+        assert_eq!(vault.state_name(), "Elder");
+
+        let root = unwrap!(TempDir::new("test"));
+        let adult = unwrap!(Adult::new(
+            vault.id.public_id().clone(),
+            root.path(),
+            Config::default().max_capacity(),
+            Init::New,
+        ));
+        vault.state = State::Adult(adult);
+        assert!(!vault.is_elder_state());
+        assert!(vault.is_adult());
+        assert!(!vault.is_infant());
+        assert_eq!(vault.state_name(), "Adult");
+
+        vault.state = State::Infant;
+        assert!(!vault.is_elder_state());
+        assert!(!vault.is_adult());
+        assert!(vault.is_infant());
+        assert_eq!(vault.state_name(), "Infant");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn action_chain_depth_includes_follow_ups_of_rpcs_sent_to_peers() {
+        let _network = mock_quic_p2p::Network::new();
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let mut vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rand::thread_rng(),
+        ));
+        let client = ClientFullId::new_ed25519(&mut rand::thread_rng())
+            .public_id()
+            .clone();
+        let put = |vault: &mut Vault<_>, content| {
+            let data = IData::Pub(safe_nd::PubImmutableData::new(content));
+            vault.drain_actions(Some(Action::ForwardClientRequest(Rpc::Request {
+                request: Request::PutIData(data),
+                requester: PublicId::Client(client.clone()),
+                message_id: MessageId::new(),
+            })));
+            vault
+                .drain_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    CapturedEvent::Action(action) => Some(action.name()),
+                    CapturedEvent::SentRpc(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The holder's response, a follow-up of `SendToPeers`, is itself followed up.
+        let chain = put(&mut vault, vec![1, 2, 3]);
+        let send_index = unwrap!(chain.iter().position(|name| *name == "SendToPeers"));
+        assert_eq!(chain[send_index + 1], "RespondToOurDataHandlers");
+        assert_eq!(chain.last(), Some(&"RespondToClientHandlers"));
+
+        // Follow-ups of the RPCs sent to peers count towards the depth of the whole chain.
+        vault.config.set_max_action_chain(Some(chain.len() - 1));
+        assert_eq!(
+            put(&mut vault, vec![4, 5, 6]),
+            chain[..chain.len() - 1].to_vec()
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn undecodable_consensus_actions_are_counted() {
+        let _network = mock_quic_p2p::Network::new();
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let mut vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rand::thread_rng(),
+        ));
+
+        for count in 1..=UNDECODABLE_CONSENSUS_THRESHOLD + 1 {
+            let event = RoutingEvent::Consensus(vec![0xff; count as usize]);
+            assert!(vault.handle_routing_event(event).is_none());
+            let metrics = vault.metrics();
+            assert_eq!(metrics.undecodable_consensus_actions, count);
+            assert_eq!(metrics.routing_events.consensus, count);
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn routing_events_are_counted_by_kind() {
+        let _network = mock_quic_p2p::Network::new();
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let mut vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rand::thread_rng(),
+        ));
+        let name = routing::XorName([1; 32]);
+
+        let events = vec![
+            RoutingEvent::Promoted,
+            RoutingEvent::MemberJoined { name, age: 4 },
+            RoutingEvent::MemberJoined { name, age: 4 },
+            RoutingEvent::MemberLeft { name, age: 4 },
+            RoutingEvent::Consensus(Vec::new()),
+            RoutingEvent::Demoted,
+            RoutingEvent::RestartRequired,
+        ];
+        for event in events {
+            assert!(vault.handle_routing_event(event).is_none());
+        }
+        assert_eq!(
+            vault.metrics().routing_events,
+            RoutingEventCounts {
+                promoted: 1,
+                member_joined: 2,
+                member_left: 1,
+                consensus: 1,
+                other: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn vault_runs_with_a_supplied_id() {
+        let _network = mock_quic_p2p::Network::new();
+        let root = unwrap!(TempDir::new("test"));
+        let mut config = Config::default();
+        config.set_root_dir(root.path());
+        let start = |id: Option<NodeFullId>| {
+            let (routing_node, routing_rx, client_rx) = Node::builder().create();
+            let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+            let rng = rand::thread_rng();
+            unwrap!(match id {
+                Some(id) => Vault::with_id(
+                    id,
+                    routing_node,
+                    routing_rx,
+                    client_rx,
+                    &config,
+                    command_rx,
+                    rng
+                ),
+                None => Vault::new(
+                    routing_node,
+                    routing_rx,
+                    client_rx,
+                    &config,
+                    command_rx,
+                    rng
+                ),
+            })
+        };
+
+        let generated = start(None).public_id().clone();
+        let supplied = NodeFullId::new(&mut rand::thread_rng());
+        assert_ne!(supplied.public_id(), &generated);
+        let public_id = supplied.public_id().clone();
+        assert_eq!(start(Some(supplied)).public_id(), &public_id);
+
+        // The supplied ID replaces the stored one.
+        let (_, stored_id, _) = unwrap!(unwrap!(Vault::<ChaChaRng>::read_state(&config)));
+        assert_eq!(stored_id.public_id(), &public_id);
+        assert_eq!(start(None).public_id(), &public_id);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn state_survives_a_crash_while_it_is_written() {
+        let _network = mock_quic_p2p::Network::new();
+        let root = unwrap!(TempDir::new("test"));
+        let mut config = Config::default();
+        config.set_root_dir(root.path());
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let vault = unwrap!(Vault::new(
+            routing_node,
+            routing_rx,
+            client_rx,
+            &config,
+            command_rx,
+            rand::thread_rng(),
+        ));
+        let public_id = vault.public_id().clone();
+        assert!(!root.path().join(STATE_TEMP_FILENAME).exists());
+
+        // A crash part way through rewriting the state leaves only the temporary file truncated.
+        unwrap!(vault.dump_state());
+        unwrap!(fs::write(root.path().join(STATE_TEMP_FILENAME), b"partial"));
+        drop(vault);
+        let (is_elder, id, _) = unwrap!(unwrap!(Vault::<ChaChaRng>::read_state(&config)));
+        assert!(is_elder);
+        assert_eq!(id.public_id(), &public_id);
     }
 }
@@ -0,0 +1,78 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A watchdog thread detecting a wedged event loop, e.g. one stuck in a handler which never returns
+//! or blocked on a borrow which is never released.
+//!
+//! The event loop sends a heartbeat on each iteration.  If none arrives within the configured
+//! interval, the watchdog logs an error and, if configured to, aborts the process so that a
+//! supervisor can restart it.  A stack trace of the wedged thread can't portably be captured from
+//! another thread, so the abort is also what provides one: via the core dump it produces.
+
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use log::{error, info};
+use std::{process, thread, time::Duration};
+
+/// Handle held by the event loop to feed the watchdog.  The watchdog thread exits once this is
+/// dropped.
+pub(crate) struct Watchdog {
+    heartbeats: Sender<()>,
+    interval: Duration,
+}
+
+impl Watchdog {
+    /// Starts a watchdog thread which reports the event loop as wedged if `heartbeat` isn't called
+    /// for `interval`, aborting the process if `abort` is set.
+    pub fn start(interval: Duration, abort: bool) -> Self {
+        let (heartbeats, heartbeat_rx) = crossbeam_channel::bounded(1);
+        let _ = thread::spawn(move || {
+            info!("Watchdog started, with an interval of {:?}", interval);
+            let mut wedged = false;
+            loop {
+                match heartbeat_rx.recv_timeout(interval) {
+                    Ok(()) => {
+                        if wedged {
+                            info!("Event loop has resumed");
+                            wedged = false;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        if abort {
+                            error!(
+                                "Event loop has made no progress for {:?}. Aborting.",
+                                interval
+                            );
+                            process::abort();
+                        }
+                        if !wedged {
+                            error!("Event loop has made no progress for {:?}", interval);
+                            wedged = true;
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        Self {
+            heartbeats,
+            interval,
+        }
+    }
+
+    /// Tells the watchdog that the event loop is making progress.  Never blocks.
+    pub fn heartbeat(&self) {
+        // If a heartbeat is already pending, the watchdog hasn't consumed it yet so needn't be sent
+        // another.
+        let _ = self.heartbeats.try_send(());
+    }
+
+    /// How long the event loop may wait for events before it must send a heartbeat regardless.
+    pub fn max_idle(&self) -> Duration {
+        self.interval / 2
+    }
+}
@@ -31,21 +31,25 @@ use safe_nd::{
 use safe_vault::{
     mock_routing::{ConsensusGroup, ConsensusGroupRef},
     routing::Node,
+    CommandSender,
 };
 use safe_vault::{Command, Config, Vault};
 use serde::Serialize;
+#[cfg(feature = "mock")]
+use std::thread::{self, JoinHandle};
 use std::{
     convert::{TryFrom, TryInto},
     fmt::Debug,
     net::SocketAddr,
     ops::{Deref, DerefMut},
+    path::Path,
     slice,
 };
 use tempdir::TempDir;
 use unwrap::unwrap;
 
 /// Default number of vaults to run the tests with.
-const DEFAULT_NUM_VAULTS: usize = 5;
+pub const DEFAULT_NUM_VAULTS: usize = 5;
 
 macro_rules! unexpected {
     ($e:expr) => {
@@ -65,6 +69,24 @@ pub struct Environment {
 impl Environment {
     #[cfg(feature = "mock")]
     pub fn with_multiple_vaults(num_vaults: usize) -> Self {
+        Self::create(num_vaults, &Config::default(), 1)
+    }
+
+    /// Create an environment whose vaults all use `config`, other than their root dirs.
+    #[cfg(feature = "mock")]
+    pub fn with_config(config: &Config) -> Self {
+        Self::create(DEFAULT_NUM_VAULTS, config, 1)
+    }
+
+    /// Create an environment where an action is only consensused once `quorum` vaults have voted
+    /// for it.
+    #[cfg(feature = "mock")]
+    pub fn with_consensus_quorum(quorum: usize) -> Self {
+        Self::create(DEFAULT_NUM_VAULTS, &Config::default(), quorum)
+    }
+
+    #[cfg(feature = "mock")]
+    fn create(num_vaults: usize, config: &Config, quorum: usize) -> Self {
         assert!(num_vaults > 0);
 
         logging::init();
@@ -74,18 +96,19 @@ impl Environment {
 
         let network = Network::new();
 
-        let consensus_group = ConsensusGroup::new();
+        let consensus_group = ConsensusGroup::new_with_quorum(quorum);
         let vaults = if num_vaults > 1 {
             let mut vaults = Vec::with_capacity(num_vaults);
             for _ in 0..num_vaults {
                 vaults.push(TestVault::new_with_mock_routing(
                     Some(consensus_group.clone()),
+                    config,
                     &mut rng,
                 ));
             }
             vaults
         } else {
-            vec![TestVault::new_with_mock_routing(None, &mut rng)]
+            vec![TestVault::new_with_mock_routing(None, config, &mut rng)]
         };
 
         Self {
@@ -146,6 +169,70 @@ impl Environment {
         &mut self.rng
     }
 
+    pub fn vault_mut(&mut self, index: usize) -> &mut Vault<TestRng> {
+        &mut self.vaults[index].inner
+    }
+
+    pub fn vault_root_dir(&self, index: usize) -> &Path {
+        self.vaults[index].root_dir.path()
+    }
+
+    /// Create a standalone vault in a temporary root dir, which isn't polled by the environment.
+    #[cfg(feature = "mock")]
+    pub fn new_in_memory_vault(&mut self) -> (Vault<TestRng>, Sender<Command>) {
+        let (command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let vault = unwrap!(Vault::new_in_memory(
+            routing_node,
+            routing_rx,
+            client_rx,
+            command_rx,
+            rng::from_rng(&mut self.rng),
+        ));
+        (vault, command_tx)
+    }
+
+    /// Create a standalone vault from `config`, e.g. to restart one of the environment's vaults
+    /// from its root dir.  It isn't polled by the environment.
+    #[cfg(feature = "mock")]
+    pub fn new_standalone_vault(&mut self, config: &Config) -> (Vault<TestRng>, Sender<Command>) {
+        let (command_tx, command_rx) = crossbeam_channel::bounded(0);
+        let (routing_node, routing_rx, client_rx) = Node::builder().create();
+        let vault = unwrap!(Vault::new(
+            routing_node,
+            routing_rx,
+            client_rx,
+            config,
+            command_rx,
+            rng::from_rng(&mut self.rng),
+        ));
+        (vault, command_tx)
+    }
+
+    /// Create a standalone vault in a temporary root dir on a new thread, which runs it until it's
+    /// stopped.  Returns the vault's `command_sender` and the thread's handle.
+    #[cfg(feature = "mock")]
+    pub fn run_in_memory_vault_on_thread(&mut self) -> (CommandSender, JoinHandle<()>) {
+        let rng = rng::from_rng(&mut self.rng);
+        let (sender_tx, sender_rx) = crossbeam_channel::bounded(1);
+        let thread = thread::spawn(move || {
+            let _network = Network::new();
+            let (routing_node, routing_rx, client_rx) = Node::builder().create();
+            // Kept alive, though unused, as the vault stops with an error if it's disconnected.
+            let (_command_tx, command_rx) = crossbeam_channel::bounded(0);
+            let mut vault = unwrap!(Vault::new_in_memory(
+                routing_node,
+                routing_rx,
+                client_rx,
+                command_rx,
+                rng,
+            ));
+            unwrap!(sender_tx.send(vault.command_sender()));
+            vault.run();
+        });
+        (unwrap!(sender_rx.recv()), thread)
+    }
+
     #[cfg(not(feature = "mock_parsec"))]
     // Poll the mock network and the environment's vault.
     pub fn poll(&mut self) {
@@ -220,7 +307,7 @@ trait AsMutSlice<T> {
 
 struct TestVault {
     inner: Vault<TestRng>,
-    _root_dir: TempDir,
+    root_dir: TempDir,
     _command_tx: Sender<Command>,
 }
 
@@ -229,12 +316,13 @@ impl TestVault {
     #[cfg(feature = "mock")]
     fn new_with_mock_routing(
         consensus_group: Option<ConsensusGroupRef>,
+        config: &Config,
         rng: &mut TestRng,
     ) -> Self {
         let root_dir = unwrap!(TempDir::new("safe_vault"));
         trace!("Creating a test vault at root_dir {:?}", root_dir);
 
-        let mut config = Config::default();
+        let mut config = config.clone();
         config.set_root_dir(root_dir.path());
 
         let (command_tx, command_rx) = crossbeam_channel::bounded(0);
@@ -255,7 +343,7 @@ impl TestVault {
 
         Self {
             inner,
-            _root_dir: root_dir,
+            root_dir,
             _command_tx: command_tx,
         }
     }
@@ -290,7 +378,7 @@ impl TestVault {
 
         Self {
             inner,
-            _root_dir: root_dir,
+            root_dir,
             _command_tx: command_tx,
         }
     }
@@ -390,6 +478,12 @@ pub trait TestClientTrait {
         self.send_to_target(&request, conn_info.clone());
     }
 
+    fn disconnect(&mut self) {
+        for conn_info in self.connected_vaults() {
+            self.quic_p2p().disconnect_from(conn_info);
+        }
+    }
+
     fn expect_connected_to(&mut self, conn_info: &SocketAddr) {
         loop {
             match self.rx().try_recv() {
@@ -463,7 +557,11 @@ pub trait TestClientTrait {
 
     fn send_request(&mut self, request: Request) -> MessageId {
         let message_id = MessageId::new();
+        self.send_request_with_message_id(request, message_id);
+        message_id
+    }
 
+    fn send_request_with_message_id(&mut self, request: Request, message_id: MessageId) {
         let to_sign = (&request, &message_id);
         let to_sign = unwrap!(bincode::serialize(&to_sign));
         let signature = self.full_id().sign(&to_sign);
@@ -475,8 +573,6 @@ pub trait TestClientTrait {
         };
 
         self.send(&msg);
-
-        message_id
     }
 
     fn expect_response(
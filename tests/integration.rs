@@ -24,6 +24,8 @@
 mod common;
 
 use self::common::{Environment, TestClientTrait};
+use crossbeam_channel::TrySendError;
+use fake_clock::FakeClock;
 use maplit::btreemap;
 use rand::{distributions::Standard, Rng};
 use safe_nd::{
@@ -33,14 +35,25 @@ use safe_nd::{
     EntryError, Error as NdError, IData, IDataAddress, LoginPacket, MData, MDataAction,
     MDataAddress, MDataEntries, MDataKind, MDataPermissionSet, MDataSeqEntryActions, MDataSeqValue,
     MDataUnseqEntryActions, MDataValue, MDataValues, Message, MessageId, PubImmutableData,
-    PubSeqAppendOnlyData, PubUnseqAppendOnlyData, PublicKey, Request, Response, Result as NdResult,
-    SeqAppendOnly, SeqMutableData, Transaction, UnpubImmutableData, UnpubSeqAppendOnlyData,
-    UnpubUnseqAppendOnlyData, UnseqAppendOnly, UnseqMutableData, XorName,
+    PubSeqAppendOnlyData, PubUnseqAppendOnlyData, PublicId, PublicKey, Request, Response,
+    Result as NdResult, SeqAppendOnly, SeqMutableData, Transaction, UnpubImmutableData,
+    UnpubSeqAppendOnlyData, UnpubUnseqAppendOnlyData, UnseqAppendOnly, UnseqMutableData, XorName,
 };
-use safe_vault::COST_OF_PUT;
-use std::collections::{BTreeMap, BTreeSet};
+use safe_vault::{
+    AdminCommand, Command, Config, Error, OperatorCapability, RefundReason, RoutingEventCounts,
+    SentRpc, COST_OF_PUT, MAINTENANCE, READ_ONLY, SECTION_BUSY,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    net::SocketAddr,
+};
+use tempdir::TempDir;
 use unwrap::unwrap;
 
+// How long, in milliseconds, a vault holds responses for a disconnected client to reconnect.
+const RECONNECT_WINDOW_MS: u64 = 30_000;
+
 #[test]
 fn client_connects() {
     let mut env = Environment::new();
@@ -48,6 +61,39 @@ fn client_connects() {
     let _app = env.new_connected_app(client.public_id().clone());
 }
 
+#[cfg(feature = "mock_parsec")]
+#[test]
+fn vaults_are_elders() {
+    let mut env = Environment::new();
+    for index in 0..common::DEFAULT_NUM_VAULTS {
+        let vault = env.vault_mut(index);
+        assert!(vault.is_elder());
+        assert!(!vault.is_adult());
+        assert!(!vault.is_infant());
+        assert_eq!(vault.state_name(), "Elder");
+    }
+}
+
+#[test]
+fn connected_clients_are_tracked() {
+    let mut env = Environment::new();
+    assert!(env.vault_mut(0).connected_clients().is_empty());
+
+    let mut client = env.new_connected_client();
+    let public_id = PublicId::Client(client.public_id().clone());
+    let connected: Vec<_> = env
+        .vault_mut(0)
+        .connected_clients()
+        .into_iter()
+        .map(|(_, public_id)| public_id)
+        .collect();
+    assert_eq!(connected, vec![Some(public_id)]);
+
+    client.disconnect();
+    env.poll();
+    assert!(env.vault_mut(0).connected_clients().is_empty());
+}
+
 #[test]
 fn invalid_signature() {
     let mut env = Environment::new();
@@ -384,6 +430,31 @@ fn coin_operations() {
     common::send_request_expect_ok(&mut env, &mut client_b, Request::GetBalance, amount_b);
 }
 
+#[cfg(feature = "mock")]
+#[test]
+fn coin_operations_are_only_applied_after_consensus() {
+    // Every vault votes, so a quorum of all of them is reached.
+    let mut env = Environment::with_consensus_quorum(common::DEFAULT_NUM_VAULTS);
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 10);
+    let balance: NdResult<Coins> =
+        common::get_from_response(&mut env, &mut client, Request::GetBalance);
+    assert_eq!(balance, Ok(unwrap!(Coins::from_nano(10))));
+
+    // A quorum which is never reached leaves the request unapplied and unanswered.
+    let mut env = Environment::with_consensus_quorum(common::DEFAULT_NUM_VAULTS + 1);
+    let mut client = env.new_connected_client();
+    let _ = client.send_request(Request::CreateBalance {
+        new_balance_owner: *client.public_id().public_key(),
+        amount: unwrap!(Coins::from_nano(10)),
+        transaction_id: 0,
+    });
+    env.poll();
+    let balance: NdResult<Coins> =
+        common::get_from_response(&mut env, &mut client, Request::GetBalance);
+    assert_eq!(balance, Err(NdError::NoSuchBalance));
+}
+
 #[test]
 fn create_balance_that_already_exists() {
     let mut env = Environment::new();
@@ -2216,6 +2287,858 @@ fn delete_immutable_data() {
     )
 }
 
+#[test]
+fn replayed_immutable_data_put_is_rejected() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+
+    let start_nano = 1_000_000_000_000;
+    common::create_balance(&mut env, &mut client, None, start_nano);
+
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    let request = Request::PutIData(pub_idata);
+    let message_id = client.send_request(request.clone());
+    env.poll();
+    assert_eq!(
+        client.expect_response(message_id, &mut env),
+        Response::Mutation(Ok(()))
+    );
+
+    // Replay the concluded request using the same message ID.
+    client.send_request_with_message_id(request.clone(), message_id);
+    env.poll();
+    assert_eq!(
+        client.expect_response(message_id, &mut env),
+        request.error_response(NdError::DuplicateMessageId)
+    );
+
+    // The replay should have been refunded.
+    let expected = unwrap!(Coins::from_nano(start_nano).checked_sub(COST_OF_PUT));
+    common::send_request_expect_ok(&mut env, &mut client, Request::GetBalance, expected);
+}
+
+#[test]
+fn immutable_data_metadata_export_and_import() {
+    let dir = unwrap!(TempDir::new("safe_vault"));
+    let exported = dir.path().join("exported");
+    {
+        let mut env = Environment::new();
+        let mut client = env.new_connected_client();
+        common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+        let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+        common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata));
+        assert_eq!(
+            unwrap!(env.vault_mut(0).export_idata_metadata(&exported)),
+            1
+        );
+    }
+
+    let mut env = Environment::new();
+    let vault = env.vault_mut(0);
+    assert_eq!(unwrap!(vault.import_idata_metadata(&exported)), 1);
+    // Importing again merges with the existing metadata rather than duplicating it.
+    assert_eq!(unwrap!(vault.import_idata_metadata(&exported)), 1);
+
+    let reexported = dir.path().join("reexported");
+    assert_eq!(unwrap!(vault.export_idata_metadata(&reexported)), 1);
+    assert_eq!(unwrap!(fs::read(exported)), unwrap!(fs::read(reexported)));
+}
+
+#[test]
+fn get_immutable_data_is_cancelled_when_client_disconnects() {
+    let dir = unwrap!(TempDir::new("safe_vault"));
+    let exported = dir.path().join("exported");
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+
+    // Make vault 1 also expect vault 0 to hold the chunk.  Vault 0 never receives the get, so it
+    // stays in flight.
+    let _ = unwrap!(env.vault_mut(0).export_idata_metadata(&exported));
+    let _ = unwrap!(env.vault_mut(1).import_idata_metadata(&exported));
+    let _ = client.send_request(Request::GetIData(*pub_idata.address()));
+    env.poll();
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 1);
+
+    // The get is kept going while the client has a chance to reconnect, and only cancelled once
+    // that has passed.
+    client.disconnect();
+    env.poll();
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 1);
+
+    FakeClock::advance_time(RECONNECT_WINDOW_MS + 1);
+    env.poll();
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 0);
+}
+
+#[test]
+fn get_immutable_data_survives_client_reconnecting() {
+    let dir = unwrap!(TempDir::new("safe_vault"));
+    let exported = dir.path().join("exported");
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+
+    let _ = unwrap!(env.vault_mut(0).export_idata_metadata(&exported));
+    let _ = unwrap!(env.vault_mut(1).import_idata_metadata(&exported));
+    let _ = client.send_request(Request::GetIData(*pub_idata.address()));
+    env.poll();
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 1);
+
+    client.disconnect();
+    env.poll();
+    env.establish_connection(&mut client);
+
+    // The client reclaimed its request, so the get isn't cancelled once the window has passed.
+    FakeClock::advance_time(RECONNECT_WINDOW_MS + 1);
+    env.poll();
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 1);
+}
+
+#[test]
+fn refunded_put_records_reason() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    let owner = *client.public_id().public_key();
+    let unpub_idata = IData::Unpub(UnpubImmutableData::new(vec![1, 2, 3], owner));
+    common::perform_mutation(
+        &mut env,
+        &mut client,
+        Request::PutIData(unpub_idata.clone()),
+    );
+    assert!(env.vault_mut(0).refunds().is_empty());
+
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::PutIData(unpub_idata),
+        NdError::DataExists,
+    );
+    let refunds = env.vault_mut(0).refunds();
+    assert_eq!(
+        refunds.keys().collect::<Vec<_>>(),
+        [&RefundReason::DataExists]
+    );
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn put_immutable_data_refunds() {
+    let start_nano = 1_000_000_000_000;
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+
+    // Puts `data` and checks the response, that exactly `expected_refund` was refunded by the data
+    // handlers, and that the requester's balance reflects that.
+    let check_put = |env: &mut Environment,
+                     client: &mut common::TestClient,
+                     data: IData,
+                     expected_response: Response,
+                     expected_refund: Option<Coins>| {
+        let (observer_tx, observer_rx) = crossbeam_channel::unbounded();
+        env.vault_mut(0).set_sent_rpc_observer(observer_tx);
+        let balance_before: Coins = common::get_from_response(env, client, Request::GetBalance);
+
+        let message_id = client.send_request(Request::PutIData(data));
+        env.poll();
+        assert_eq!(client.expect_response(message_id, env), expected_response);
+
+        let refunds: Vec<_> = observer_rx
+            .try_iter()
+            .filter(|sent| sent.message_id() == message_id)
+            .filter_map(|sent| sent.refund())
+            .collect();
+        assert_eq!(refunds, expected_refund.into_iter().collect::<Vec<_>>());
+
+        let expected_balance = unwrap!(unwrap!(balance_before.checked_sub(COST_OF_PUT))
+            .checked_add(expected_refund.unwrap_or_else(|| unwrap!(Coins::from_nano(0)))));
+        common::send_request_expect_ok(env, client, Request::GetBalance, expected_balance);
+    };
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, start_nano);
+    let owner = *client.public_id().public_key();
+    let unpub_idata = IData::Unpub(UnpubImmutableData::new(vec![1, 2, 3], owner));
+
+    // Success.
+    check_put(
+        &mut env,
+        &mut client,
+        unpub_idata.clone(),
+        Response::Mutation(Ok(())),
+        None,
+    );
+    check_put(
+        &mut env,
+        &mut client,
+        pub_idata.clone(),
+        Response::Mutation(Ok(())),
+        None,
+    );
+
+    // Unpublished data which already exists.
+    check_put(
+        &mut env,
+        &mut client,
+        unpub_idata,
+        Response::Mutation(Err(NdError::DataExists)),
+        Some(COST_OF_PUT),
+    );
+
+    // Published data which already exists is reported as a success.
+    check_put(
+        &mut env,
+        &mut client,
+        pub_idata,
+        Response::Mutation(Ok(())),
+        None,
+    );
+
+    // The data handlers are at capacity.
+    let mut config = Config::default();
+    config.set_max_in_flight_ops(Some(0));
+    let mut env = Environment::with_config(&config);
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, start_nano);
+    check_put(
+        &mut env,
+        &mut client,
+        IData::Pub(PubImmutableData::new(vec![4, 5, 6])),
+        Response::Mutation(Err(NdError::NetworkOther(SECTION_BUSY.to_string()))),
+        Some(COST_OF_PUT),
+    );
+}
+
+#[test]
+fn immutable_data_op_latencies_are_recorded() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+    common::send_request_expect_ok(
+        &mut env,
+        &mut client,
+        Request::GetIData(*pub_idata.address()),
+        pub_idata,
+    );
+
+    let latencies = env.vault_mut(0).idata_op_latencies();
+    assert_eq!(latencies.put.count(), 1);
+    assert_eq!(latencies.get.count(), 1);
+    assert_eq!(latencies.delete.count(), 0);
+}
+
+#[test]
+fn health_check_reports_vault_state() {
+    let dir = unwrap!(TempDir::new("safe_vault"));
+    let exported = dir.path().join("exported");
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+
+    let report = env.vault_mut(0).health_check();
+    assert_eq!(report.role, "Elder");
+    assert_eq!(report.in_flight_ops, 0);
+    assert_eq!(report.oldest_op_age, None);
+    assert!(report.used_space > 0);
+    assert!(report.db_reachable);
+
+    // Leave a get in flight on vault 1, as it waits for vault 0 which never receives it.
+    let _ = unwrap!(env.vault_mut(0).export_idata_metadata(&exported));
+    let _ = unwrap!(env.vault_mut(1).import_idata_metadata(&exported));
+    let _ = client.send_request(Request::GetIData(*pub_idata.address()));
+    env.poll();
+    let report = env.vault_mut(1).health_check();
+    assert_eq!(report.in_flight_ops, 1);
+    assert!(report.oldest_op_age.is_some());
+
+    unwrap!(fs::remove_file(
+        env.vault_root_dir(0).join("immutable_data.db")
+    ));
+    assert!(!env.vault_mut(0).health_check().db_reachable);
+}
+
+#[test]
+fn shutdown_tells_clients_of_pending_gets_to_retry() {
+    let dir = unwrap!(TempDir::new("safe_vault"));
+    let exported = dir.path().join("exported");
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+
+    // Leave a get in flight on vault 1, as it waits for vault 0 which never receives it.  The
+    // other vaults respond straight away.
+    let _ = unwrap!(env.vault_mut(0).export_idata_metadata(&exported));
+    let _ = unwrap!(env.vault_mut(1).import_idata_metadata(&exported));
+    let message_id = client.send_request(Request::GetIData(*pub_idata.address()));
+    env.poll();
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 1);
+
+    assert!(env.vault_mut(1).handle_command(Command::Shutdown));
+    assert_eq!(env.vault_mut(1).in_flight_ops(), 0);
+    assert_eq!(
+        client.expect_response(message_id, &mut env),
+        Response::GetIData(Err(NdError::NetworkOther(SECTION_BUSY.to_string())))
+    );
+}
+
+#[test]
+fn connection_info_reports_advertised_address() {
+    let bound = unwrap!(Environment::new().vault_mut(0).our_connection_info());
+    let advertised: SocketAddr = unwrap!("203.0.113.1:5483".parse());
+    assert_ne!(bound, advertised);
+
+    let mut config = Config::default();
+    config.set_advertised_address(Some(advertised));
+    let mut env = Environment::with_config(&config);
+    assert_eq!(unwrap!(env.vault_mut(0).our_connection_info()), advertised);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn network_name_is_displayed_and_stored() {
+    let mut config = Config::default();
+    config.set_network_name(Some("testnet".to_string()));
+    let mut env = Environment::with_config(&config);
+    let display = env.vault_mut(0).to_string();
+    assert!(display.ends_with("@testnet"), "{}", display);
+
+    let state = unwrap!(fs::read(env.vault_root_dir(0).join("state")));
+    assert!(state.windows(7).any(|window| window == b"testnet"));
+
+    // A vault restarted from the same root dir keeps its ID.
+    config.set_root_dir(env.vault_root_dir(0));
+    let (vault, _command_tx) = env.new_standalone_vault(&config);
+    assert_eq!(vault.to_string(), display);
+}
+
+#[test]
+fn oversized_rpcs_are_fitted_to_the_limit() {
+    let mut config = Config::default();
+    config.set_max_rpc_size(Some(2048));
+    let mut env = Environment::with_config(&config);
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    // The put is split into segments which fit, but the chunk is too large to be returned.
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1; 4096]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::GetIData(*pub_idata.address()),
+        NdError::ExceededSize,
+    );
+}
+
+#[test]
+fn metrics_are_logged_as_one_line() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata));
+
+    let metrics = env.vault_mut(0).metrics();
+    assert_eq!(metrics.in_flight_ops, 0);
+    assert_eq!(metrics.idata_op_latencies.put.count(), 1);
+    let line = metrics.to_string();
+    assert!(!line.contains('\n'));
+    for name in &[
+        "in_flight_ops=0",
+        "unclaimed_client_responses=",
+        "idata_put=",
+    ] {
+        assert!(line.contains(name), "{} missing from {}", name, line);
+    }
+
+    assert!(!env.vault_mut(0).handle_command(Command::LogMetrics));
+}
+
+#[test]
+fn routing_events_are_counted_by_kind() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    assert_eq!(
+        env.vault_mut(0).metrics().routing_events,
+        RoutingEventCounts::default()
+    );
+
+    // Creating a balance and paying for a put are each agreed by consensus.  Mock routing produces
+    // no other kind of event.
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata));
+
+    let metrics = env.vault_mut(0).metrics();
+    let consensus = metrics.routing_events.consensus;
+    assert!(consensus >= 2);
+    assert_eq!(
+        metrics.routing_events,
+        RoutingEventCounts {
+            consensus,
+            ..Default::default()
+        }
+    );
+    let line = metrics.to_string();
+    assert!(line.contains(&format!("routing_events.consensus={}", consensus)));
+    assert!(line.contains("routing_events.member_left=0"));
+}
+
+#[test]
+fn put_immutable_data_rpcs_are_observed() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    let (observer_tx, observer_rx) = crossbeam_channel::unbounded();
+    env.vault_mut(0).set_sent_rpc_observer(observer_tx);
+
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    let data_name = *pub_idata.name();
+    let message_id = client.send_request(Request::PutIData(pub_idata));
+    env.poll();
+    assert_eq!(
+        client.expect_response(message_id, &mut env),
+        Response::Mutation(Ok(()))
+    );
+
+    let is_put = |sent: &SentRpc| match sent.request() {
+        Some(Request::PutIData(_)) => true,
+        _ => false,
+    };
+    let sent: Vec<_> = observer_rx
+        .try_iter()
+        .filter(|sent| sent.message_id() == message_id)
+        .collect();
+    // Forwarded by the client handlers to the data handlers...
+    assert!(sent
+        .iter()
+        .any(|sent| is_put(sent) && sent.dst == data_name));
+    // ...which send it on to the holders.
+    assert!(sent
+        .iter()
+        .any(|sent| is_put(sent) && sent.src == data_name));
+    // The success is reported back towards the client.
+    assert!(sent
+        .iter()
+        .any(|sent| sent.response() == Some(&Response::Mutation(Ok(())))));
+}
+
+#[test]
+fn get_immutable_data_from_holder_queries_only_that_holder() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    let (observer_tx, observer_rx) = crossbeam_channel::unbounded();
+    env.vault_mut(0).set_sent_rpc_observer(observer_tx);
+
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    let address = *pub_idata.address();
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+    let holder = unwrap!(observer_rx
+        .try_iter()
+        .find(|sent| match sent.request() {
+            Some(Request::PutIData(_)) => sent.src == *address.name(),
+            _ => false,
+        })
+        .map(|sent| sent.dst));
+
+    let (reply_tx, reply_rx) = crossbeam_channel::unbounded();
+    let _ = env
+        .vault_mut(0)
+        .handle_command(Command::GetIDataFromHolder {
+            address,
+            holder,
+            reply: reply_tx,
+        });
+    env.poll();
+    assert_eq!(unwrap!(reply_rx.try_recv()), Ok(pub_idata));
+
+    let targets: Vec<_> = observer_rx
+        .try_iter()
+        .filter(|sent| sent.request() == Some(&Request::GetIData(address)))
+        .map(|sent| sent.dst)
+        .collect();
+    assert_eq!(targets, [holder]);
+}
+
+#[test]
+fn retried_immutable_data_put_is_sent_to_holders_once() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    let (observer_tx, observer_rx) = crossbeam_channel::unbounded();
+    env.vault_mut(0).set_sent_rpc_observer(observer_tx);
+
+    // Send the same chunk twice, as a client retrying with a new message ID would.
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    let data_name = *pub_idata.name();
+    let first_id = client.send_request(Request::PutIData(pub_idata.clone()));
+    let second_id = client.send_request(Request::PutIData(pub_idata));
+    env.poll();
+    assert_eq!(
+        client.expect_response(first_id, &mut env),
+        Response::Mutation(Ok(()))
+    );
+    let _ = client.expect_response(second_id, &mut env);
+
+    let fan_outs = observer_rx
+        .try_iter()
+        .filter(|sent| match sent.request() {
+            Some(Request::PutIData(_)) => sent.src == data_name,
+            _ => false,
+        })
+        .count();
+    assert_eq!(fan_outs, 1);
+}
+
+#[test]
+fn admin_command_requires_operator_credentials() {
+    let mut env = Environment::new();
+    let vault = env.vault_mut(0);
+
+    let forged = Command::Admin {
+        command: AdminCommand::Shutdown,
+        capability: OperatorCapability::Token("forged".to_string()),
+    };
+    assert!(!vault.handle_command(forged));
+    assert!(vault.handle_command(Command::Admin {
+        command: AdminCommand::Shutdown,
+        capability: OperatorCapability::InProcess,
+    }));
+    assert!(vault.handle_command(Command::Shutdown));
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn is_operator_checks_the_whitelist() {
+    let mut config = Config::default();
+    config.set_operator_whitelist(vec!["operator".to_string()]);
+    let mut env = Environment::with_config(&config);
+    let vault = env.vault_mut(0);
+
+    let mut is_operator = |capability| {
+        let (reply, outcome) = crossbeam_channel::bounded(1);
+        assert!(!vault.handle_command(Command::IsOperator { capability, reply }));
+        unwrap!(outcome.try_recv())
+    };
+    assert!(is_operator(OperatorCapability::InProcess));
+    assert!(is_operator(OperatorCapability::Token(
+        "operator".to_string()
+    )));
+    assert!(!is_operator(OperatorCapability::Token(
+        "forged".to_string()
+    )));
+}
+
+#[test]
+fn reload_config_applies_tunables() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    // A reload issued without operator credentials is ignored.
+    for index in 0..common::DEFAULT_NUM_VAULTS {
+        let mut config = Config::default();
+        config.set_root_dir(env.vault_root_dir(index));
+        config.set_max_in_flight_ops(Some(0));
+        assert!(!env.vault_mut(index).handle_command(Command::ReloadConfig {
+            config: Box::new(config),
+            capability: OperatorCapability::Token("forged".to_string()),
+        }));
+    }
+    common::perform_mutation(
+        &mut env,
+        &mut client,
+        Request::PutIData(IData::Pub(PubImmutableData::new(vec![7, 8, 9]))),
+    );
+
+    for index in 0..common::DEFAULT_NUM_VAULTS {
+        let mut config = Config::default();
+        config.set_root_dir(env.vault_root_dir(index));
+        config.set_max_in_flight_ops(Some(0));
+        assert!(!env.vault_mut(index).handle_command(Command::ReloadConfig {
+            config: Box::new(config),
+            capability: OperatorCapability::InProcess,
+        }));
+    }
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::PutIData(IData::Pub(PubImmutableData::new(vec![1, 2, 3]))),
+        NdError::NetworkOther(SECTION_BUSY.to_string()),
+    );
+
+    // The root dir can't be changed without a restart.
+    let dir = unwrap!(TempDir::new("safe_vault"));
+    let mut config = Config::default();
+    config.set_root_dir(dir.path());
+    match env.vault_mut(0).reload_config(config) {
+        Err(Error::InvalidConfig(message)) => assert!(message.contains("root_dir")),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn unpublished_re_put_by_owner_can_be_idempotent() {
+    for &idempotent in &[false, true] {
+        let mut config = Config::default();
+        config.set_idempotent_unpub_put(idempotent);
+        let mut env = Environment::with_config(&config);
+        let mut owner = env.new_connected_client();
+        let mut other = env.new_connected_client();
+        common::create_balance(&mut env, &mut owner, None, 1_000_000_000_000);
+        common::create_balance(&mut env, &mut other, None, 1_000_000_000_000);
+
+        let unpub_idata = IData::Unpub(UnpubImmutableData::new(
+            vec![1, 2, 3],
+            *owner.public_id().public_key(),
+        ));
+        common::perform_mutation(&mut env, &mut owner, Request::PutIData(unpub_idata.clone()));
+
+        if idempotent {
+            common::perform_mutation(&mut env, &mut owner, Request::PutIData(unpub_idata.clone()));
+        } else {
+            common::send_request_expect_err(
+                &mut env,
+                &mut owner,
+                Request::PutIData(unpub_idata.clone()),
+                NdError::DataExists,
+            );
+        }
+
+        // The address covers the owner, so another client can only re-put the same data by
+        // claiming to own it.
+        common::send_request_expect_err(
+            &mut env,
+            &mut other,
+            Request::PutIData(unpub_idata),
+            NdError::InvalidOwners,
+        );
+    }
+}
+
+#[test]
+fn maintenance_refuses_client_data_requests() {
+    let admin = |command| Command::Admin {
+        command,
+        capability: OperatorCapability::InProcess,
+    };
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    let mut other_client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+
+    for index in 0..common::DEFAULT_NUM_VAULTS {
+        assert!(!env
+            .vault_mut(index)
+            .handle_command(admin(AdminCommand::EnterMaintenance)));
+    }
+    let maintenance = NdError::NetworkOther(MAINTENANCE.to_string());
+    let new_idata = IData::Pub(PubImmutableData::new(vec![4, 5, 6]));
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::PutIData(new_idata.clone()),
+        maintenance.clone(),
+    );
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::GetIData(*pub_idata.address()),
+        maintenance,
+    );
+
+    // Coin transactions, agreed through Routing, still proceed.
+    common::create_balance(&mut env, &mut client, Some(&mut other_client), 1);
+
+    for index in 0..common::DEFAULT_NUM_VAULTS {
+        assert!(!env
+            .vault_mut(index)
+            .handle_command(admin(AdminCommand::ExitMaintenance)));
+    }
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(new_idata));
+}
+
+#[test]
+fn read_only_vault_serves_gets_but_refuses_mutations() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    let mut other_client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata.clone()));
+
+    for index in 0..common::DEFAULT_NUM_VAULTS {
+        let mut config = Config::default();
+        config.set_root_dir(env.vault_root_dir(index));
+        config.set_read_only(true);
+        assert!(!env.vault_mut(index).handle_command(Command::ReloadConfig {
+            config: Box::new(config),
+            capability: OperatorCapability::InProcess,
+        }));
+    }
+    common::send_request_expect_ok(
+        &mut env,
+        &mut client,
+        Request::GetIData(*pub_idata.address()),
+        pub_idata,
+    );
+
+    let read_only = NdError::NetworkOther(READ_ONLY.to_string());
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::PutIData(IData::Pub(PubImmutableData::new(vec![4, 5, 6]))),
+        read_only.clone(),
+    );
+    common::send_request_expect_err(
+        &mut env,
+        &mut client,
+        Request::CreateBalance {
+            new_balance_owner: *other_client.public_id().public_key(),
+            amount: unwrap!(Coins::from_nano(1)),
+            transaction_id: 0,
+        },
+        read_only,
+    );
+}
+
+#[test]
+fn leave_is_refused_while_holding_chunks() {
+    let leave = || Command::Admin {
+        command: AdminCommand::Leave,
+        capability: OperatorCapability::InProcess,
+    };
+
+    let mut env = Environment::new();
+    assert!(env.vault_mut(0).handle_command(leave()));
+
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata));
+    assert!(!env.vault_mut(0).handle_command(leave()));
+}
+
+#[test]
+fn self_test_round_trips_chunk() {
+    let mut env = Environment::new();
+
+    let report = env.vault_mut(0).self_test();
+    assert!(report.passed(), "Self-test {}", report);
+    let steps: Vec<_> = report.steps.iter().map(|step| step.name).collect();
+    assert_eq!(steps, ["put", "get", "delete"]);
+}
+
+#[test]
+fn verify_storage_reports_corrupt_chunks() {
+    let mut env = Environment::new();
+    let mut client = env.new_connected_client();
+    common::create_balance(&mut env, &mut client, None, 1_000_000_000_000);
+
+    let pub_idata = IData::Pub(PubImmutableData::new(vec![1, 2, 3]));
+    let address = *pub_idata.address();
+    common::perform_mutation(&mut env, &mut client, Request::PutIData(pub_idata));
+
+    let verify_storage = |env: &mut Environment| {
+        let (reply_tx, reply_rx) = crossbeam_channel::unbounded();
+        let _ = env
+            .vault_mut(0)
+            .handle_command(Command::VerifyStorage { reply: reply_tx });
+        // The scan is carried out by polling.
+        assert!(reply_rx.try_recv().is_err());
+        env.poll();
+        unwrap!(reply_rx.try_recv())
+    };
+
+    let report = verify_storage(&mut env);
+    assert!(report.checked > 0);
+    assert!(report.passed(), "Storage scan {}", report);
+
+    // Simulate bit rot of the stored chunk.
+    let chunk_path = env
+        .vault_root_dir(0)
+        .join("chunks")
+        .join("immutable")
+        .join(hex::encode(unwrap!(bincode::serialize(&address))));
+    assert!(chunk_path.is_file());
+    unwrap!(fs::write(chunk_path, b"rotten"));
+
+    let report = verify_storage(&mut env);
+    assert!(report.corrupt.contains(&address));
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn running_vault_is_stopped_via_its_command_sender() {
+    let mut env = Environment::new();
+    let (command_sender, thread) = env.run_in_memory_vault_on_thread();
+
+    let (reply, report) = crossbeam_channel::bounded(1);
+    unwrap!(command_sender.send(Command::SelfTest { reply }));
+    let report = unwrap!(report.recv());
+    assert!(report.passed(), "Self-test {}", report);
+
+    unwrap!(command_sender.send(Command::Shutdown));
+    unwrap!(thread.join());
+}
+
+#[test]
+fn bounded_command_channel_rejects_commands_once_full() {
+    let (command_tx, command_rx) = safe_vault::bounded_command_channel(2);
+    for _ in 0..2 {
+        unwrap!(command_tx.send(Command::LogMetrics));
+    }
+    match command_tx.send(Command::LogMetrics) {
+        Err(TrySendError::Full(Command::LogMetrics)) => (),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    assert_eq!(command_rx.len(), 2);
+
+    // The queued commands are unaffected, and there's room again once one is handled.
+    match unwrap!(command_rx.try_recv()) {
+        Command::LogMetrics => (),
+        command => panic!("Unexpected {:?}", command),
+    }
+    unwrap!(command_tx.send(Command::Shutdown));
+    assert_eq!(command_rx.len(), 2);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn in_memory_vault_passes_self_test() {
+    let mut env = Environment::new();
+    let (mut vault, _command_tx) = env.new_in_memory_vault();
+
+    let report = vault.self_test();
+    assert!(report.passed(), "Self-test {}", report);
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 //
 // Auth keys